@@ -1,7 +1,9 @@
+#![allow(clippy::redundant_static_lifetimes)]
+
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use schemeish::{
     enviroment::EnvRef,
-    evaluator,
+    evaluator::Engine,
     lexer::TokenStream,
     parser::{
         Expr::{self},
@@ -97,31 +99,43 @@ const COIN_COMBO: &'static str = "
 (cc 150 us-coins)
 ";
 
-pub fn eval_test(scm: &str) -> Vec<Expr> {
+pub fn eval_test(scm: &str, engine: Engine) -> Vec<Expr> {
     let exprs = Parser::new(TokenStream::new(scm).collect_tokens().unwrap())
         .parse()
         .unwrap();
     let global = EnvRef::global();
-    global.import_prelude().unwrap();
+    global.import_prelude(engine).unwrap();
     exprs
         .into_iter()
-        .map(|e| evaluator::eval(e, &global).unwrap_or_else(|err| panic!("{err}")))
+        .map(|e| {
+            engine
+                .eval(e, &global)
+                .unwrap_or_else(|err| panic!("{err}"))
+        })
         .collect()
 }
 
-fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("tak 18 12 6", |b| b.iter(|| eval_test(black_box(TAK))));
-
-    c.bench_function("takl 18 12 6", |b| b.iter(|| eval_test(black_box(TAKL))));
+const BENCHES: [(&str, &str); 5] = [
+    ("tak 18 12 6", TAK),
+    ("takl 18 12 6", TAKL),
+    ("cpstak 12 6 3", CPSTAK),
+    ("fib 25", FIB),
+    ("coin combo 150", COIN_COMBO),
+];
 
-    c.bench_function("cpstak 12 6 3", |b| b.iter(|| eval_test(black_box(CPSTAK))));
-
-    c.bench_function("fib 25", |b| b.iter(|| eval_test(black_box(FIB))));
+fn criterion_benchmark(c: &mut Criterion) {
+    for (name, scm) in BENCHES {
+        c.bench_function(name, |b| b.iter(|| eval_test(black_box(scm), Engine::Tree)));
+    }
+}
 
-    c.bench_function("coin combo 150", |b| {
-        b.iter(|| eval_test(black_box(COIN_COMBO)))
-    });
+fn bytecode_benchmark(c: &mut Criterion) {
+    for (name, scm) in BENCHES {
+        c.bench_function(&format!("{name} (bytecode)"), |b| {
+            b.iter(|| eval_test(black_box(scm), Engine::Bytecode))
+        });
+    }
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(benches, criterion_benchmark, bytecode_benchmark);
 criterion_main!(benches);
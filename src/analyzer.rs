@@ -0,0 +1,155 @@
+use std::rc::Rc;
+
+use crate::enviroment::{EnvRef, Local, Variable};
+use crate::error::EvalErr;
+use crate::lexer::Token;
+use crate::parser::Expr;
+use crate::special_form::Analyze;
+use crate::symbol::Symbol;
+
+// Expanded expressions are analyzed into code once, before they run, so that evaluating them
+// never has to pick apart or copy the syntax tree again. Code can't be changed once made, and
+// every part of it is shared: keeping some in a continuation frame or closing over a lambda's body
+// only bumps a reference count.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Code(Rc<Node>);
+
+#[derive(Debug, PartialEq)]
+pub enum Node {
+    Constant(Expr),
+    Variable(Variable),
+    // The operator followed by each of the operands.
+    Call(Body),
+    If {
+        predicate: Code,
+        consequence: Code,
+        alternative: Code,
+    },
+    Define(Variable, Code),
+    Assignment(Variable, Code),
+    Lambda(Rc<LambdaCode>),
+    CaseLambda(Vec<Rc<LambdaCode>>),
+    Begin(Body),
+    And(Body),
+    Or(Body),
+    // The clauses are made into a procedure of the raised object and a continuation that raises
+    // it again, called once the guard has been escaped to.
+    Guard {
+        clauses: Rc<LambdaCode>,
+        body: Body,
+    },
+}
+
+impl Code {
+    pub fn new(node: Node) -> Code {
+        Code(Rc::new(node))
+    }
+
+    pub fn node(&self) -> &Node {
+        &self.0
+    }
+}
+
+pub type Body = Rc<[Code]>;
+
+// Everything about a lambda that doesn't depend on where it is evaluated, shared by every
+// closure made from it. Calls get a frame with a slot for each parameter, then the rest
+// parameter, then each variable defined in the body.
+#[derive(Debug, PartialEq)]
+pub struct LambdaCode {
+    pub params: Vec<Symbol>,
+    pub rest: Option<Symbol>,
+    pub slots: usize,
+    pub body: Body,
+}
+
+// Hands out each piece of a body in turn without copying the body itself.
+#[derive(Debug, Clone)]
+pub struct BodyIter {
+    body: Body,
+    next: usize,
+}
+
+impl BodyIter {
+    pub fn new(body: Body) -> Self {
+        BodyIter { body, next: 0 }
+    }
+}
+
+impl Iterator for BodyIter {
+    type Item = Code;
+
+    fn next(&mut self) -> Option<Code> {
+        let code = self.body.get(self.next)?.clone();
+        self.next += 1;
+        Some(code)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.body.len() - self.next;
+        (len, Some(len))
+    }
+}
+
+impl ExactSizeIterator for BodyIter {}
+
+// The local variables in scope at the code being analyzed, with a frame for each lambda or guard
+// clause around it, innermost last.
+pub struct Scope<'a> {
+    env: &'a EnvRef,
+    frames: Vec<Vec<Symbol>>,
+}
+
+impl<'a> Scope<'a> {
+    // Analysis starts out at the top level of `env`, where every variable is a global.
+    pub fn new(env: &'a EnvRef) -> Self {
+        Scope {
+            env,
+            frames: vec![],
+        }
+    }
+
+    pub fn variable(&self, name: Symbol) -> Result<Variable, EvalErr> {
+        for (depth, frame) in self.frames.iter().rev().enumerate() {
+            if let Some(slot) = frame.iter().rposition(|var| *var == name) {
+                return Ok(Variable::Local(Local { name, depth, slot }));
+            }
+        }
+        self.global(name)
+    }
+
+    pub fn global(&self, name: Symbol) -> Result<Variable, EvalErr> {
+        Ok(Variable::Global(self.env.global_cell(name)?))
+    }
+
+    pub fn with_frame<T>(&mut self, vars: Vec<Symbol>, analyze: impl FnOnce(&mut Self) -> T) -> T {
+        self.frames.push(vars);
+        let analyzed = analyze(self);
+        self.frames.pop();
+        analyzed
+    }
+}
+
+pub fn analyze(expr: &Expr, scope: &mut Scope) -> Result<Code, EvalErr> {
+    let node = match expr {
+        Expr::Atom(Token::Symbol(identifier)) => Node::Variable(scope.variable(*identifier)?),
+        Expr::Call(items) => match items.first() {
+            Some(Expr::SpecialForm(form)) => return form.analyze(scope),
+            Some(_) => Node::Call(analyze_body(items, scope)?),
+            None => return Err(EvalErr::InvalidArgs("expected operation")),
+        },
+        Expr::Quoted(datum) => Node::Constant(datum.as_ref().clone()),
+        Expr::Atom(_)
+        | Expr::Pair(_)
+        | Expr::Vector(_)
+        | Expr::Bytevector(_)
+        | Expr::EmptyList
+        | Expr::Void => Node::Constant(expr.clone()),
+        expr => return Err(EvalErr::TypeError("expression", expr.clone())),
+    };
+    Ok(Code::new(node))
+}
+
+pub fn analyze_body(exprs: &[Expr], scope: &mut Scope) -> Result<Body, EvalErr> {
+    exprs.iter().map(|expr| analyze(expr, scope)).collect()
+}
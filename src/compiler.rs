@@ -0,0 +1,214 @@
+use std::rc::Rc;
+
+use crate::analyzer::{Code, LambdaCode, Node};
+use crate::enviroment::Variable;
+use crate::parser::Expr;
+use crate::utils::ToExpr;
+
+// Analyzed code can also be compiled into bytecode for the VM to run. A chunk is the flat list of
+// instructions for a top-level expression or a lambda's body, along with the constants, variables
+// and nested lambdas they refer to by index. Operands are the arguments to a call, pushed in order
+// onto the operand stack of the chunk's activation.
+#[derive(Debug, Default, PartialEq)]
+pub struct Chunk {
+    pub code: Vec<Op>,
+    pub constants: Vec<Expr>,
+    pub variables: Vec<Variable>,
+    pub lambdas: Vec<Template>,
+}
+
+// A lambda along with the chunk compiled from its body, which every closure made from it shares.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pub lambda: Rc<LambdaCode>,
+    pub chunk: Rc<Chunk>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    // Pushes the constant at the index.
+    Constant(u32),
+    // Pushes the value of the variable at the index.
+    Get(u32),
+    // Pops a value into the variable at the index, pushing void.
+    Define(u32),
+    // Pops a value into the variable at the index, pushing it back.
+    Set(u32),
+    // Pushes a closure over the current enviroment of the lambda at the index.
+    Closure(u32),
+    // Pushes a case-lambda made of the lambdas from the first index, of the given count.
+    CaseLambda(u32, u32),
+    Jump(u32),
+    // Pops a value and jumps if it is false.
+    JumpIfFalse(u32),
+    // Jumps if the value on top is false, leaving it as the result. Otherwise pops it.
+    JumpIfFalseOrPop(u32),
+    // Jumps if the value on top is true, leaving it as the result. Otherwise pops it.
+    JumpIfTrueOrPop(u32),
+    Pop,
+    // Pops the given number of arguments and then the procedure, and calls it. A tail call
+    // replaces the current activation instead of returning to it.
+    Call(u32),
+    TailCall(u32),
+    // Returns the value on top to whatever called the chunk.
+    Return,
+    // Installs a guard, with the lambda at the index as its clauses, until the matching `EndGuard`.
+    // The guard escapes to the instruction at the second index.
+    Guard(u32, u32),
+    EndGuard,
+}
+
+pub fn compile(code: &Code) -> Chunk {
+    let mut compiler = Compiler::default();
+    compiler.expr(code, true);
+    compiler.emit(Op::Return);
+    compiler.chunk
+}
+
+pub fn compile_lambda(lambda: &Rc<LambdaCode>) -> Template {
+    let mut compiler = Compiler::default();
+    compiler.body(&lambda.body, true);
+    compiler.emit(Op::Return);
+    Template {
+        lambda: Rc::clone(lambda),
+        chunk: Rc::new(compiler.chunk),
+    }
+}
+
+#[derive(Default)]
+struct Compiler {
+    chunk: Chunk,
+}
+
+impl Compiler {
+    // Compiles code that leaves exactly one value on the stack. Code in tail position is the last
+    // thing its chunk does before returning.
+    fn expr(&mut self, code: &Code, tail: bool) {
+        match code.node() {
+            Node::Constant(val) => self.constant(val.clone()),
+            Node::Variable(variable) => {
+                let index = self.variable(variable);
+                self.emit(Op::Get(index));
+            }
+            Node::Call(items) => {
+                items.iter().for_each(|item| self.expr(item, false));
+                let argc = items.len() as u32 - 1;
+                self.emit(match tail {
+                    true => Op::TailCall(argc),
+                    false => Op::Call(argc),
+                });
+            }
+            Node::If {
+                predicate,
+                consequence,
+                alternative,
+            } => {
+                self.expr(predicate, false);
+                let to_alternative = self.emit(Op::JumpIfFalse(0));
+                self.expr(consequence, tail);
+                let to_end = self.emit(Op::Jump(0));
+                self.patch(to_alternative);
+                self.expr(alternative, tail);
+                self.patch(to_end);
+            }
+            Node::Define(variable, body) => {
+                self.expr(body, false);
+                let index = self.variable(variable);
+                self.emit(Op::Define(index));
+            }
+            Node::Assignment(variable, value) => {
+                self.expr(value, false);
+                let index = self.variable(variable);
+                self.emit(Op::Set(index));
+            }
+            Node::Lambda(lambda) => {
+                let index = self.lambda(lambda);
+                self.emit(Op::Closure(index));
+            }
+            Node::CaseLambda(clauses) => {
+                let start = self.chunk.lambdas.len() as u32;
+                clauses.iter().for_each(|lambda| {
+                    self.lambda(lambda);
+                });
+                self.emit(Op::CaseLambda(start, clauses.len() as u32));
+            }
+            Node::Begin(body) => self.body(body, tail),
+            Node::And(body) => self.short_circuit(body, true.to_expr(), tail, Op::JumpIfFalseOrPop),
+            Node::Or(body) => self.short_circuit(body, false.to_expr(), tail, Op::JumpIfTrueOrPop),
+            Node::Guard { clauses, body } => {
+                let index = self.lambda(clauses);
+                let guard = self.emit(Op::Guard(index, 0));
+                self.body(body, false);
+                self.emit(Op::EndGuard);
+                self.patch(guard);
+            }
+        }
+    }
+
+    // Compiles each piece of a body in turn, keeping only the value of the last.
+    fn body(&mut self, body: &[Code], tail: bool) {
+        let Some((last, init)) = body.split_last() else {
+            return self.constant(Expr::Void);
+        };
+        for code in init {
+            self.expr(code, false);
+            self.emit(Op::Pop);
+        }
+        self.expr(last, tail);
+    }
+
+    fn short_circuit(&mut self, body: &[Code], empty: Expr, tail: bool, jump: fn(u32) -> Op) {
+        let Some((last, init)) = body.split_last() else {
+            return self.constant(empty);
+        };
+        let exits = init
+            .iter()
+            .map(|code| {
+                self.expr(code, false);
+                self.emit(jump(0))
+            })
+            .collect::<Vec<usize>>();
+        self.expr(last, tail);
+        exits.into_iter().for_each(|exit| self.patch(exit));
+    }
+
+    fn constant(&mut self, val: Expr) {
+        self.chunk.constants.push(val);
+        let index = self.chunk.constants.len() as u32 - 1;
+        self.emit(Op::Constant(index));
+    }
+
+    fn variable(&mut self, variable: &Variable) -> u32 {
+        let index = match self.chunk.variables.iter().position(|var| var == variable) {
+            Some(index) => index,
+            None => {
+                self.chunk.variables.push(variable.clone());
+                self.chunk.variables.len() - 1
+            }
+        };
+        index as u32
+    }
+
+    fn lambda(&mut self, lambda: &Rc<LambdaCode>) -> u32 {
+        self.chunk.lambdas.push(compile_lambda(lambda));
+        self.chunk.lambdas.len() as u32 - 1
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.code.push(op);
+        self.chunk.code.len() - 1
+    }
+
+    // Points the jump at `at` to the next instruction to be emitted.
+    fn patch(&mut self, at: usize) {
+        let target = self.chunk.code.len() as u32;
+        match &mut self.chunk.code[at] {
+            Op::Jump(to)
+            | Op::JumpIfFalse(to)
+            | Op::JumpIfFalseOrPop(to)
+            | Op::JumpIfTrueOrPop(to)
+            | Op::Guard(_, to) => *to = target,
+            op => unreachable!("{op:?} is not a jump"),
+        }
+    }
+}
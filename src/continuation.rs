@@ -0,0 +1,381 @@
+use std::rc::Rc;
+use std::vec;
+
+use crate::analyzer::{BodyIter, Code};
+use crate::enviroment::{EnvRef, Variable};
+use crate::error::EvalErr;
+use crate::evaluator::{eval_operands, Args, Step};
+use crate::parser::Expr;
+use crate::procedure::Proc;
+use crate::utils::ToExpr;
+use crate::vm::Activation;
+
+// The rest of a computation, kept as an explicit stack of frames rather than on the Rust call
+// stack. Capturing it for call/cc only bumps a reference count; the frames are copied lazily the
+// next time either copy is pushed to or popped from.
+#[derive(Debug, Clone, Default)]
+pub struct Continuation(Rc<Vec<Frame>>);
+
+impl Continuation {
+    pub fn push(&mut self, frame: Frame) {
+        Rc::make_mut(&mut self.0).push(frame)
+    }
+
+    pub fn pop(&mut self) -> Option<Frame> {
+        Rc::make_mut(&mut self.0).pop()
+    }
+
+    // The dynamic-wind extents this continuation is inside of, outermost first.
+    pub fn winders(&self) -> Vec<Rc<Winder>> {
+        self.0
+            .iter()
+            .filter_map(|frame| match frame {
+                Frame::Wind(winder) => Some(Rc::clone(winder)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // Replaces this continuation with `target`, running the `after` thunks of every extent being
+    // left and then the `before` thunks of every extent being entered before `val` is delivered.
+    // Each thunk runs just outside its own extent, so anything it raises is only seen by the
+    // handlers and extents that are around it at that point of the transfer.
+    pub fn reinstate(
+        &mut self,
+        target: &Continuation,
+        val: Expr,
+        env: EnvRef,
+    ) -> Result<Step, EvalErr> {
+        let from = self.winders();
+        let to = target.winders();
+        let shared = from
+            .iter()
+            .zip(to.iter())
+            .take_while(|(x, y)| Rc::ptr_eq(x, y))
+            .count();
+
+        let thunks = from[shared..]
+            .iter()
+            .rev()
+            .map(|winder| (self.outside(winder), winder.after.clone()))
+            .chain(
+                to[shared..]
+                    .iter()
+                    .map(|winder| (target.outside(winder), winder.before.clone())),
+            )
+            .collect::<Vec<(Continuation, Expr)>>();
+
+        rewind(thunks.into_iter(), target.clone(), Ok(val), env, self)
+    }
+
+    // The part of this continuation from before the extent of `winder` was entered.
+    fn outside(&self, winder: &Rc<Winder>) -> Continuation {
+        let entered = self
+            .0
+            .iter()
+            .position(|frame| matches!(frame, Frame::Wind(w) if Rc::ptr_eq(w, winder)))
+            .unwrap_or(0);
+        Continuation(Rc::new(self.0[..entered].to_vec()))
+    }
+
+    // The exception handlers in effect at the top of this continuation.
+    pub fn handlers(&self) -> Handlers {
+        self.0
+            .iter()
+            .rev()
+            .find_map(|frame| match frame {
+                Frame::Handlers(handlers) => Some(handlers.clone()),
+                _ => None,
+            })
+            .unwrap_or_default()
+    }
+
+    // Passes `obj` to the current exception handler. The handler runs with the handlers that were
+    // in effect when it was installed. If the raise is not continuable and the handler returns,
+    // `obj` is raised again to the next handler out. Once no handlers are left the continuation
+    // is unwound and the error escapes `eval`.
+    pub fn raise(&mut self, obj: Expr, continuable: bool) -> Result<Step, EvalErr> {
+        let Some((handler, outer)) = self.handlers().split() else {
+            return self.unwind(EvalErr::from_raised(obj));
+        };
+        match handler {
+            Handler::Procedure(proc, env) => {
+                self.push(Frame::Handlers(outer));
+                if !continuable {
+                    self.push(Frame::NonContinuable(obj.clone()));
+                }
+                Ok(Step::Apply(proc, Args::new(vec![obj], &env)?))
+            }
+            // The clauses are tried back where the guard was entered. If none of them match, the
+            // object is raised again from here, as if the guard had never caught it, so the outer
+            // handler gets it the same way it would have without the guard.
+            Handler::Guard(guard_k, catch) => {
+                self.push(Frame::Handlers(outer));
+                self.push(Frame::Reraise(obj.clone(), continuable));
+                let reraise = Proc::Continuation(self.clone()).to_expr();
+                let env = catch.env.clone_rc()?;
+                let mut target = guard_k;
+                target.push(Frame::Catch(catch, reraise));
+                self.reinstate(&target, obj, env)
+            }
+        }
+    }
+
+    // Abandons the whole continuation because of an error, still giving every enclosing
+    // dynamic-wind the chance to run its `after` thunk before the error is passed on.
+    pub fn unwind(&mut self, err: EvalErr) -> Result<Step, EvalErr> {
+        let winders = self.winders();
+        match winders.last() {
+            Some(winder) => {
+                let env = winder.env.clone_rc()?;
+                let thunks = winders
+                    .iter()
+                    .rev()
+                    .map(|winder| (self.outside(winder), winder.after.clone()))
+                    .collect::<Vec<(Continuation, Expr)>>();
+                rewind(
+                    thunks.into_iter(),
+                    Continuation::default(),
+                    Err(err),
+                    env,
+                    self,
+                )
+            }
+            None => {
+                *self = Continuation::default();
+                Err(err)
+            }
+        }
+    }
+}
+
+impl PartialEq for Continuation {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Debug)]
+pub struct Winder {
+    before: Expr,
+    after: Expr,
+    env: EnvRef,
+}
+
+impl Winder {
+    pub fn new(before: Expr, after: Expr, env: EnvRef) -> Self {
+        Winder { before, after, env }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Handler {
+    Procedure(Expr, EnvRef),
+    // A `guard` form: the continuation to escape to, and the clauses to dispatch on once there.
+    Guard(Continuation, Rc<Catch>),
+}
+
+// The stack of installed exception handlers, innermost first. It is shared between the frames
+// that install it so that capturing a continuation does not copy it.
+#[derive(Debug, Clone, Default)]
+pub struct Handlers(Option<Rc<(Handler, Handlers)>>);
+
+impl Handlers {
+    pub fn with(&self, handler: Handler) -> Handlers {
+        Handlers(Some(Rc::new((handler, self.clone()))))
+    }
+
+    fn split(&self) -> Option<(Handler, Handlers)> {
+        self.0.as_ref().map(|node| (node.0.clone(), node.1.clone()))
+    }
+}
+
+// The clauses of a `guard`, as a procedure of the raised object.
+#[derive(Debug)]
+pub struct Catch {
+    clauses: Expr,
+    env: EnvRef,
+}
+
+impl Catch {
+    pub fn new(clauses: Expr, env: EnvRef) -> Self {
+        Catch { clauses, env }
+    }
+}
+
+// Each frame records what to do with the value of the expression currently being evaluated.
+#[derive(Debug, Clone)]
+pub enum Frame {
+    Operands {
+        evaluated: Vec<Expr>,
+        pending: BodyIter,
+        env: EnvRef,
+    },
+    Sequence {
+        pending: BodyIter,
+        env: EnvRef,
+    },
+    Branch {
+        consequence: Code,
+        alternative: Code,
+        env: EnvRef,
+    },
+    Define {
+        variable: Variable,
+        env: EnvRef,
+    },
+    Assignment {
+        variable: Variable,
+        env: EnvRef,
+    },
+    And {
+        pending: BodyIter,
+        env: EnvRef,
+    },
+    Or {
+        pending: BodyIter,
+        env: EnvRef,
+    },
+    WindEnter {
+        thunk: Expr,
+        winder: Rc<Winder>,
+    },
+    Wind(Rc<Winder>),
+    Rewind {
+        pending: vec::IntoIter<(Continuation, Expr)>,
+        target: Continuation,
+        then: Result<Expr, EvalErr>,
+        env: EnvRef,
+    },
+    Handlers(Handlers),
+    NonContinuable(Expr),
+    Reraise(Expr, bool),
+    Catch(Rc<Catch>, Expr),
+    Resume(Activation),
+}
+
+impl Frame {
+    pub fn resume(self, val: Expr, k: &mut Continuation) -> Result<Step, EvalErr> {
+        match self {
+            Frame::Operands {
+                mut evaluated,
+                pending,
+                env,
+            } => {
+                evaluated.push(val);
+                eval_operands(evaluated, pending, env, k)
+            }
+            Frame::Sequence { pending, env } => Ok(eval_sequence(pending, env, k)),
+            Frame::Branch {
+                consequence,
+                alternative,
+                env,
+            } => match val.is_true() {
+                true => Ok(Step::TailCall(consequence, env)),
+                false => Ok(Step::TailCall(alternative, env)),
+            },
+            Frame::Define { variable, env } => {
+                env.define_var(&variable, val)?;
+                Ok(Step::Done(Expr::Void))
+            }
+            Frame::Assignment { variable, env } => Ok(Step::Done(env.set_var(&variable, val)?)),
+            // Only the last expression is evaluated in tail position, so its value is returned
+            // as it is. Before that, `and` stops at the first false value and `or` at the first
+            // true one.
+            Frame::And { mut pending, env } => match val.is_true() {
+                false => Ok(Step::Done(val)),
+                true => {
+                    let next = pending.next().ok_or(EvalErr::UnexpectedEnd)?;
+                    if pending.len() > 0 {
+                        k.push(Frame::And {
+                            pending,
+                            env: env.clone_rc()?,
+                        });
+                    }
+                    Ok(Step::TailCall(next, env))
+                }
+            },
+            Frame::Or { mut pending, env } => match val.is_true() {
+                true => Ok(Step::Done(val)),
+                false => {
+                    let next = pending.next().ok_or(EvalErr::UnexpectedEnd)?;
+                    if pending.len() > 0 {
+                        k.push(Frame::Or {
+                            pending,
+                            env: env.clone_rc()?,
+                        });
+                    }
+                    Ok(Step::TailCall(next, env))
+                }
+            },
+            Frame::WindEnter { thunk, winder } => {
+                let env = winder.env.clone_rc()?;
+                k.push(Frame::Wind(winder));
+                Ok(Step::Apply(thunk, Args::new(vec![], &env)?))
+            }
+            Frame::Wind(winder) => {
+                let env = winder.env.clone_rc()?;
+                let after = vec![(k.clone(), winder.after.clone())];
+                rewind(after.into_iter(), k.clone(), Ok(val), env, k)
+            }
+            Frame::Rewind {
+                pending,
+                target,
+                then,
+                env,
+            } => rewind(pending, target, then, env, k),
+            Frame::Handlers(_) => Ok(Step::Done(val)),
+            Frame::NonContinuable(obj) => Err(EvalErr::from_raised(obj)),
+            Frame::Reraise(obj, continuable) => k.raise(obj, continuable),
+            Frame::Catch(catch, reraise) => Ok(Step::Apply(
+                catch.clauses.clone(),
+                Args::new(vec![val, reraise], &catch.env)?,
+            )),
+            Frame::Resume(act) => Ok(act.resume(val)),
+        }
+    }
+}
+
+// Calls each thunk in turn for its side effects, in the continuation it is paired with, then
+// carries on with `then` in `target`.
+fn rewind(
+    mut pending: vec::IntoIter<(Continuation, Expr)>,
+    target: Continuation,
+    then: Result<Expr, EvalErr>,
+    env: EnvRef,
+    k: &mut Continuation,
+) -> Result<Step, EvalErr> {
+    match pending.next() {
+        Some((outside, thunk)) => {
+            let args = Args::new(vec![], &env)?;
+            *k = outside;
+            k.push(Frame::Rewind {
+                pending,
+                target,
+                then,
+                env,
+            });
+            Ok(Step::Apply(thunk, args))
+        }
+        None => {
+            *k = target;
+            then.map(Step::Done)
+        }
+    }
+}
+
+// Evaluates each piece of code in turn, leaving the last one in tail position.
+pub fn eval_sequence(mut body: BodyIter, env: EnvRef, k: &mut Continuation) -> Step {
+    match body.next() {
+        Some(next) => {
+            if body.len() > 0 {
+                k.push(Frame::Sequence {
+                    pending: body,
+                    env: env.clone(),
+                });
+            }
+            Step::TailCall(next, env)
+        }
+        None => Step::Done(Expr::Void),
+    }
+}
@@ -1,15 +1,19 @@
 use core::cell::RefCell;
 use core::cell::{Ref, RefMut};
-use std::collections::HashMap;
 use std::rc::Rc;
 
 use crate::error::EvalErr;
-use crate::evaluator::eval;
+use crate::evaluator::{eval, Engine};
+use crate::heap::{self, Trace, Tracer};
 use crate::lexer::TokenStream;
 use crate::parser::Expr;
 use crate::parser::Parser;
-use crate::primitives::{compare, core_lang, io, numeric, pair, prelude, typecheck};
-use crate::procedure::{PSig, Primitive};
+use crate::primitives::{
+    bytevector, character, compare, core_lang, exception, gc, hash_table, io, numeric, pair, port,
+    prelude, record, string, typecheck, vector,
+};
+use crate::procedure::{CSig, Control, PSig, Primitive};
+use crate::symbol::{Symbol, SymbolMap};
 use crate::utils::ToExpr;
 
 type RcCellEnv = Option<Rc<RefCell<Env>>>;
@@ -23,7 +27,9 @@ impl EnvRef {
     }
 
     pub fn new(env: Env) -> EnvRef {
-        EnvRef(Some(Rc::new(RefCell::new(env))))
+        let env = Rc::new(RefCell::new(env));
+        heap::track(&env);
+        EnvRef(Some(env))
     }
 
     pub fn get_env(&self) -> RcCellEnv {
@@ -31,7 +37,9 @@ impl EnvRef {
     }
 
     pub fn global() -> EnvRef {
-        EnvRef::new(Env::new(EnvRef::nil())).install_primitives()
+        EnvRef::new(Env::Global(SymbolMap::default()))
+            .install_primitives()
+            .install_syntax()
     }
 
     pub fn clone_rc(&self) -> Result<EnvRef, EvalErr> {
@@ -40,34 +48,119 @@ impl EnvRef {
         ))))
     }
 
-    fn borrow_ref(&self) -> Result<Ref<Env>, EvalErr> {
+    pub fn trace(&self, tracer: &mut Tracer) {
+        if let Some(env) = &self.0 {
+            tracer.object(env);
+        }
+    }
+
+    fn borrow_ref(&self) -> Result<Ref<'_, Env>, EvalErr> {
         Ok(self.0.as_ref().ok_or(EvalErr::NilEnv)?.borrow())
     }
 
-    fn borrow_ref_mut(&self) -> Result<RefMut<Env>, EvalErr> {
+    fn borrow_ref_mut(&self) -> Result<RefMut<'_, Env>, EvalErr> {
         Ok(self.0.as_ref().ok_or(EvalErr::NilEnv)?.borrow_mut())
     }
 
-    pub fn get_val(&self, name: &str) -> Result<Expr, EvalErr> {
-        self.borrow_ref()
-            .map_err(|_| EvalErr::UnboundVar(name.to_string()))?
-            .get_val(name)
+    // Only globals are still looked up by name, local variables are found by where the analyzer
+    // placed them.
+    pub fn get_val(&self, name: Symbol) -> Result<Expr, EvalErr> {
+        match &*self.borrow_ref()? {
+            Env::Global(globals) => globals
+                .get(&name)
+                .ok_or_else(|| EvalErr::UnboundVar(name.to_string()))?
+                .get(),
+            Env::Frame { parent, .. } => parent.get_val(name),
+        }
     }
 
-    pub fn insert_val(&self, name: String, val: Expr) -> Result<(), EvalErr> {
-        self.borrow_ref_mut()?.insert_val(name, val);
+    pub fn insert_val(&self, name: Symbol, val: Expr) -> Result<(), EvalErr> {
+        self.global_cell(name)?.define(val);
         Ok(())
     }
 
-    pub fn update_val(&self, name: String, val: Expr) -> Result<Expr, EvalErr> {
-        self.borrow_ref_mut()
-            .map_err(|_| EvalErr::UnboundVar(name.to_string()))?
-            .update_val(name, val)
+    // The cell of a global variable, which is made empty the first time the name is referred to.
+    pub fn global_cell(&self, name: Symbol) -> Result<Rc<Global>, EvalErr> {
+        match &mut *self.borrow_ref_mut()? {
+            Env::Global(globals) => Ok(Rc::clone(
+                globals
+                    .entry(name)
+                    .or_insert_with(|| Rc::new(Global::new(name))),
+            )),
+            Env::Frame { parent, .. } => parent.global_cell(name),
+        }
+    }
+
+    pub fn get_var(&self, var: &Variable) -> Result<Expr, EvalErr> {
+        match var {
+            Variable::Local(local) => self.get_local(local, local.depth),
+            Variable::Global(global) => global.get(),
+        }
+    }
+
+    pub fn define_var(&self, var: &Variable, val: Expr) -> Result<(), EvalErr> {
+        match var {
+            Variable::Local(local) => self.set_local(local, local.depth, val),
+            Variable::Global(global) => {
+                global.define(val);
+                Ok(())
+            }
+        }
+    }
+
+    // Unlike a definition, assigning to a global that was never defined is an error.
+    pub fn set_var(&self, var: &Variable, val: Expr) -> Result<Expr, EvalErr> {
+        match var {
+            Variable::Local(local) => {
+                self.set_local(local, local.depth, val.clone())?;
+                Ok(val)
+            }
+            Variable::Global(global) => global.set(val),
+        }
+    }
+
+    fn get_local(&self, local: &Local, depth: usize) -> Result<Expr, EvalErr> {
+        match &*self.borrow_ref()? {
+            Env::Frame { slots, .. } if depth == 0 => slots
+                .get(local.slot)
+                .cloned()
+                .ok_or_else(|| EvalErr::UnboundVar(local.name.to_string())),
+            Env::Frame { parent, .. } => parent.get_local(local, depth - 1),
+            Env::Global(_) => Err(EvalErr::UnboundVar(local.name.to_string())),
+        }
+    }
+
+    fn set_local(&self, local: &Local, depth: usize, val: Expr) -> Result<(), EvalErr> {
+        if depth == 0 {
+            if let Env::Frame { slots, .. } = &mut *self.borrow_ref_mut()? {
+                if let Some(slot) = slots.get_mut(local.slot) {
+                    *slot = val;
+                    return Ok(());
+                }
+            }
+            return Err(EvalErr::UnboundVar(local.name.to_string()));
+        }
+        match &*self.borrow_ref()? {
+            Env::Frame { parent, .. } => parent.set_local(local, depth - 1, val),
+            Env::Global(_) => Err(EvalErr::UnboundVar(local.name.to_string())),
+        }
     }
 
     fn install_primitives(self) -> EnvRef {
+        let control = [
+            ("apply", core_lang::apply as CSig),
+            ("call-with-current-continuation", core_lang::call_cc as CSig),
+            ("call/cc", core_lang::call_cc as CSig),
+            ("dynamic-wind", core_lang::dynamic_wind as CSig),
+            ("hash-table-ref", hash_table::hash_table_ref as CSig),
+            (
+                "with-exception-handler",
+                exception::with_exception_handler as CSig,
+            ),
+            ("raise-continuable", exception::raise_continuable as CSig),
+        ];
+
         let primitives = [
-            ("apply", core_lang::apply as PSig),
             ("+", numeric::add as PSig),
             ("-", numeric::subtract as PSig),
             ("*", numeric::multiply as PSig),
@@ -77,39 +170,200 @@ impl EnvRef {
             (">=", numeric::greater_than_or_eq as PSig),
             ("<", numeric::less_than as PSig),
             ("<=", numeric::less_than_or_eq as PSig),
+            ("quotient", numeric::quotient as PSig),
             ("remainder", numeric::remainder as PSig),
             ("modulo", numeric::modulo as PSig),
+            ("numerator", numeric::numerator as PSig),
+            ("denominator", numeric::denominator as PSig),
+            ("exact", numeric::exact as PSig),
+            ("inexact", numeric::inexact as PSig),
+            ("exact->inexact", numeric::inexact as PSig),
+            ("inexact->exact", numeric::exact as PSig),
+            ("exact?", numeric::is_exact as PSig),
+            ("inexact?", numeric::is_inexact as PSig),
             ("cons", pair::cons as PSig),
             ("car", pair::car as PSig),
             ("cdr", pair::cdr as PSig),
             ("set-car!", pair::set_car as PSig),
             ("set-cdr!", pair::set_cdr as PSig),
             ("list", pair::list as PSig),
+            ("append", pair::append as PSig),
             ("display", io::display as PSig),
             ("error", io::error as PSig),
+            ("raise", exception::raise as PSig),
+            ("error-object?", exception::error_object as PSig),
+            (
+                "error-object-message",
+                exception::error_object_message as PSig,
+            ),
+            (
+                "error-object-irritants",
+                exception::error_object_irritants as PSig,
+            ),
             ("equal?", compare::equal as PSig),
-            ("eq?", compare::equal as PSig),
+            ("eq?", compare::eqv as PSig),
+            ("eqv?", compare::eqv as PSig),
             ("not", compare::not as PSig),
             ("symbol?", typecheck::symbol as PSig),
             ("string?", typecheck::string as PSig),
+            ("char?", typecheck::char as PSig),
+            ("char->integer", character::char_to_integer as PSig),
+            ("integer->char", character::integer_to_char as PSig),
+            ("char-upcase", character::upcase as PSig),
+            ("char-downcase", character::downcase as PSig),
+            ("char=?", character::char_eq as PSig),
+            ("char<?", character::char_lt as PSig),
+            ("char>?", character::char_gt as PSig),
+            ("char<=?", character::char_le as PSig),
+            ("char>=?", character::char_ge as PSig),
+            ("char-alphabetic?", character::alphabetic as PSig),
+            ("char-numeric?", character::numeric as PSig),
+            ("char-whitespace?", character::whitespace as PSig),
+            ("char-upper-case?", character::upper_case as PSig),
+            ("char-lower-case?", character::lower_case as PSig),
+            ("digit-value", character::digit_value as PSig),
+            ("make-string", string::make_string as PSig),
+            ("string", string::string as PSig),
+            ("string-length", string::string_length as PSig),
+            ("string-ref", string::string_ref as PSig),
+            ("string-set!", string::string_set as PSig),
+            ("string-fill!", string::string_fill as PSig),
+            ("substring", string::substring as PSig),
+            ("string-append", string::string_append as PSig),
+            ("string-copy", string::string_copy as PSig),
+            ("string=?", string::string_eq as PSig),
+            ("string<?", string::string_lt as PSig),
+            ("string>?", string::string_gt as PSig),
+            ("string<=?", string::string_le as PSig),
+            ("string>=?", string::string_ge as PSig),
+            ("string->list", string::string_to_list as PSig),
+            ("list->string", string::list_to_string as PSig),
+            ("string->symbol", string::string_to_symbol as PSig),
+            ("symbol->string", string::symbol_to_string as PSig),
+            ("string->number", string::string_to_number as PSig),
+            ("number->string", string::number_to_string as PSig),
+            ("string-upcase", string::string_upcase as PSig),
+            ("string-downcase", string::string_downcase as PSig),
+            ("string-index", string::string_index as PSig),
+            ("string-split", string::string_split as PSig),
+            ("string-join", string::string_join as PSig),
+            ("vector?", typecheck::vector as PSig),
+            ("make-vector", vector::make_vector as PSig),
+            ("vector", vector::vector as PSig),
+            ("vector-length", vector::vector_length as PSig),
+            ("vector-ref", vector::vector_ref as PSig),
+            ("vector-set!", vector::vector_set as PSig),
+            ("vector-fill!", vector::vector_fill as PSig),
+            ("vector-copy", vector::vector_copy as PSig),
+            ("vector->list", vector::vector_to_list as PSig),
+            ("list->vector", vector::list_to_vector as PSig),
+            ("bytevector?", typecheck::bytevector as PSig),
+            ("make-bytevector", bytevector::make_bytevector as PSig),
+            ("bytevector", bytevector::bytevector as PSig),
+            ("bytevector-length", bytevector::bytevector_length as PSig),
+            ("bytevector-u8-ref", bytevector::u8_ref as PSig),
+            ("bytevector-u8-set!", bytevector::u8_set as PSig),
+            ("bytevector-copy", bytevector::bytevector_copy as PSig),
+            ("bytevector-copy!", bytevector::bytevector_copy_to as PSig),
+            ("bytevector-append", bytevector::bytevector_append as PSig),
+            ("utf8->string", bytevector::utf8_to_string as PSig),
+            ("string->utf8", bytevector::string_to_utf8 as PSig),
+            ("open-input-bytevector", port::open_input_bytevector as PSig),
+            (
+                "open-output-bytevector",
+                port::open_output_bytevector as PSig,
+            ),
+            ("get-output-bytevector", port::get_output_bytevector as PSig),
+            (
+                "open-binary-input-file",
+                port::open_binary_input_file as PSig,
+            ),
+            (
+                "open-binary-output-file",
+                port::open_binary_output_file as PSig,
+            ),
+            ("close-port", port::close_port as PSig),
+            ("read-u8", port::read_u8 as PSig),
+            ("peek-u8", port::peek_u8 as PSig),
+            ("u8-ready?", port::u8_ready as PSig),
+            ("read-bytevector", port::read_bytevector as PSig),
+            ("write-u8", port::write_u8 as PSig),
+            ("write-bytevector", port::write_bytevector as PSig),
+            ("eof-object", port::eof_object as PSig),
+            ("eof-object?", port::is_eof_object as PSig),
+            ("port?", port::is_port as PSig),
+            ("binary-port?", port::is_port as PSig),
+            ("input-port?", port::is_input_port as PSig),
+            ("output-port?", port::is_output_port as PSig),
+            ("hash-table?", typecheck::hash_table as PSig),
+            ("make-hash-table", hash_table::make_hash_table as PSig),
+            (
+                "hash-table-ref/default",
+                hash_table::hash_table_ref_default as PSig,
+            ),
+            ("hash-table-set!", hash_table::hash_table_set as PSig),
+            ("hash-table-delete!", hash_table::hash_table_delete as PSig),
+            (
+                "hash-table-contains?",
+                hash_table::hash_table_contains as PSig,
+            ),
+            (
+                "hash-table-exists?",
+                hash_table::hash_table_contains as PSig,
+            ),
+            ("hash-table-count", hash_table::hash_table_count as PSig),
+            ("hash-table-size", hash_table::hash_table_count as PSig),
+            ("hash-table-keys", hash_table::hash_table_keys as PSig),
+            ("hash-table-values", hash_table::hash_table_values as PSig),
+            ("hash-table->alist", hash_table::hash_table_to_alist as PSig),
+            ("hash-table-copy", hash_table::hash_table_copy as PSig),
+            ("hash-table-clear!", hash_table::hash_table_clear as PSig),
+            ("make-record-type", record::make_record_type as PSig),
+            ("make-record", record::make_record as PSig),
+            ("record-is?", record::record_is as PSig),
+            ("record-ref", record::record_ref as PSig),
+            ("record-set!", record::record_set as PSig),
+            ("gc", gc::gc as PSig),
+            ("gc-statistics", gc::gc_statistics as PSig),
             ("number?", typecheck::number as PSig),
+            ("integer?", typecheck::integer as PSig),
+            ("rational?", typecheck::rational as PSig),
             ("pair?", typecheck::pair as PSig),
             ("null?", typecheck::null as PSig),
         ];
 
         for (name, proc) in primitives.into_iter() {
-            self.insert_val(name.to_string(), Primitive::new(proc).to_expr())
+            self.insert_val(name.into(), Primitive::new(proc).to_expr())
+                .unwrap_or_else(|err| panic!("unable to initalize global enviroment. {err}"));
+        }
+
+        for (name, proc) in control.into_iter() {
+            self.insert_val(name.into(), Control::new(proc).to_expr())
                 .unwrap_or_else(|err| panic!("unable to initalize global enviroment. {err}"));
         }
 
         self
     }
 
-    pub fn import_prelude(&self) -> Result<(), EvalErr> {
+    // Derived expression types that are defined as macros over the core special forms.
+    fn install_syntax(self) -> EnvRef {
+        TokenStream::new(prelude::SYNTAX)
+            .collect_tokens()
+            .and_then(|tokens| Parser::new(tokens).parse())
+            .and_then(|exprs| {
+                exprs
+                    .into_iter()
+                    .try_for_each(|exp| eval(exp, &self).map(|_| ()))
+            })
+            .unwrap_or_else(|err| panic!("unable to initalize global enviroment. {err}"));
+        self
+    }
+
+    pub fn import_prelude(&self, engine: Engine) -> Result<(), EvalErr> {
         let tokens = TokenStream::new(prelude::PRELUDE).collect_tokens()?;
         let exprs = Parser::new(tokens).parse()?;
         for exp in exprs.into_iter() {
-            match eval(exp, self) {
+            match engine.eval(exp, self) {
                 Ok(_) => (),
                 Err(err) => eprintln!("{err}"),
             }
@@ -118,38 +372,111 @@ impl EnvRef {
     }
 }
 
+impl Trace for RefCell<Env> {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self.try_borrow().as_deref() {
+            Ok(Env::Global(globals)) => globals.values().for_each(|global| {
+                if let Some(val) = &*global.val.borrow() {
+                    tracer.expr(val);
+                }
+            }),
+            Ok(Env::Frame { parent, slots }) => {
+                parent.trace(tracer);
+                slots.iter().for_each(|val| tracer.expr(val));
+            }
+            Err(_) => (),
+        }
+    }
+
+    // The cells of globals are emptied as well as dropped, since code can still be holding them.
+    fn clear(&self) {
+        match self.try_borrow_mut().as_deref_mut() {
+            Ok(Env::Global(globals)) => {
+                globals.values().for_each(|global| drop(global.val.take()));
+                globals.clear();
+            }
+            Ok(Env::Frame { parent, slots }) => {
+                *parent = EnvRef::nil();
+                slots.clear();
+            }
+            Err(_) => (),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub struct Env {
-    parent: EnvRef,
-    values: HashMap<String, Expr>,
+pub enum Env {
+    // The outermost enviroment, where each variable has a cell that code referring to it holds on
+    // to, so a global is only looked up by name once.
+    Global(SymbolMap<Rc<Global>>),
+    // The variables of a procedure call, or of a guard clause, kept in the order the analyzer gave
+    // them slots in.
+    Frame { parent: EnvRef, slots: Vec<Expr> },
 }
 
-impl Env {
-    pub fn new(parent: EnvRef) -> Self {
-        Env {
-            parent,
-            values: HashMap::new(),
+#[derive(Debug)]
+pub struct Global {
+    name: Symbol,
+    // Empty until the variable is defined.
+    val: RefCell<Option<Expr>>,
+}
+
+impl PartialEq for Global {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Global {
+    fn new(name: Symbol) -> Self {
+        Global {
+            name,
+            val: RefCell::new(None),
         }
     }
 
-    pub fn get_val(&self, name: &str) -> Result<Expr, EvalErr> {
-        self.values
-            .get(name)
-            .cloned()
-            .map_or_else(|| self.parent.get_val(name), Ok)
+    pub fn get(&self) -> Result<Expr, EvalErr> {
+        self.val
+            .borrow()
+            .clone()
+            .ok_or_else(|| EvalErr::UnboundVar(self.name.to_string()))
     }
 
-    pub fn insert_val(&mut self, name: String, val: Expr) {
-        self.values.insert(name, val);
+    pub fn define(&self, val: Expr) {
+        self.val.replace(Some(val));
     }
 
-    pub fn update_val(&mut self, name: String, val: Expr) -> Result<Expr, EvalErr> {
-        match self.values.get_mut(&name) {
-            Some(entry) => {
-                *entry = val;
-                self.get_val(&name)
+    pub fn set(&self, val: Expr) -> Result<Expr, EvalErr> {
+        match &mut *self.val.borrow_mut() {
+            Some(old) => {
+                *old = val.clone();
+                Ok(val)
             }
-            None => self.parent.update_val(name, val),
+            None => Err(EvalErr::UnboundVar(self.name.to_string())),
+        }
+    }
+}
+
+// A local variable is found by counting out frames from the current one, then taking one of that
+// frame's slots. The name is only kept for error messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Local {
+    pub name: Symbol,
+    pub depth: usize,
+    pub slot: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Variable {
+    Local(Local),
+    Global(Rc<Global>),
+}
+
+impl Variable {
+    pub fn name(&self) -> Symbol {
+        match self {
+            Variable::Local(local) => local.name,
+            Variable::Global(global) => global.name,
         }
     }
 }
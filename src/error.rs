@@ -3,20 +3,24 @@ use std::{error::Error, fmt};
 
 use crate::parser::Expr;
 use crate::print::Printable;
+use crate::utils::ToExpr;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum EvalErr {
     InvalidExpr(Expr),
     UnboundVar(String),
+    UnassignedVar(String),
     InvalidArgs(&'static str),
     TypeError(&'static str, Expr),
     UnexpectedToken(String),
     MalformedToken(&'static str),
     LexingFailures(Vec<EvalErr>),
-    RuntimeException(String),
+    RuntimeException(String, Vec<Expr>),
+    Raised(Expr),
     MapAsRecoverable,
     UnexpectedEnd,
     NilEnv,
+    IoError(String),
 }
 
 impl Error for EvalErr {}
@@ -27,15 +31,52 @@ impl fmt::Display for EvalErr {
     }
 }
 
+impl EvalErr {
+    // Recovers the error carried by a raised object, so that a condition which reaches the top
+    // level uncaught is reported as the error that produced it.
+    pub fn from_raised(obj: Expr) -> EvalErr {
+        match obj {
+            Expr::Condition(err) => err.as_ref().clone(),
+            obj => EvalErr::Raised(obj),
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            EvalErr::RuntimeException(m, _) => m.to_owned(),
+            err => make_message(err),
+        }
+    }
+
+    // The objects an error is about, as exposed to Scheme by `error-object-irritants`.
+    pub fn irritants(&self) -> Vec<Expr> {
+        match self {
+            EvalErr::RuntimeException(_, irritants) => irritants.clone(),
+            EvalErr::InvalidExpr(expr) | EvalErr::TypeError(_, expr) | EvalErr::Raised(expr) => {
+                vec![expr.clone()]
+            }
+            EvalErr::UnboundVar(var) | EvalErr::UnassignedVar(var) => vec![var.as_str().to_expr()],
+            _ => vec![],
+        }
+    }
+}
+
 fn make_message(err: &EvalErr) -> String {
     match err {
-        EvalErr::RuntimeException(m) => m.to_owned(),
+        EvalErr::RuntimeException(m, irritants) => {
+            irritants.iter().fold(m.to_owned(), |msg, irritant| {
+                format!("{msg} {}", irritant.printable())
+            })
+        }
+        EvalErr::Raised(obj) => format!("uncaught exception {}", obj.printable()),
         EvalErr::UnboundVar(var) => format!("accessing unbound variable {var}"),
+        EvalErr::UnassignedVar(var) => format!("accessing variable {var} before it is defined"),
         EvalErr::InvalidExpr(expr) => format!("invalid expression {}", expr.printable()),
         EvalErr::InvalidArgs(msg) => format!("invalid argument, {msg}"),
         EvalErr::MalformedToken(msg) => msg.to_string(),
         EvalErr::UnexpectedEnd => "unexpected end of expression".to_string(),
         EvalErr::NilEnv => "inserting value into empty enviroment".to_string(),
+        EvalErr::IoError(msg) => format!("i/o error, {msg}"),
         EvalErr::MapAsRecoverable => "recoverable".to_string(),
         EvalErr::UnexpectedToken(msg) => format!("unexpected token {msg}"),
         EvalErr::TypeError(expected, got) => {
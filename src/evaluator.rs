@@ -1,40 +1,215 @@
-use crate::enviroment::EnvRef;
+use std::rc::Rc;
+
+use crate::analyzer::{analyze, BodyIter, Code, Node, Scope};
+use crate::continuation::{eval_sequence, Catch, Continuation, Frame, Handler};
+use crate::enviroment::{EnvRef, Variable};
 use crate::error::EvalErr;
-use crate::lexer::Token;
+use crate::expander::expand;
+use crate::heap;
 use crate::parser::Expr;
-use crate::procedure::Proc;
-use crate::{special_form::Eval, utils::OwnIterVals};
+use crate::procedure::{Compound, Proc};
+use crate::utils::ToExpr;
+use crate::vm::{self, Activation};
+
+// Evaluation runs as a loop over an explicit continuation. Special forms and procedures never
+// evaluate sub-expressions themselves: they push a frame describing what to do with the result
+// and hand back the next step, so neither tail calls nor deep recursion grow the Rust stack, and
+// the continuation can be captured and reinstated by call/cc.
+#[derive(Debug)]
+pub enum Step {
+    Done(Expr),
+    TailCall(Code, EnvRef),
+    Apply(Expr, Args),
+    Run(Activation),
+}
+
+// Code is either evaluated from its analyzed form or compiled to bytecode and run on the VM. Both
+// engines share the continuation, so procedures made by either can be called from the other.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Engine {
+    #[default]
+    Tree,
+    Bytecode,
+}
+
+impl Engine {
+    pub fn eval(self, expr: Expr, env: &EnvRef) -> Result<Expr, EvalErr> {
+        match self {
+            Engine::Tree => eval(expr, env),
+            Engine::Bytecode => vm::eval(expr, env),
+        }
+    }
+}
 
 pub fn eval(expr: Expr, env: &EnvRef) -> Result<Expr, EvalErr> {
-    match expr {
-        Expr::Atom(Token::Symbol(ref identifier)) => env.get_val(identifier),
-        Expr::Call(ls) => {
-            let (op, args) = ls
-                .into_iter()
-                .own_one_and_rest_or_else(|| EvalErr::InvalidArgs("expected operation"))?;
-            let args = Args::new(args.collect(), env)?;
-            match op {
-                Expr::SpecialForm(x) => x.eval(env),
-                _ => apply(op, args),
+    let code = analyze(&expand(expr, env)?, &mut Scope::new(env))?;
+    run(Step::TailCall(code, env.clone_rc()?))
+}
+
+// Takes steps until one gives a value with nothing left in the continuation to pass it to.
+pub fn run(mut step: Step) -> Result<Expr, EvalErr> {
+    let mut k = Continuation::default();
+    loop {
+        heap::collect_if_due();
+        let next = match step {
+            Step::TailCall(code, env) => eval_step(&code, env, &mut k),
+            Step::Apply(op, args) => apply(op, args, &mut k),
+            Step::Run(act) => vm::run(act, &mut k),
+            Step::Done(val) => match k.pop() {
+                Some(frame) => frame.resume(val, &mut k),
+                None => return Ok(val),
+            },
+        };
+        step = match next {
+            Ok(step) => step,
+            Err(err) => k.raise(err.to_expr(), false)?,
+        };
+    }
+}
+
+fn eval_step(code: &Code, env: EnvRef, k: &mut Continuation) -> Result<Step, EvalErr> {
+    match code.node() {
+        Node::Constant(val) => Ok(Step::Done(val.clone())),
+        Node::Variable(variable) => Ok(Step::Done(lookup(variable, &env)?)),
+        Node::Call(items) => {
+            let pending = BodyIter::new(items.clone());
+            let evaluated = Vec::with_capacity(pending.len());
+            eval_operands(evaluated, pending, env, k)
+        }
+        Node::If {
+            predicate,
+            consequence,
+            alternative,
+        } => {
+            k.push(Frame::Branch {
+                consequence: consequence.clone(),
+                alternative: alternative.clone(),
+                env: env.clone_rc()?,
+            });
+            Ok(Step::TailCall(predicate.clone(), env))
+        }
+        Node::Define(variable, body) => {
+            k.push(Frame::Define {
+                variable: variable.clone(),
+                env: env.clone_rc()?,
+            });
+            Ok(Step::TailCall(body.clone(), env))
+        }
+        Node::Assignment(variable, value) => {
+            k.push(Frame::Assignment {
+                variable: variable.clone(),
+                env: env.clone_rc()?,
+            });
+            Ok(Step::TailCall(value.clone(), env))
+        }
+        Node::Lambda(lambda) => {
+            let closure = Compound::new(Rc::clone(lambda), env);
+            Ok(Step::Done(Proc::Compound(closure).to_expr()))
+        }
+        Node::CaseLambda(clauses) => {
+            let clauses = clauses
+                .iter()
+                .map(|lambda| Ok(Compound::new(Rc::clone(lambda), env.clone_rc()?)))
+                .collect::<Result<Vec<Compound>, EvalErr>>()?;
+            Ok(Step::Done(Proc::CaseLambda(clauses).to_expr()))
+        }
+        Node::Begin(body) => Ok(eval_sequence(BodyIter::new(body.clone()), env, k)),
+        Node::And(body) => {
+            let mut pending = BodyIter::new(body.clone());
+            let Some(first) = pending.next() else {
+                return Ok(Step::Done(true.to_expr()));
+            };
+            if pending.len() > 0 {
+                k.push(Frame::And {
+                    pending,
+                    env: env.clone_rc()?,
+                });
+            }
+            Ok(Step::TailCall(first, env))
+        }
+        Node::Or(body) => {
+            let mut pending = BodyIter::new(body.clone());
+            let Some(first) = pending.next() else {
+                return Ok(Step::Done(false.to_expr()));
+            };
+            if pending.len() > 0 {
+                k.push(Frame::Or {
+                    pending,
+                    env: env.clone_rc()?,
+                });
+            }
+            Ok(Step::TailCall(first, env))
+        }
+        Node::Guard { clauses, body } => {
+            let clauses = Compound::new(Rc::clone(clauses), env.clone_rc()?);
+            let catch = Catch::new(Proc::Compound(clauses).to_expr(), env.clone_rc()?);
+            let handlers = k.handlers().with(Handler::Guard(k.clone(), Rc::new(catch)));
+            k.push(Frame::Handlers(handlers));
+            Ok(eval_sequence(BodyIter::new(body.clone()), env, k))
+        }
+    }
+}
+
+fn lookup(variable: &Variable, env: &EnvRef) -> Result<Expr, EvalErr> {
+    match env.get_var(variable)? {
+        Expr::Unassigned => Err(EvalErr::UnassignedVar(variable.name().to_string())),
+        val => Ok(val),
+    }
+}
+
+// Evaluates the rest of a call's operator and operands, then applies it. Constants and variables
+// are looked up on the spot, so only operands that need evaluating take a step of their own.
+pub fn eval_operands(
+    mut evaluated: Vec<Expr>,
+    mut pending: BodyIter,
+    env: EnvRef,
+    k: &mut Continuation,
+) -> Result<Step, EvalErr> {
+    while let Some(next) = pending.next() {
+        match next.node() {
+            Node::Constant(val) => evaluated.push(val.clone()),
+            Node::Variable(variable) => evaluated.push(lookup(variable, &env)?),
+            _ => {
+                k.push(Frame::Operands {
+                    evaluated,
+                    pending,
+                    env: env.clone_rc()?,
+                });
+                return Ok(Step::TailCall(next, env));
             }
         }
-        Expr::Quoted(x) => Ok(*x),
-        x @ Expr::Atom(_) | x @ Expr::Pair(_) | x @ Expr::EmptyList | x @ Expr::Void => Ok(x),
-        x => Err(EvalErr::TypeError("expression", x)),
     }
+    let mut evaluated = evaluated.into_iter();
+    let op = evaluated
+        .next()
+        .ok_or(EvalErr::InvalidArgs("expected operation"))?;
+    Ok(Step::Apply(op, Args::new(evaluated.collect(), &env)?))
 }
 
-pub fn apply(op: Expr, args: Args) -> Result<Expr, EvalErr> {
-    let env = &args.env()?;
-    match eval(op, env)? {
+pub fn apply(op: Expr, args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+    match op {
         Expr::Proc(proc) => match proc.as_ref() {
-            Proc::Primitive(proc) => proc.call(args.eval()?),
-            Proc::Compound(proc) => proc.call(args.eval()?),
+            Proc::Primitive(proc) => Ok(Step::Done(proc.call(args)?)),
+            Proc::Control(proc) => proc.call(args, k),
+            Proc::Compound(proc) => proc.call(args, k),
+            Proc::CaseLambda(clauses) => clauses
+                .iter()
+                .find(|clause| clause.accepts(args.len()))
+                .ok_or(EvalErr::InvalidArgs(
+                    "'case-lambda'. no clause accepts that many arguments",
+                ))?
+                .call(args, k),
+            Proc::Continuation(cont) => {
+                let env = args.env()?;
+                let val = args.into_iter().next().unwrap_or(Expr::Void);
+                k.reinstate(cont, val, env)
+            }
         },
         op => Err(EvalErr::TypeError("procedure", op)),
     }
 }
 
+#[derive(Debug)]
 pub struct Args {
     args: Vec<Expr>,
     env: EnvRef,
@@ -48,17 +223,6 @@ impl Args {
         })
     }
 
-    pub fn eval(mut self) -> Result<Args, EvalErr> {
-        let evaled = self
-            .args
-            .into_iter()
-            .map(|expr| eval(expr, &self.env))
-            .collect::<Result<Vec<Expr>, EvalErr>>()?;
-
-        self.args = evaled;
-        Ok(self)
-    }
-
     pub fn env(&self) -> Result<EnvRef, EvalErr> {
         self.env.clone_rc()
     }
@@ -0,0 +1,881 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec::IntoIter;
+
+use crate::enviroment::EnvRef;
+use crate::error::EvalErr;
+use crate::lexer::Token;
+use crate::parser::Expr;
+use crate::primitives::pair::Pair;
+use crate::special_form::{
+    And, Assignment, Begin, CaseLambda, Declare, Define, GlobalRef, Guard, If, Lambda, Or,
+};
+use crate::symbol::Symbol;
+use crate::syntax_rules::Macro;
+use crate::utils::{OwnIterVals, ToExpr};
+
+// Expansion turns the lists read by the parser into the special forms the evaluator runs. It
+// works on one top level form at a time, right before it is evaluated, so that a macro defined
+// by one form can be used by the next.
+//
+// Macros are hygienic. Each identifier a macro's template introduces becomes an alias that
+// remembers the scope the macro was defined in: binding an alias gives the variable a fresh name
+// so it can't capture a variable of the same name from the use, and a free alias resolves to
+// whatever its name meant where the macro was defined.
+pub fn expand(expr: Expr, env: &EnvRef) -> Result<Expr, EvalErr> {
+    Expander { env }.expand_toplevel(expr)
+}
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn fresh_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Symbols can't start with '#' in source, so renamed variables never clash with written ones.
+fn fresh_name(name: Symbol) -> Symbol {
+    Symbol::intern(&format!("#:{name}.{}", fresh_id()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Keyword {
+    Quote,
+    If,
+    Define,
+    Lambda,
+    CaseLambda,
+    Assignment,
+    Begin,
+    And,
+    Or,
+    Guard,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    DefineSyntax,
+    LetSyntax,
+    LetrecSyntax,
+    SyntaxRules,
+}
+
+impl Keyword {
+    fn from_name(name: Symbol) -> Option<Keyword> {
+        match name {
+            Symbol::QUOTE => Some(Keyword::Quote),
+            Symbol::IF => Some(Keyword::If),
+            Symbol::DEFINE => Some(Keyword::Define),
+            Symbol::LAMBDA => Some(Keyword::Lambda),
+            Symbol::CASE_LAMBDA => Some(Keyword::CaseLambda),
+            Symbol::SET => Some(Keyword::Assignment),
+            Symbol::BEGIN => Some(Keyword::Begin),
+            Symbol::AND => Some(Keyword::And),
+            Symbol::OR => Some(Keyword::Or),
+            Symbol::GUARD => Some(Keyword::Guard),
+            Symbol::QUASIQUOTE => Some(Keyword::Quasiquote),
+            Symbol::UNQUOTE => Some(Keyword::Unquote),
+            Symbol::UNQUOTE_SPLICING => Some(Keyword::UnquoteSplicing),
+            Symbol::DEFINE_SYNTAX => Some(Keyword::DefineSyntax),
+            Symbol::LET_SYNTAX => Some(Keyword::LetSyntax),
+            Symbol::LETREC_SYNTAX => Some(Keyword::LetrecSyntax),
+            Symbol::SYNTAX_RULES => Some(Keyword::SyntaxRules),
+            _ => None,
+        }
+    }
+}
+
+// An identifier introduced by a macro expansion.
+#[derive(Debug)]
+pub struct Alias {
+    id: usize,
+    name: Expr,
+    scope: Scope,
+}
+
+#[allow(clippy::new_ret_no_self)]
+impl Alias {
+    pub fn new(name: Expr, scope: Scope) -> Expr {
+        Expr::Alias(Rc::new(Alias {
+            id: fresh_id(),
+            name,
+            scope,
+        }))
+    }
+}
+
+impl PartialEq for Alias {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum IdentKey {
+    Symbol(Symbol),
+    Alias(usize),
+}
+
+impl IdentKey {
+    pub fn of(expr: &Expr) -> Option<IdentKey> {
+        match expr {
+            Expr::Atom(Token::Symbol(name)) => Some(IdentKey::Symbol(*name)),
+            Expr::Alias(alias) => Some(IdentKey::Alias(alias.id)),
+            _ => None,
+        }
+    }
+}
+
+// The name an identifier was written with, looking through any renaming.
+pub fn identifier_name(expr: &Expr) -> Option<Symbol> {
+    match expr {
+        Expr::Atom(Token::Symbol(name)) => Some(*name),
+        Expr::Alias(alias) => identifier_name(&alias.name),
+        _ => None,
+    }
+}
+
+// Turns syntax back into plain data, as `quote` sees it: lists become pairs and aliases lose their
+// renaming.
+pub fn strip_syntax(expr: Expr) -> Expr {
+    match expr {
+        Expr::Call(items) => items.into_iter().rev().fold(Expr::EmptyList, |cdr, car| {
+            Pair::new(strip_syntax(car), cdr).to_expr()
+        }),
+        Expr::Pair(pair) => Pair::new(strip_syntax(pair.car()), strip_syntax(pair.cdr())).to_expr(),
+        Expr::Vector(v) => {
+            Expr::literal_vector(v.items().iter().cloned().map(strip_syntax).collect())
+        }
+        Expr::Alias(alias) => identifier_name(&alias.name)
+            .map(|name| name.to_expr())
+            .unwrap_or(Expr::Void),
+        expr => expr,
+    }
+}
+
+// The parser reads quoted lists straight into pairs. Macro patterns and templates turn them back
+// into syntax so that pattern variables inside quoted data are substituted like anywhere else.
+pub fn to_syntax(expr: Expr) -> Expr {
+    match expr {
+        Expr::Call(items) => items
+            .into_iter()
+            .map(to_syntax)
+            .collect::<Vec<Expr>>()
+            .to_expr(),
+        Expr::Pair(pair) => Pair::new(to_syntax(pair.car()), to_syntax(pair.cdr())).to_expr(),
+        Expr::Quoted(datum) => vec!["quote".to_expr(), datum_to_syntax(*datum)].to_expr(),
+        expr @ Expr::Vector(_) => datum_to_syntax(expr),
+        expr => expr,
+    }
+}
+
+fn datum_to_syntax(datum: Expr) -> Expr {
+    if let Expr::Vector(v) = datum {
+        return Expr::literal_vector(v.items().iter().cloned().map(datum_to_syntax).collect());
+    }
+    let (items, tail) = dotted_parts(&datum);
+    dotted(items.into_iter().map(datum_to_syntax).collect(), tail)
+}
+
+// An improper list in syntax, `(a b . c)`, is read as a chain of pairs holding syntax. These split
+// one into its elements and final cdr, and join them back up again, using a plain list when the
+// tail turns out to be one.
+pub fn dotted_parts(expr: &Expr) -> (Vec<Expr>, Expr) {
+    let mut items = vec![];
+    let mut rest = expr.clone();
+    loop {
+        match rest {
+            Expr::Pair(pair) => {
+                items.push(pair.car());
+                rest = pair.cdr();
+            }
+            Expr::Call(more) => {
+                items.extend(more);
+                return (items, Expr::EmptyList);
+            }
+            tail => return (items, tail),
+        }
+    }
+}
+
+pub fn dotted(items: Vec<Expr>, tail: Expr) -> Expr {
+    match tail {
+        Expr::Pair(_) | Expr::Call(_) => {
+            let (more, tail) = dotted_parts(&tail);
+            dotted([items, more].concat(), tail)
+        }
+        Expr::EmptyList if items.is_empty() => Expr::EmptyList,
+        Expr::EmptyList => items.to_expr(),
+        tail => items
+            .into_iter()
+            .rev()
+            .fold(tail, |cdr, car| Pair::new(car, cdr).to_expr()),
+    }
+}
+
+// The bindings visible to the expander while it walks the body of a procedure. Local variables
+// map to the name they have at runtime, which differs from the written one once renamed.
+#[derive(Clone, Default)]
+pub struct Scope(Option<Rc<ScopeFrame>>);
+
+struct ScopeFrame {
+    bindings: RefCell<HashMap<IdentKey, Binding>>,
+    parent: Scope,
+}
+
+#[derive(Clone)]
+enum Binding {
+    Variable(Symbol),
+    Macro(Rc<Macro>),
+}
+
+impl Scope {
+    fn extend(&self) -> Scope {
+        Scope(Some(Rc::new(ScopeFrame {
+            bindings: RefCell::new(HashMap::new()),
+            parent: self.clone(),
+        })))
+    }
+
+    fn lookup(&self, key: &IdentKey) -> Option<Binding> {
+        let frame = self.0.as_ref()?;
+        match frame.bindings.borrow().get(key) {
+            Some(binding) => Some(binding.clone()),
+            None => frame.parent.lookup(key),
+        }
+    }
+
+    fn bind(&self, key: IdentKey, binding: Binding) {
+        if let Some(frame) = self.0.as_ref() {
+            frame.bindings.borrow_mut().insert(key, binding);
+        }
+    }
+
+    // Whether a local variable with this runtime name is in scope.
+    fn binds_name(&self, name: Symbol) -> bool {
+        let Some(frame) = self.0.as_ref() else {
+            return false;
+        };
+        let bound = frame
+            .bindings
+            .borrow()
+            .values()
+            .any(|binding| matches!(binding, Binding::Variable(var) if *var == name));
+        bound || frame.parent.binds_name(name)
+    }
+}
+
+impl fmt::Debug for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#<scope>")
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Resolved {
+    Variable(Symbol),
+    Global(Symbol),
+    Macro(Rc<Macro>),
+    Keyword(Keyword),
+}
+
+fn has_unquote(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call(items) => items.iter().any(has_unquote),
+        Expr::Pair(pair) => has_unquote(&pair.car()) || has_unquote(&pair.cdr()),
+        Expr::Vector(v) => v.items().iter().any(has_unquote),
+        Expr::Quoted(datum) => has_unquote(&to_syntax(Expr::Quoted(datum.clone()))),
+        ident => identifier_name(ident)
+            .is_some_and(|name| name == Symbol::UNQUOTE || name == Symbol::UNQUOTE_SPLICING),
+    }
+}
+
+fn operands(form: Vec<Expr>) -> IntoIter<Expr> {
+    let mut operands = form.into_iter();
+    operands.next();
+    operands
+}
+
+enum BodyItem {
+    Definition(Expr, Expr),
+    Expression(Expr),
+}
+
+struct Expander<'a> {
+    env: &'a EnvRef,
+}
+
+impl Expander<'_> {
+    fn resolve(&self, ident: &Expr, scope: &Scope) -> Option<Resolved> {
+        if let Some(binding) = IdentKey::of(ident).and_then(|key| scope.lookup(&key)) {
+            return Some(match binding {
+                Binding::Variable(name) => Resolved::Variable(name),
+                Binding::Macro(m) => Resolved::Macro(m),
+            });
+        }
+        match ident {
+            Expr::Alias(alias) => self.resolve(&alias.name, &alias.scope),
+            Expr::Atom(Token::Symbol(name)) => Some(match self.env.get_val(*name) {
+                Ok(Expr::Macro(m)) => Resolved::Macro(m),
+                _ => match Keyword::from_name(*name) {
+                    Some(keyword) => Resolved::Keyword(keyword),
+                    None => Resolved::Global(*name),
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    fn resolve_head(&self, form: &[Expr], scope: &Scope) -> Option<Resolved> {
+        form.first().and_then(|head| self.resolve(head, scope))
+    }
+
+    fn expand_toplevel(&self, expr: Expr) -> Result<Expr, EvalErr> {
+        let scope = Scope::default();
+        let Expr::Call(form) = expr else {
+            return self.expand_expr(expr, &scope);
+        };
+        match self.resolve_head(&form, &scope) {
+            Some(Resolved::Macro(m)) => self.expand_toplevel(self.expand_macro(&m, &form, &scope)?),
+            Some(Resolved::Keyword(Keyword::Begin)) => Begin::new(
+                operands(form)
+                    .map(|expr| self.expand_toplevel(expr))
+                    .collect::<Result<Vec<Expr>, EvalErr>>()?,
+            )
+            .to_expr()
+            .into_call(),
+            // Top level definitions always bind the name as written, even when a macro introduced
+            // it, so that macros can define globals.
+            Some(Resolved::Keyword(Keyword::Define)) => {
+                let (identifier, value) = self.definition(operands(form))?;
+                let name = identifier_name(&identifier)
+                    .ok_or(EvalErr::TypeError("symbol or list", identifier))?;
+                Define::new(name.to_expr(), self.expand_expr(value, &scope)?)
+                    .to_expr()
+                    .into_call()
+            }
+            Some(Resolved::Keyword(Keyword::DefineSyntax)) => {
+                let (keyword, spec) = self.syntax_definition(operands(form))?;
+                let name =
+                    identifier_name(&keyword).ok_or(EvalErr::TypeError("symbol", keyword))?;
+                let transformer = self.transformer(spec, &scope)?;
+                self.env
+                    .insert_val(name, Expr::Macro(Rc::new(transformer)))?;
+                Ok(Expr::Void)
+            }
+            _ => self.expand_form(form, &scope),
+        }
+    }
+
+    fn expand_expr(&self, expr: Expr, scope: &Scope) -> Result<Expr, EvalErr> {
+        match expr {
+            Expr::Call(form) => self.expand_form(form, scope),
+            ident @ (Expr::Atom(Token::Symbol(_)) | Expr::Alias(_)) => {
+                self.expand_reference(ident, scope)
+            }
+            expr @ Expr::Pair(_) => Err(EvalErr::InvalidExpr(strip_syntax(expr))),
+            // A vector is a literal, but one from a macro template may still hold aliases.
+            expr @ Expr::Vector(_) => Ok(strip_syntax(expr)),
+            expr => Ok(expr),
+        }
+    }
+
+    fn expand_each(&self, exprs: IntoIter<Expr>, scope: &Scope) -> Result<Vec<Expr>, EvalErr> {
+        exprs.map(|expr| self.expand_expr(expr, scope)).collect()
+    }
+
+    fn expand_form(&self, form: Vec<Expr>, scope: &Scope) -> Result<Expr, EvalErr> {
+        match self.resolve_head(&form, scope) {
+            Some(Resolved::Macro(m)) => {
+                let expanded = self.expand_macro(&m, &form, scope)?;
+                self.expand_expr(expanded, scope)
+            }
+            Some(Resolved::Keyword(keyword)) => self.expand_keyword(keyword, form, scope),
+            _ => Ok(self.expand_each(form.into_iter(), scope)?.to_expr()),
+        }
+    }
+
+    fn expand_macro(&self, m: &Macro, form: &[Expr], scope: &Scope) -> Result<Expr, EvalErr> {
+        m.expand(form, &|input, literal| {
+            self.resolve(input, scope) == self.resolve(literal, m.scope())
+        })
+    }
+
+    fn expand_reference(&self, ident: Expr, scope: &Scope) -> Result<Expr, EvalErr> {
+        match self.resolve(&ident, scope) {
+            Some(Resolved::Variable(name)) => Ok(name.to_expr()),
+            // A global that a macro refers to may be shadowed by a local variable where the macro
+            // is used, in which case it has to be looked up past the local one.
+            Some(Resolved::Global(name)) => {
+                match matches!(ident, Expr::Alias(_)) && scope.binds_name(name) {
+                    true => GlobalRef::new(name).to_expr().into_call(),
+                    false => Ok(name.to_expr()),
+                }
+            }
+            _ => Err(EvalErr::InvalidExpr(strip_syntax(ident))),
+        }
+    }
+
+    // The variable a `set!` or a `define` outside of a body refers to, which is looked up past any
+    // local variable that shadows a global the same way as a reference.
+    fn target(&self, ident: Expr, scope: &Scope, expected: &'static str) -> Result<Expr, EvalErr> {
+        match self.resolve(&ident, scope) {
+            Some(Resolved::Variable(name)) => Ok(name.to_expr()),
+            Some(Resolved::Global(name)) => {
+                match matches!(ident, Expr::Alias(_)) && scope.binds_name(name) {
+                    true => Ok(GlobalRef::new(name).to_expr()),
+                    false => Ok(name.to_expr()),
+                }
+            }
+            Some(_) => Err(EvalErr::InvalidExpr(strip_syntax(ident))),
+            None => Err(EvalErr::TypeError(expected, ident)),
+        }
+    }
+
+    // Identifiers introduced by a macro, and those that shadow another local variable, are given
+    // a fresh name so that they can't capture references meant for something else.
+    fn bind_variable(&self, ident: Expr, scope: &Scope) -> Result<Expr, EvalErr> {
+        let (Some(key), Some(name)) = (IdentKey::of(&ident), identifier_name(&ident)) else {
+            return Err(EvalErr::TypeError("symbol", ident));
+        };
+        let name = match key {
+            IdentKey::Symbol(_) if !scope.binds_name(name) => name,
+            _ => fresh_name(name),
+        };
+        scope.bind(key, Binding::Variable(name));
+        Ok(name.to_expr())
+    }
+
+    fn expand_keyword(
+        &self,
+        keyword: Keyword,
+        form: Vec<Expr>,
+        scope: &Scope,
+    ) -> Result<Expr, EvalErr> {
+        if matches!(
+            keyword,
+            Keyword::DefineSyntax
+                | Keyword::SyntaxRules
+                | Keyword::Unquote
+                | Keyword::UnquoteSplicing
+        ) {
+            return Err(EvalErr::InvalidExpr(strip_syntax(form.to_expr())));
+        }
+
+        let mut operands = operands(form);
+        match keyword {
+            Keyword::Quote => Ok(Expr::Quoted(Box::new(strip_syntax(
+                operands.own_one_or_else(|| {
+                    EvalErr::InvalidArgs("'quote' expression. expected datum")
+                })?,
+            )))),
+            Keyword::If => self.expand_if(operands, scope),
+            Keyword::Define => {
+                let (identifier, value) = self.definition(operands)?;
+                Define::new(
+                    self.target(identifier, scope, "symbol or list")?,
+                    self.expand_expr(value, scope)?,
+                )
+                .to_expr()
+                .into_call()
+            }
+            Keyword::Lambda => {
+                let (params, body) = operands.own_one_and_rest_or_else(|| {
+                    EvalErr::InvalidArgs("'lambda' expression. expected parameters and body")
+                })?;
+                self.expand_lambda(params, body.collect(), scope)
+            }
+            Keyword::CaseLambda => {
+                let clauses = operands
+                    .map(|clause| match clause {
+                        Expr::Call(clause) => {
+                            let (params, body) =
+                                clause.into_iter().own_one_and_rest_or_else(|| {
+                                    EvalErr::InvalidArgs(
+                                        "'case-lambda' clause. expected parameters and body",
+                                    )
+                                })?;
+                            self.lambda(params, body.collect(), scope)
+                        }
+                        expr => Err(EvalErr::TypeError("list", expr)),
+                    })
+                    .collect::<Result<Vec<Lambda>, EvalErr>>()?;
+                CaseLambda::new(clauses).to_expr().into_call()
+            }
+            Keyword::Assignment => {
+                let (identifier, value) = operands.own_two_or_else(|| {
+                    EvalErr::InvalidArgs("'set!' expression. expected identifier and value")
+                })?;
+                Assignment::new(
+                    self.target(identifier, scope, "symbol")?,
+                    self.expand_expr(value, scope)?,
+                )
+                .to_expr()
+                .into_call()
+            }
+            Keyword::Begin => Begin::new(self.expand_each(operands, scope)?)
+                .to_expr()
+                .into_call(),
+            Keyword::And => And::new(self.expand_each(operands, scope)?)
+                .to_expr()
+                .into_call(),
+            Keyword::Or => Or::new(self.expand_each(operands, scope)?)
+                .to_expr()
+                .into_call(),
+            Keyword::Guard => self.expand_guard(operands, scope),
+            Keyword::Quasiquote => {
+                let template = operands.own_one_or_else(|| {
+                    EvalErr::InvalidArgs("'quasiquote' expression. expected template")
+                })?;
+                self.quasiquote(template, 1, scope)
+            }
+            Keyword::LetSyntax => self.expand_let_syntax(operands, scope, false),
+            Keyword::LetrecSyntax => self.expand_let_syntax(operands, scope, true),
+            Keyword::DefineSyntax
+            | Keyword::SyntaxRules
+            | Keyword::Unquote
+            | Keyword::UnquoteSplicing => unreachable!(),
+        }
+    }
+
+    fn expand_if(&self, mut operands: IntoIter<Expr>, scope: &Scope) -> Result<Expr, EvalErr> {
+        let err = || {
+            EvalErr::InvalidArgs("'if' expression. expected condition, predicate, and consequence")
+        };
+        let (predicate, consequence) = operands.own_two_or_else(err)?;
+        let alternative = match operands.next() {
+            Some(alternative) => self.expand_expr(alternative, scope)?,
+            None => Expr::Void,
+        };
+        if operands.next().is_some() {
+            return Err(err());
+        }
+        If::new(
+            self.expand_expr(predicate, scope)?,
+            self.expand_expr(consequence, scope)?,
+            alternative,
+        )
+        .to_expr()
+        .into_call()
+    }
+
+    fn expand_lambda(&self, params: Expr, body: Vec<Expr>, scope: &Scope) -> Result<Expr, EvalErr> {
+        self.lambda(params, body, scope)?.to_expr().into_call()
+    }
+
+    fn lambda(&self, params: Expr, body: Vec<Expr>, scope: &Scope) -> Result<Lambda, EvalErr> {
+        let scope = scope.extend();
+        // `(a b)`, `(a b . rest)` or just `args`, where the trailing identifier takes a list of
+        // any remaining arguments.
+        let (params, rest) = match params {
+            Expr::Call(_) | Expr::Pair(_) | Expr::EmptyList => dotted_parts(&params),
+            ident => (vec![], ident),
+        };
+        let params = params
+            .into_iter()
+            .map(|param| self.bind_variable(param, &scope))
+            .collect::<Result<Vec<Expr>, EvalErr>>()?;
+        let rest = match rest {
+            Expr::EmptyList => Expr::EmptyList,
+            ident => self.bind_variable(ident, &scope)?,
+        };
+        let params = dotted(params, rest);
+        let body = self.expand_body(body, &scope)?;
+        Ok(Lambda::new(params, body))
+    }
+
+    // Splits `(define (name . params) body...)` and `(define name value)` into the identifier and
+    // the syntax of its value.
+    fn definition(&self, operands: IntoIter<Expr>) -> Result<(Expr, Expr), EvalErr> {
+        let (target, mut body) = operands.own_one_and_rest_or_else(|| {
+            EvalErr::InvalidArgs("'define' expression. expected identifier and value")
+        })?;
+        match target {
+            Expr::Call(_) | Expr::Pair(_) => {
+                let (target, rest) = dotted_parts(&target);
+                let (identifier, params) = target.into_iter().own_one_and_rest_or_else(|| {
+                    EvalErr::InvalidArgs("'define' procedure. expected parameters and body")
+                })?;
+                let params = dotted(params.collect(), rest);
+                // Refers to the core `lambda` whatever the name is bound to where this appears.
+                let lambda = Alias::new("lambda".to_expr(), Scope::default());
+                Ok((
+                    identifier,
+                    [vec![lambda, params], body.collect()].concat().to_expr(),
+                ))
+            }
+            identifier => Ok((
+                identifier,
+                body.own_one_or_else(|| {
+                    EvalErr::InvalidArgs("'define' expression. expected identifier and value")
+                })?,
+            )),
+        }
+    }
+
+    fn syntax_definition(&self, mut operands: IntoIter<Expr>) -> Result<(Expr, Expr), EvalErr> {
+        operands.own_two_or_else(|| {
+            EvalErr::InvalidArgs("'define-syntax' expression. expected keyword and transformer")
+        })
+    }
+
+    fn transformer(&self, spec: Expr, scope: &Scope) -> Result<Macro, EvalErr> {
+        match spec {
+            Expr::Call(form)
+                if self.resolve_head(&form, scope)
+                    == Some(Resolved::Keyword(Keyword::SyntaxRules)) =>
+            {
+                Macro::new(operands(form).collect(), scope.clone())
+            }
+            expr => Err(EvalErr::TypeError("syntax-rules", strip_syntax(expr))),
+        }
+    }
+
+    // Definitions in a body are found first, expanding macros as far as needed to tell them apart
+    // from expressions, so that every expression in the body sees all of them.
+    fn expand_body(&self, body: Vec<Expr>, scope: &Scope) -> Result<Vec<Expr>, EvalErr> {
+        let mut pending = VecDeque::from(body);
+        let mut items = vec![];
+        while let Some(expr) = pending.pop_front() {
+            let Expr::Call(form) = expr else {
+                items.push(BodyItem::Expression(expr));
+                continue;
+            };
+            match self.resolve_head(&form, scope) {
+                Some(Resolved::Macro(m)) => {
+                    pending.push_front(self.expand_macro(&m, &form, scope)?);
+                }
+                Some(Resolved::Keyword(Keyword::Begin)) => {
+                    for expr in operands(form).rev() {
+                        pending.push_front(expr);
+                    }
+                }
+                Some(Resolved::Keyword(Keyword::Define)) => {
+                    let (identifier, value) = self.definition(operands(form))?;
+                    let name = self.bind_variable(identifier, scope)?;
+                    items.push(BodyItem::Definition(name, value));
+                }
+                Some(Resolved::Keyword(Keyword::DefineSyntax)) => {
+                    let (keyword, spec) = self.syntax_definition(operands(form))?;
+                    let key =
+                        IdentKey::of(&keyword).ok_or(EvalErr::TypeError("symbol", keyword))?;
+                    let transformer = self.transformer(spec, scope)?;
+                    scope.bind(key, Binding::Macro(Rc::new(transformer)));
+                }
+                _ => items.push(BodyItem::Expression(form.to_expr())),
+            }
+        }
+
+        let declared = items
+            .iter()
+            .filter_map(|item| match item {
+                BodyItem::Definition(name, _) => identifier_name(name),
+                BodyItem::Expression(_) => None,
+            })
+            .collect::<Vec<Symbol>>();
+        let declare = match declared.is_empty() {
+            true => None,
+            false => Some(Declare::new(declared).to_expr().into_call()),
+        };
+
+        declare
+            .into_iter()
+            .chain(items.into_iter().map(|item| {
+                match item {
+                    BodyItem::Definition(name, value) => {
+                        Define::new(name, self.expand_expr(value, scope)?)
+                            .to_expr()
+                            .into_call()
+                    }
+                    BodyItem::Expression(expr) => self.expand_expr(expr, scope),
+                }
+            }))
+            .collect()
+    }
+
+    fn expand_let_syntax(
+        &self,
+        operands: IntoIter<Expr>,
+        scope: &Scope,
+        recursive: bool,
+    ) -> Result<Expr, EvalErr> {
+        let (bindings, body) = operands.own_one_and_rest_or_else(|| {
+            EvalErr::InvalidArgs("'let-syntax' expression. expected bindings and body")
+        })?;
+        let bindings = match bindings {
+            Expr::Call(bindings) => bindings,
+            Expr::EmptyList => vec![],
+            expr => return Err(EvalErr::TypeError("list", expr)),
+        };
+
+        let inner = scope.extend();
+        for binding in bindings {
+            let Expr::Call(binding) = binding else {
+                return Err(EvalErr::TypeError("list", binding));
+            };
+            let (keyword, spec) = binding.into_iter().own_two_or_else(|| {
+                EvalErr::InvalidArgs("'let-syntax' expression. expected keyword and transformer")
+            })?;
+            let key = IdentKey::of(&keyword).ok_or(EvalErr::TypeError("symbol", keyword))?;
+            let transformer = self.transformer(spec, if recursive { &inner } else { scope })?;
+            inner.bind(key, Binding::Macro(Rc::new(transformer)));
+        }
+
+        // The body runs like the body of a procedure that is called straight away.
+        let body = self.expand_body(body.collect(), &inner.extend())?;
+        Ok(vec![Lambda::new(Expr::EmptyList, body).to_expr().into_call()?].to_expr())
+    }
+
+    // Builds the code for a quasiquote template. Parts without anything unquoted stay quoted
+    // data, the rest is put together with `cons` and `append`. `depth` counts the quasiquotes the
+    // template is nested in, only unquotes at depth one are evaluated.
+    fn quasiquote(&self, template: Expr, depth: usize, scope: &Scope) -> Result<Expr, EvalErr> {
+        if !has_unquote(&template) {
+            return Ok(Expr::Quoted(Box::new(strip_syntax(template))));
+        }
+        let (mut items, tail) = match template {
+            Expr::Call(items) => (items, Expr::EmptyList),
+            Expr::Pair(_) => dotted_parts(&template),
+            Expr::Quoted(datum) => {
+                return self.quasiquote(to_syntax(Expr::Quoted(datum)), depth, scope)
+            }
+            Expr::Vector(v) => {
+                let items = v.items().to_vec().to_expr();
+                return Ok(vec![
+                    self.library_proc("list->vector", scope)?,
+                    self.quasiquote(items, depth, scope)?,
+                ]
+                .to_expr());
+            }
+            template => return Ok(template),
+        };
+
+        if let (Some((keyword, name, operand)), Expr::EmptyList) =
+            (self.quasi_form(&items, scope), &tail)
+        {
+            let depth = match keyword {
+                Keyword::Quasiquote => depth + 1,
+                _ => depth - 1,
+            };
+            return match (keyword, depth) {
+                (Keyword::Unquote, 0) => self.expand_expr(operand, scope),
+                (Keyword::UnquoteSplicing, 0) => {
+                    Err(EvalErr::InvalidExpr(strip_syntax(items.to_expr())))
+                }
+                _ => Ok(vec![
+                    self.library_proc("list", scope)?,
+                    Expr::Quoted(Box::new(name.to_expr())),
+                    self.quasiquote(operand, depth, scope)?,
+                ]
+                .to_expr()),
+            };
+        }
+
+        // `(a . ,b)` reads the same as `(a unquote b)`, so a form that ends the items is the tail
+        // of the list rather than two more of its items.
+        let end = items.len().saturating_sub(2);
+        let tail = match tail {
+            Expr::EmptyList if end > 0 && self.quasi_form(&items[end..], scope).is_some() => {
+                self.quasiquote(items.split_off(end).to_expr(), depth, scope)?
+            }
+            Expr::EmptyList => Expr::EmptyList,
+            tail => self.quasiquote(tail, depth, scope)?,
+        };
+
+        items.into_iter().rev().try_fold(tail, |rest, item| {
+            // Inside a vector the forms are data, read as pairs rather than as calls.
+            let spliced = match &item {
+                Expr::Call(_) | Expr::Pair(_) => match dotted_parts(&item) {
+                    (inner, Expr::EmptyList) => match self.quasi_form(&inner, scope) {
+                        Some((Keyword::UnquoteSplicing, _, operand)) if depth == 1 => Some(operand),
+                        _ => None,
+                    },
+                    _ => None,
+                },
+                _ => None,
+            };
+            Ok(match spliced {
+                Some(operand) => vec![
+                    self.library_proc("append", scope)?,
+                    self.expand_expr(operand, scope)?,
+                    rest,
+                ]
+                .to_expr(),
+                None => vec![
+                    self.library_proc("cons", scope)?,
+                    self.quasiquote(item, depth, scope)?,
+                    rest,
+                ]
+                .to_expr(),
+            })
+        })
+    }
+
+    // Matches `(quasiquote x)`, `(unquote x)` and `(unquote-splicing x)` inside a template.
+    fn quasi_form(&self, items: &[Expr], scope: &Scope) -> Option<(Keyword, Symbol, Expr)> {
+        let [head, operand] = items else {
+            return None;
+        };
+        match self.resolve(head, scope)? {
+            Resolved::Keyword(
+                keyword @ (Keyword::Quasiquote | Keyword::Unquote | Keyword::UnquoteSplicing),
+            ) => Some((keyword, identifier_name(head)?, operand.clone())),
+            _ => None,
+        }
+    }
+
+    // A reference to a global procedure from code the expander generates, which must not be
+    // affected by local variables of the same name.
+    fn library_proc(&self, name: &str, scope: &Scope) -> Result<Expr, EvalErr> {
+        self.expand_reference(Alias::new(name.to_expr(), Scope::default()), scope)
+    }
+
+    // Clauses are handled like `cond`, with a final clause added to re-raise the condition to the
+    // next handler out when none of them apply.
+    fn expand_guard(&self, operands: IntoIter<Expr>, scope: &Scope) -> Result<Expr, EvalErr> {
+        let (spec, body) = operands.own_one_and_rest_or_else(|| {
+            EvalErr::InvalidArgs("'guard' expression. expected clauses and body")
+        })?;
+        let (identifier, clauses) = match spec {
+            Expr::Call(spec) => spec.into_iter().own_one_and_rest_or_else(|| {
+                EvalErr::InvalidArgs("'guard' expression. expected variable and clauses")
+            })?,
+            expr => return Err(EvalErr::TypeError("list", expr)),
+        };
+
+        // When no clause matches, the clauses call `reraise` to raise the object again from
+        // where it was first raised.
+        let clause_scope = scope.extend();
+        let name = self.bind_variable(identifier, &clause_scope)?;
+        let reraise = Alias::new("reraise".to_expr(), Scope::default());
+        let reraise_name = self.bind_variable(reraise.clone(), &clause_scope)?;
+
+        let else_ = Alias::new("else".to_expr(), Scope::default());
+        let mut clauses: Vec<Expr> = clauses.collect();
+        let has_else = matches!(
+            clauses.last(),
+            Some(Expr::Call(clause))
+                if self.resolve_head(clause, &clause_scope) == self.resolve(&else_, &clause_scope)
+        );
+        if !has_else {
+            clauses.push(vec![else_, vec![reraise].to_expr()].to_expr());
+        }
+        let cond = Alias::new("cond".to_expr(), Scope::default());
+        let clauses = [vec![cond], clauses].concat().to_expr();
+
+        // The body is a body like any other, so it runs as a procedure that is called straight
+        // away to give its definitions somewhere to go.
+        let body = self.expand_body(body.collect(), &scope.extend())?;
+        let body = vec![Lambda::new(Expr::EmptyList, body).to_expr().into_call()?].to_expr();
+
+        Guard::new(
+            name,
+            reraise_name,
+            self.expand_expr(clauses, &clause_scope)?,
+            vec![body],
+        )
+        .to_expr()
+        .into_call()
+    }
+}
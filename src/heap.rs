@@ -0,0 +1,191 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::parser::Expr;
+
+// Objects are still owned by reference counting, which frees everything that isn't caught up in
+// a cycle. Every pair, vector, hash table, record, procedure and enviroment is also registered
+// with the heap so that the cycles can be found and broken by a mark and sweep collection.
+//
+// Nothing has to register itself as a root. An object is a root when it has more strong
+// references than the other heap objects account for, since the rest must be held from outside the
+// heap: by the evaluator, a primitive part way through running, or the REPL. Anything that can't
+// be reached from a root is only being kept alive by a cycle, so it is cleared out, which drops
+// the references making up the cycle and lets the reference counts free it.
+//
+// Objects held by something the heap doesn't trace, like a captured continuation or a macro, look
+// to be held from outside and are kept as roots.
+pub trait Trace {
+    // Reports every heap object this one holds a strong reference to.
+    fn trace(&self, tracer: &mut Tracer);
+
+    // Drops everything this object holds. Only called once it can no longer be reached.
+    fn clear(&self);
+}
+
+#[derive(Default)]
+pub struct Tracer(Vec<usize>);
+
+impl Tracer {
+    pub fn object<T: ?Sized>(&mut self, rc: &Rc<T>) {
+        self.0.push(address(rc));
+    }
+
+    // Expressions held inline, like the items of a call, are searched for the objects inside them.
+    pub fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Pair(p) => self.object(p),
+            Expr::Vector(v) => self.object(v),
+            Expr::HashTable(t) => self.object(t),
+            Expr::Record(r) => self.object(r),
+            Expr::Proc(p) => self.object(p),
+            Expr::Call(items) => items.iter().for_each(|item| self.expr(item)),
+            Expr::Quoted(datum) => self.expr(datum),
+            _ => (),
+        }
+    }
+}
+
+fn address<T: ?Sized>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+// Collections are started by the evaluator once as many objects have been allocated since the
+// last one as were still alive after it, and never for fewer than this.
+const MIN_THRESHOLD: usize = 10_000;
+
+struct Heap {
+    objects: Vec<Weak<dyn Trace>>,
+    allocated: usize,
+    threshold: usize,
+    allocations: usize,
+    collections: usize,
+    collected: usize,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = RefCell::new(Heap {
+        objects: vec![],
+        allocated: 0,
+        threshold: MIN_THRESHOLD,
+        allocations: 0,
+        collections: 0,
+        collected: 0,
+    });
+}
+
+pub fn track<T: Trace + 'static>(rc: &Rc<T>) {
+    let weak: Weak<dyn Trace> = Rc::downgrade(rc) as Weak<T>;
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(weak);
+        heap.allocated += 1;
+        heap.allocations += 1;
+    });
+}
+
+pub fn collect_if_due() {
+    let due = HEAP.with(|heap| {
+        let heap = heap.borrow();
+        heap.allocated >= heap.threshold
+    });
+    if due {
+        collect();
+    }
+}
+
+// Runs a full collection, giving the number of objects that were found to be unreachable.
+pub fn collect() -> usize {
+    let objects = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.retain(|object| object.strong_count() > 0);
+        heap.objects
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<Rc<dyn Trace>>>()
+    });
+    let index = objects
+        .iter()
+        .enumerate()
+        .map(|(i, object)| (address(object), i))
+        .collect::<HashMap<usize, usize>>();
+    let children = objects
+        .iter()
+        .map(|object| {
+            let mut tracer = Tracer::default();
+            object.trace(&mut tracer);
+            tracer
+                .0
+                .iter()
+                .filter_map(|addr| index.get(addr).copied())
+                .collect::<Vec<usize>>()
+        })
+        .collect::<Vec<Vec<usize>>>();
+
+    // Takes away the references held from inside the heap, along with the one just taken above,
+    // leaving only those held from outside it.
+    let mut external = objects
+        .iter()
+        .map(|object| Rc::strong_count(object) - 1)
+        .collect::<Vec<usize>>();
+    for child in children.iter().flatten() {
+        external[*child] -= 1;
+    }
+
+    let mut marked = vec![false; objects.len()];
+    let mut pending = (0..objects.len())
+        .filter(|i| external[*i] > 0)
+        .collect::<Vec<usize>>();
+    while let Some(i) = pending.pop() {
+        if !marked[i] {
+            marked[i] = true;
+            pending.extend(children[i].iter().filter(|child| !marked[**child]));
+        }
+    }
+
+    let garbage = objects
+        .iter()
+        .zip(marked.iter())
+        .filter(|(_, marked)| !**marked)
+        .map(|(object, _)| object)
+        .collect::<Vec<&Rc<dyn Trace>>>();
+    garbage.iter().for_each(|object| object.clear());
+    let collected = garbage.len();
+    drop(garbage);
+    drop(objects);
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.retain(|object| object.strong_count() > 0);
+        heap.allocated = 0;
+        heap.threshold = heap.objects.len().max(MIN_THRESHOLD);
+        heap.collections += 1;
+        heap.collected += collected;
+    });
+    collected
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub objects: usize,
+    pub allocations: usize,
+    pub collections: usize,
+    pub collected: usize,
+}
+
+pub fn stats() -> Stats {
+    HEAP.with(|heap| {
+        let heap = heap.borrow();
+        Stats {
+            objects: heap
+                .objects
+                .iter()
+                .filter(|object| object.strong_count() > 0)
+                .count(),
+            allocations: heap.allocations,
+            collections: heap.collections,
+            collected: heap.collected,
+        }
+    })
+}
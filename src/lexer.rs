@@ -1,29 +1,34 @@
 use core::str::Chars;
 use std::iter::Peekable;
+use std::rc::Rc;
 
 use crate::error::EvalErr;
+use crate::number::Number;
+use crate::primitives::string::Str;
+use crate::symbol::Symbol;
 use crate::utils::SoftIter;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     LParen,
     RParen,
-    If,
-    And,
-    Or,
-    Define,
-    Let,
-    LetStar,
-    Lambda,
-    Cond,
-    Begin,
-    Assignment,
+    HashParen,
+    U8Paren,
     QuoteTick,
-    QuoteProc,
-    Number(f64),
+    Backquote,
+    Comma,
+    CommaAt,
+    Number(Number),
     Boolean(bool),
-    Str(String),    //TODO: String -> Rc<&str>?
-    Symbol(String), //TODO: String -> Rc<&str>?
+    Char(char),
+    Str(Rc<Str>), // shared so that string-set! is seen through every reference
+    Symbol(Symbol),
+}
+
+impl Token {
+    pub fn string(value: impl Into<String>) -> Token {
+        Token::Str(Rc::new(Str::new(value.into().chars().collect())))
+    }
 }
 
 pub type TokenRes<T> = Result<T, EvalErr>;
@@ -60,10 +65,7 @@ impl<'a> TokenStream<'a> {
                 self.0.next();
                 Some(Ok(Token::RParen))
             }
-            '#' => {
-                self.0.next();
-                Some(self.parse_bool())
-            }
+            '#' => Some(self.parse_hash()),
             '"' => {
                 self.0.next();
                 Some(self.parse_string())
@@ -72,20 +74,68 @@ impl<'a> TokenStream<'a> {
                 self.0.next();
                 Some(Ok(Token::QuoteTick))
             }
-            c if c.is_numeric() => Some(self.parse_number()),
-            _ => Some(self.parse_symbol()),
+            '`' => {
+                self.0.next();
+                Some(Ok(Token::Backquote))
+            }
+            ',' => {
+                self.0.next();
+                match self.0.next_if_eq(&'@') {
+                    Some(_) => Some(Ok(Token::CommaAt)),
+                    None => Some(Ok(Token::Comma)),
+                }
+            }
+            _ => Some(self.parse_atom()),
         }
     }
 
-    fn parse_bool(&mut self) -> TokenRes<Token> {
-        match self.0.next() {
-            Some('t') => Ok(Token::Boolean(true)),
-            Some('f') => Ok(Token::Boolean(false)),
-            Some(c) => Err(EvalErr::UnexpectedToken(c.to_string())),
-            None => Err(EvalErr::MalformedToken(
+    // Booleans, characters, the opening of a vector or bytevector, and numbers written with a radix
+    // or exactness prefix.
+    fn parse_hash(&mut self) -> TokenRes<Token> {
+        self.0.next();
+        if self.0.next_if_eq(&'\\').is_some() {
+            return self.parse_char();
+        }
+        if self.0.next_if_eq(&'(').is_some() {
+            return Ok(Token::HashParen);
+        }
+        let value: String = self.0.take_until(|c| !end_of_token(c)).collect();
+        if value == "u8" && self.0.next_if_eq(&'(').is_some() {
+            return Ok(Token::U8Paren);
+        }
+        match value.as_str() {
+            "t" | "true" => Ok(Token::Boolean(true)),
+            "f" | "false" => Ok(Token::Boolean(false)),
+            "" => Err(EvalErr::MalformedToken(
                 "expected charater indicating bool type",
             )),
+            _ => Number::parse(&format!("#{value}"))
+                .map(Token::Number)
+                .ok_or(EvalErr::UnexpectedToken(format!("#{value}"))),
+        }
+    }
+
+    // `#\a`, `#\space` or `#\x41`. The first character is taken as it is even when it would end a
+    // token, so that `#\(` and `#\ ` can be written.
+    fn parse_char(&mut self) -> TokenRes<Token> {
+        let first = self
+            .0
+            .next()
+            .ok_or(EvalErr::MalformedToken("expected character after #\\"))?;
+        let rest: String = self.0.take_until(|c| !end_of_token(c)).collect();
+        if rest.is_empty() {
+            return Ok(Token::Char(first));
         }
+
+        let name = format!("{first}{rest}");
+        let hex = || {
+            let digits = name.strip_prefix('x')?;
+            char::from_u32(u32::from_str_radix(digits, 16).ok()?)
+        };
+        char_from_name(&name)
+            .or_else(hex)
+            .map(Token::Char)
+            .ok_or(EvalErr::UnexpectedToken(format!("#\\{name}")))
     }
 
     fn parse_string(&mut self) -> TokenRes<Token> {
@@ -93,38 +143,22 @@ impl<'a> TokenStream<'a> {
         self.0
             .next() //consume remaining quote
             .ok_or(EvalErr::MalformedToken("unclosed string"))?;
-        Ok(Token::Str(value))
+        Ok(Token::Str(Rc::new(Str::literal(value.chars().collect()))))
     }
 
-    fn parse_symbol(&mut self) -> TokenRes<Token> {
+    // Keywords are lexed as plain symbols, syntax is resolved later by the expander so that it can
+    // be shadowed and extended by macros. Anything that reads as a number is one, and anything else
+    // that starts out like one, such as `5d` or `-1.2.3`, is malformed rather than a symbol.
+    fn parse_atom(&mut self) -> TokenRes<Token> {
         let value: String = self.0.take_until(|c| !end_of_token(c)).collect();
-
-        Ok(match value.as_str() {
-            "if" => Token::If,
-            "define" => Token::Define,
-            "lambda" => Token::Lambda,
-            "quote" => Token::QuoteProc,
-            "begin" => Token::Begin,
-            "let" => Token::Let,
-            "let*" => Token::LetStar,
-            "and" => Token::And,
-            "or" => Token::Or,
-            "cond" => Token::Cond,
-            "set!" => Token::Assignment,
-            _ => Token::Symbol(value),
-        })
-    }
-
-    fn parse_number(&mut self) -> TokenRes<Token> {
-        let err = EvalErr::MalformedToken("failed to parse number");
-        let value: String = self
-            .0
-            .take_until(|c| c.is_numeric() && !end_of_token(c))
-            .collect();
-
-        match self.0.peek() {
-            Some(c) if !c.is_numeric() && !end_of_token(c) => Err(err),
-            _ => Ok(Token::Number(value.parse().map_err(|_| err)?)),
+        if let Some(n) = Number::parse(&value) {
+            return Ok(Token::Number(n));
+        }
+        let unsigned = value.trim_start_matches(['+', '-']);
+        let unsigned = unsigned.strip_prefix('.').unwrap_or(unsigned);
+        match unsigned.starts_with(|c: char| c.is_ascii_digit()) {
+            true => Err(EvalErr::MalformedToken("failed to parse number")),
+            false => Ok(Token::Symbol(value.into())),
         }
     }
 
@@ -132,7 +166,7 @@ impl<'a> TokenStream<'a> {
         while self
             .consume_comment()?
             .peek()
-            .map_or(false, |c| c.is_whitespace())
+            .is_some_and(|c| c.is_whitespace())
         {
             self.0.next();
         }
@@ -160,7 +194,29 @@ fn end_of_token(c: &char) -> bool {
     c.is_whitespace() || c == &')' || c == &'(' || c == &';'
 }
 
+// Characters that are written by name, as in `#\space`.
+const CHAR_NAMES: [(&str, char); 9] = [
+    ("alarm", '\u{7}'),
+    ("backspace", '\u{8}'),
+    ("delete", '\u{7f}'),
+    ("escape", '\u{1b}'),
+    ("newline", '\n'),
+    ("null", '\0'),
+    ("return", '\r'),
+    ("space", ' '),
+    ("tab", '\t'),
+];
+
+fn char_from_name(name: &str) -> Option<char> {
+    CHAR_NAMES.iter().find(|(n, _)| *n == name).map(|(_, c)| *c)
+}
+
+pub fn char_name(c: char) -> Option<&'static str> {
+    CHAR_NAMES.iter().find(|(_, ch)| *ch == c).map(|(n, _)| *n)
+}
+
 #[cfg(test)]
+#[allow(clippy::needless_borrow, clippy::useless_format)]
 mod test {
     use super::*;
 
@@ -173,17 +229,17 @@ mod test {
         let scm = format!(r##"  (+ 1(+ 2  3)  2 "lolz")"omg"   (#t #f)"##);
         let res = vec![
             Token::LParen,
-            Token::Symbol("+".to_string()),
-            Token::Number(1.0),
+            Token::Symbol("+".into()),
+            Token::Number(1.into()),
             Token::LParen,
-            Token::Symbol("+".to_string()),
-            Token::Number(2.0),
-            Token::Number(3.0),
+            Token::Symbol("+".into()),
+            Token::Number(2.into()),
+            Token::Number(3.into()),
             Token::RParen,
-            Token::Number(2.0),
-            Token::Str("lolz".to_string()),
+            Token::Number(2.into()),
+            Token::string("lolz"),
             Token::RParen,
-            Token::Str("omg".to_string()),
+            Token::string("omg"),
             Token::LParen,
             Token::Boolean(true),
             Token::Boolean(false),
@@ -193,6 +249,23 @@ mod test {
         assert_eq!(tokens, res);
     }
 
+    #[test]
+    fn tokenize_quasiquote() {
+        let scm = "`(a ,b ,@c)";
+        let res = vec![
+            Token::Backquote,
+            Token::LParen,
+            Token::Symbol("a".into()),
+            Token::Comma,
+            Token::Symbol("b".into()),
+            Token::CommaAt,
+            Token::Symbol("c".into()),
+            Token::RParen,
+        ];
+        let tokens = tokenize(scm).unwrap();
+        assert_eq!(tokens, res);
+    }
+
     #[test]
     fn tokenise_empty() {
         let scm = "";
@@ -204,7 +277,7 @@ mod test {
     #[test]
     fn tokenise_symbol() {
         let scm = "yoda";
-        let res: Vec<Token> = vec![Token::Symbol("yoda".to_string())];
+        let res: Vec<Token> = vec![Token::Symbol("yoda".into())];
         let tokens = tokenize(&scm).unwrap();
         assert_eq!(tokens, res);
     }
@@ -212,7 +285,7 @@ mod test {
     #[test]
     fn tokenise_number() {
         let scm = " 123";
-        let res: Vec<Token> = vec![Token::Number(123.0)];
+        let res: Vec<Token> = vec![Token::Number(123.into())];
         let tokens = tokenize(&scm).unwrap();
         assert_eq!(tokens, res);
     }
@@ -220,11 +293,84 @@ mod test {
     #[test]
     fn tokenise_parse_fail() {
         let scm = " 123)";
-        let res: Vec<Token> = vec![Token::Number(123.0), Token::RParen];
+        let res: Vec<Token> = vec![Token::Number(123.into()), Token::RParen];
         let tokens = tokenize(&scm).unwrap();
         assert_eq!(tokens, res);
     }
 
+    #[test]
+    fn tokenise_number_literals() {
+        let scm = "-5 +5 2.5 .5 1e3 #x1F #b-1010 #o17 1/2 #e1.25 #i3/4 +inf.0 - ... -> +a";
+        let res: Vec<Token> = vec![
+            Token::Number((-5).into()),
+            Token::Number(5.into()),
+            Token::Number(2.5.into()),
+            Token::Number(0.5.into()),
+            Token::Number(1000.0.into()),
+            Token::Number(31.into()),
+            Token::Number((-10).into()),
+            Token::Number(15.into()),
+            Token::Number(Number::parse("2/4").unwrap()),
+            Token::Number(Number::parse("5/4").unwrap()),
+            Token::Number(0.75.into()),
+            Token::Number(f64::INFINITY.into()),
+            Token::Symbol("-".into()),
+            Token::Symbol("...".into()),
+            Token::Symbol("->".into()),
+            Token::Symbol("+a".into()),
+        ];
+        let tokens = tokenize(scm).unwrap();
+        assert_eq!(tokens, res);
+    }
+
+    #[test]
+    fn tokenise_chars() {
+        let scm = r"(#\a #\space #\x41 #\( #\) #\newline #\\)";
+        let res: Vec<Token> = vec![
+            Token::LParen,
+            Token::Char('a'),
+            Token::Char(' '),
+            Token::Char('A'),
+            Token::Char('('),
+            Token::Char(')'),
+            Token::Char('\n'),
+            Token::Char('\\'),
+            Token::RParen,
+        ];
+        let tokens = tokenize(scm).unwrap();
+        assert_eq!(tokens, res);
+    }
+
+    #[test]
+    fn tokenise_vector() {
+        let scm = "#(1 #t) #u8(255)";
+        let res: Vec<Token> = vec![
+            Token::HashParen,
+            Token::Number(1.into()),
+            Token::Boolean(true),
+            Token::RParen,
+            Token::U8Paren,
+            Token::Number(255.into()),
+            Token::RParen,
+        ];
+        let tokens = tokenize(scm).unwrap();
+        assert_eq!(tokens, res);
+    }
+
+    #[test]
+    #[should_panic]
+    fn unknown_char_name() {
+        let scm = r"#\nope";
+        tokenize(scm).unwrap();
+    }
+
+    #[test]
+    #[should_panic]
+    fn parse_ratio_failure() {
+        let scm = "1/0";
+        tokenize(scm).unwrap();
+    }
+
     #[test]
     #[should_panic]
     fn tokenise_unclosed_string() {
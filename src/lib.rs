@@ -1,11 +1,20 @@
+pub mod analyzer;
+pub mod compiler;
+pub mod continuation;
 pub mod enviroment;
 pub mod error;
 pub mod evaluator;
+pub mod expander;
+pub mod heap;
 pub mod lexer;
+pub mod number;
 pub mod parser;
 pub mod primitives;
 pub mod print;
 pub mod procedure;
 pub mod repl;
 pub mod special_form;
+pub mod symbol;
+pub mod syntax_rules;
 pub mod utils;
+pub mod vm;
@@ -4,7 +4,7 @@ use std::fs;
 use std::process;
 
 use schemeish::enviroment::EnvRef;
-use schemeish::evaluator;
+use schemeish::evaluator::Engine;
 use schemeish::lexer::TokenStream;
 use schemeish::parser::Parser;
 use schemeish::repl::Repl;
@@ -17,18 +17,18 @@ enum Runtime {
 fn main() {
     let mut args = env::args();
 
-    let runtime = read(&mut args).unwrap_or_else(|err| {
+    let (runtime, engine) = read(&mut args).unwrap_or_else(|err| {
         eprintln!("{err}");
         process::exit(1);
     });
 
     match runtime {
-        Runtime::File(f) => run_from_file(&f),
-        Runtime::Repl => Repl::default().run(),
+        Runtime::File(f) => run_from_file(&f, engine),
+        Runtime::Repl => Repl::with_engine(engine).run(),
     }
 }
 
-fn run_from_file(file: &str) {
+fn run_from_file(file: &str, engine: Engine) {
     let tokens = TokenStream::new(file)
         .collect_tokens()
         .unwrap_or_else(|err| {
@@ -43,55 +43,84 @@ fn run_from_file(file: &str) {
 
     let global = EnvRef::global();
 
-    global.import_prelude().unwrap_or_else(|err| {
+    global.import_prelude(engine).unwrap_or_else(|err| {
         eprintln!("{err}");
         process::exit(1);
     });
 
     for exp in exprs.into_iter() {
-        match evaluator::eval(exp, &global) {
+        match engine.eval(exp, &global) {
             Ok(_) => (),
             Err(err) => eprintln!("{err}"),
         }
     }
 }
 
-fn read<T>(args: &mut T) -> Result<Runtime, Box<dyn Error>>
+// Code runs on the tree walking evaluator unless `--bytecode` is given.
+fn read<T>(args: &mut T) -> Result<(Runtime, Engine), Box<dyn Error>>
 where
     T: Iterator<Item = String>,
 {
     args.next();
 
-    let Some(path) = args.next() else {
-        return Ok(Runtime::Repl);
+    let mut engine = Engine::Tree;
+    let mut path = None;
+    for arg in args {
+        match arg.as_str() {
+            "--bytecode" => engine = Engine::Bytecode,
+            flag if flag.starts_with("--") => return Err(format!("unknown option {flag}").into()),
+            _ if path.is_some() => return Err(format!("unexpected argument {arg}").into()),
+            _ => path = Some(arg),
+        }
+    }
+
+    let Some(path) = path else {
+        return Ok((Runtime::Repl, engine));
     };
 
-    Ok(Runtime::File(fs::read_to_string(path)?))
+    Ok((Runtime::File(fs::read_to_string(path)?), engine))
 }
 
 #[cfg(test)]
+#[allow(
+    clippy::get_first,
+    clippy::match_like_matches_macro,
+    clippy::needless_borrow
+)]
 mod test {
     use core::panic;
+    use std::cell::Cell;
 
     use schemeish::{
         error::EvalErr,
-        lexer::Token::{Boolean, Number},
+        lexer::Token::{self, Boolean, Number},
         parser::Expr::{self, Atom, EmptyList},
         primitives::pair::Pair,
+        print::Printable,
         utils::ToExpr,
     };
 
     use super::*;
 
+    // The engine the tests on this thread run with, so every test can be run again on the VM.
+    thread_local! {
+        static ENGINE: Cell<Engine> = const { Cell::new(Engine::Tree) };
+    }
+
     pub fn eval_test(scm: &str) -> Vec<Expr> {
         let exprs = Parser::new(TokenStream::new(scm).collect_tokens().unwrap())
             .parse()
             .unwrap();
+        let engine = ENGINE.get();
         let global = EnvRef::global();
-        global.import_prelude().unwrap();
+        global.import_prelude(engine).unwrap();
         exprs
             .into_iter()
-            .map(|e| evaluator::eval(e, &global).unwrap_or_else(|err| panic!("{err}")))
+            .map(|e| {
+                engine
+                    .eval(e, &global)
+                    .unwrap_or_else(|err| panic!("{err}"))
+            })
             .collect()
     }
 
@@ -99,12 +128,10 @@ mod test {
         let exprs = Parser::new(TokenStream::new(scm).collect_tokens().unwrap())
             .parse()
             .unwrap();
+        let engine = ENGINE.get();
         let global = EnvRef::global();
-        global.import_prelude().unwrap();
-        exprs
-            .into_iter()
-            .map(|e| evaluator::eval(e, &global))
-            .collect()
+        global.import_prelude(engine).unwrap();
+        exprs.into_iter().map(|e| engine.eval(e, &global)).collect()
     }
 
     #[test]
@@ -114,7 +141,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(0).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(27.0)));
+        assert_eq!(res, Atom(Number(27.into())));
     }
 
     #[test]
@@ -130,8 +157,8 @@ mod test {
         let evalulated = eval_test(scm);
         let res1 = evalulated.get(1).unwrap().to_owned();
         let res2 = evalulated.get(2).unwrap().to_owned();
-        assert_eq!(res1, Atom(Number(20.0)));
-        assert_eq!(res2, Atom(Number(10.0)));
+        assert_eq!(res1, Atom(Number(20.into())));
+        assert_eq!(res2, Atom(Number(10.into())));
     }
 
     #[test]
@@ -140,7 +167,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(0).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(6.0)));
+        assert_eq!(res, Atom(Number(6.into())));
     }
 
     #[test]
@@ -154,7 +181,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(0).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(7.0)));
+        assert_eq!(res, Atom(Number(7.into())));
     }
 
     #[test]
@@ -170,7 +197,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(1).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(14.0)));
+        assert_eq!(res, Atom(Number(14.into())));
     }
 
     #[test]
@@ -189,7 +216,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(2).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(3628800.0)));
+        assert_eq!(res, Atom(Number(3628800.into())));
     }
 
     #[test]
@@ -204,7 +231,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(2).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(149.0)));
+        assert_eq!(res, Atom(Number(149.into())));
     }
 
     #[test]
@@ -224,10 +251,10 @@ mod test {
         assert_eq!(
             res,
             Pair::new(
-                Atom(Number(2.0)),
+                Atom(Number(2.into())),
                 Pair::new(
-                    Atom(Number(4.0)),
-                    Pair::new(Atom(Number(6.0)), EmptyList).to_expr()
+                    Atom(Number(4.into())),
+                    Pair::new(Atom(Number(6.into())), EmptyList).to_expr()
                 )
                 .to_expr()
             )
@@ -248,7 +275,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(3).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(1.0)));
+        assert_eq!(res, Atom(Number(1.into())));
     }
 
     #[test]
@@ -293,7 +320,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(0).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(1.0)));
+        assert_eq!(res, Atom(Number(1.into())));
     }
 
     #[test]
@@ -311,7 +338,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(0).unwrap().to_owned();
-        assert_eq!(res, Pair::new(Atom(Number(1.0)), EmptyList).to_expr());
+        assert_eq!(res, Pair::new(Atom(Number(1.into())), EmptyList).to_expr());
     }
 
     #[test]
@@ -324,7 +351,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(0).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(10.0)));
+        assert_eq!(res, Atom(Number(70.into())));
     }
 
     #[test]
@@ -351,7 +378,7 @@ mod test {
 
         let evalulated = eval_test(scm);
         let res = evalulated.get(5).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(39.0)));
+        assert_eq!(res, Atom(Number(39.into())));
     }
 
     #[test]
@@ -456,11 +483,10 @@ mod test {
         assert_eq!(
             res,
             Pair::new(
-                1.0.to_expr(),
+                1.to_expr(),
                 Pair::new(
-                    4.0.to_expr(),
-                    Pair::new(5.0.to_expr(), Pair::new(6.0.to_expr(), EmptyList).to_expr())
-                        .to_expr()
+                    4.to_expr(),
+                    Pair::new(5.to_expr(), Pair::new(6.to_expr(), EmptyList).to_expr()).to_expr()
                 )
                 .to_expr()
             )
@@ -477,6 +503,55 @@ mod test {
         eval_test(scm);
     }
 
+    #[test]
+    fn cyclic_lists() {
+        let scm = "
+            (define p (list 1 2))
+            (set-cdr! (cdr p) p)
+            p
+            (define q (list 1 2 3))
+            (set-car! (cdr q) q)
+            q
+            (define v (vector 1 2))
+            (vector-set! v 0 v)
+            v
+            (list->vector p)
+            (apply + p)
+            (append p '(3))";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(2), "#0=(1 2 . #0#)");
+        assert_eq!(printed(5), "#0='(1 #0# 3)");
+        assert_eq!(printed(8), "#0=#(#0# 2)");
+        for i in 9..12 {
+            assert!(matches!(
+                evalulated.get(i).unwrap(),
+                Err(EvalErr::TypeError("proper list", _))
+            ));
+        }
+    }
+
+    #[test]
+    fn improper_lists() {
+        let scm = "
+            (cons 1 2)
+            '(1 2 . 3)
+            (cons 1 (cons '(2) (cons 3 #(4))))
+            (list (cons 1 2) '(3 . 4))
+            (define r (list 1 2 3))
+            (set-cdr! (cdr (cdr r)) (cdr r))
+            r";
+
+        let evalulated = eval_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().printable();
+        assert_eq!(printed(0), "(1 . 2)");
+        assert_eq!(printed(1), "(1 2 . 3)");
+        assert_eq!(printed(2), "(1 '(2) 3 . #(4))");
+        assert_eq!(printed(3), "'((1 . 2) (3 . 4))");
+        assert_eq!(printed(6), "(1 . #0=(2 3 . #0#))");
+    }
+
     #[test]
     fn missplaced_else() {
         let scm = "(cond (#f 1) (else (list 1 2 3)) (#t 3))";
@@ -495,15 +570,1409 @@ mod test {
         }
     }
 
+    #[test]
+    fn tail_call_loop() {
+        let scm = "
+            (define (loop n)
+              (if (= n 0)
+                  'done
+                  (loop (- n 1))))
+            (loop 100000)";
+
+        let evalulated = eval_test(scm);
+        let res = evalulated.get(1).unwrap().to_owned();
+        assert_eq!(res, "done".to_expr());
+    }
+
+    #[test]
+    fn tail_call_through_derived_forms() {
+        let scm = "
+            (define (even-loop? n)
+              (cond ((= n 0) #t)
+                    (else (let ((m (- n 1)))
+                            (begin (odd-loop? m))))))
+            (define (odd-loop? n)
+              (and (not (= n 0)) (or #f (even-loop? (- n 1)))))
+            (even-loop? 100000)
+            (let ((x 1)) (odd-loop? 100001))";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(evalulated.get(2).unwrap().to_owned(), Atom(Boolean(true)));
+        assert_eq!(evalulated.get(3).unwrap().to_owned(), Atom(Boolean(true)));
+    }
+
+    #[test]
+    fn call_cc_early_exit() {
+        let scm = "
+            (define (find-first pred lst)
+              (call/cc
+                (lambda (return)
+                  (foldl (lambda (acc x) (if (pred x) (return x) acc)) #f lst))))
+            (find-first (lambda (x) (> x 2)) (list 1 2 3 4 5))
+            (+ 1 (call-with-current-continuation (lambda (k) (+ 10 (k 41)))))";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(
+            evalulated.get(1).unwrap().to_owned(),
+            Atom(Number(3.into()))
+        );
+        assert_eq!(
+            evalulated.get(2).unwrap().to_owned(),
+            Atom(Number(42.into()))
+        );
+    }
+
+    #[test]
+    fn call_cc_reentry() {
+        let scm = "
+            (define saved #f)
+            (define count 0)
+            (+ 100 (call/cc (lambda (k) (set! saved k) 0)))
+            (set! count (+ count 1))
+            (if (< count 3) (saved count) count)";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(
+            evalulated.get(2).unwrap().to_owned(),
+            Atom(Number(100.into()))
+        );
+        assert_eq!(
+            evalulated.get(4).unwrap().to_owned(),
+            Atom(Number(101.into()))
+        );
+    }
+
+    #[test]
+    fn call_cc_generator() {
+        let scm = "
+            (define (make-generator lst)
+              (define return #f)
+              (define resume #f)
+              (define started #f)
+              (define (walk lst)
+                (if (null? lst)
+                    (return 'done)
+                    (begin
+                      (call/cc (lambda (k)
+                                 (set! resume k)
+                                 (return (car lst))))
+                      (walk (cdr lst)))))
+              (lambda ()
+                (call/cc (lambda (r)
+                           (set! return r)
+                           (if started
+                               (resume #f)
+                               (begin (set! started #t) (walk lst)))))))
+            (define next (make-generator (list 1 2 3)))
+            (list (next) (next) (next) (next))";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(
+            evalulated.get(2).unwrap().to_owned(),
+            Pair::new(
+                1.to_expr(),
+                Pair::new(
+                    2.to_expr(),
+                    Pair::new(
+                        3.to_expr(),
+                        Pair::new("done".to_expr(), EmptyList).to_expr()
+                    )
+                    .to_expr()
+                )
+                .to_expr()
+            )
+            .to_expr()
+        );
+    }
+
+    #[test]
+    fn dynamic_wind_normal_exit() {
+        let scm = "
+            (define trail '())
+            (define (note x) (set! trail (cons x trail)))
+            (dynamic-wind
+              (lambda () (note 'before))
+              (lambda () (note 'during) 'result)
+              (lambda () (note 'after)))
+            trail";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(evalulated.get(2).unwrap().to_owned(), "result".to_expr());
+        assert_eq!(
+            evalulated.get(3).unwrap().printable(),
+            "'(after during before)"
+        );
+    }
+
+    #[test]
+    fn dynamic_wind_continuation_exit_and_reentry() {
+        let scm = "
+            (define trail '())
+            (define (note x) (set! trail (cons x trail)))
+            (define reenter #f)
+            (call/cc
+              (lambda (escape)
+                (dynamic-wind
+                  (lambda () (note 'in))
+                  (lambda ()
+                    (call/cc (lambda (k) (set! reenter k)))
+                    (escape 'escaped))
+                  (lambda () (note 'out)))))
+            (define count 0)
+            (if (= count 0) (begin (set! count 1) (reenter #f)) count)
+            trail";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(evalulated.get(3).unwrap().to_owned(), "escaped".to_expr());
+        assert_eq!(evalulated.get(5).unwrap().to_owned(), "escaped".to_expr());
+        assert_eq!(evalulated.get(6).unwrap().printable(), "'(out in out in)");
+    }
+
+    #[test]
+    fn dynamic_wind_error_exit() {
+        let scm = r#"
+            (define trail '())
+            (dynamic-wind
+              (lambda () (set! trail (cons 'before trail)))
+              (lambda () (error "boom"))
+              (lambda () (set! trail (cons 'after trail))))
+            trail"#;
+
+        let evalulated = eval_err_test(scm);
+        assert!(matches!(
+            evalulated.get(1).unwrap(),
+            Err(EvalErr::RuntimeException(_, _))
+        ));
+        assert_eq!(
+            evalulated.get(2).unwrap().as_ref().unwrap().printable(),
+            "'(after before)"
+        );
+    }
+
+    #[test]
+    fn dynamic_wind_raise_during_exit() {
+        let scm = "
+            (define trail '())
+            (guard (e (#t (list e trail)))
+              (call/cc
+                (lambda (k)
+                  (dynamic-wind
+                    (lambda () (set! trail (cons 'in trail)))
+                    (lambda ()
+                      (dynamic-wind
+                        (lambda () #t)
+                        (lambda () (k 'escaped))
+                        (lambda () (raise 'inner-after))))
+                    (lambda () (set! trail (cons 'out trail)))))))
+            (define handled '())
+            (call/cc
+              (lambda (k)
+                (with-exception-handler
+                  (lambda (e) (k (cons e handled)))
+                  (lambda ()
+                    (call/cc
+                      (lambda (escape)
+                        (dynamic-wind
+                          (lambda () #t)
+                          (lambda ()
+                            (with-exception-handler
+                              (lambda (e) (set! handled (cons 'inner handled)) 0)
+                              (lambda ()
+                                (dynamic-wind
+                                  (lambda () #t)
+                                  (lambda () (escape 'gone))
+                                  (lambda () (raise 'after))))))
+                          (lambda () #t))))))))";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(
+            evalulated.get(1).unwrap().printable(),
+            "'(inner-after '(out in))"
+        );
+        assert_eq!(evalulated.get(3).unwrap().printable(), "'(after inner)");
+    }
+
+    #[test]
+    fn guard_error_objects() {
+        let scm = r#"
+            (guard (e ((error-object? e) (error-object-message e)))
+              (error "bad thing" 1 2))
+            (guard (e ((error-object? e) (error-object-irritants e)))
+              (error "bad thing" 1 2))
+            (guard (e ((symbol? e) e))
+              (raise 'oops))
+            (guard (e ((error-object? e) (error-object-irritants e)))
+              (+ 1 "a"))
+            (guard (e (#t (error-object-message e)))
+              undefined-thing)
+            (guard (e ((string? e) 'outer))
+              (guard (e ((symbol? e) 'inner))
+                (raise "str")))"#;
+
+        let evalulated = eval_test(scm);
+        assert_eq!(
+            evalulated.first().unwrap().to_owned(),
+            Atom(Token::string("bad thing"))
+        );
+        assert_eq!(evalulated.get(1).unwrap().printable(), "'(1 2)");
+        assert_eq!(evalulated.get(2).unwrap().to_owned(), "oops".to_expr());
+        assert_eq!(evalulated.get(3).unwrap().printable(), r#"'("a")"#);
+        assert_eq!(
+            evalulated.get(4).unwrap().to_owned(),
+            Atom(Token::string("accessing unbound variable undefined-thing"))
+        );
+        assert_eq!(evalulated.get(5).unwrap().to_owned(), "outer".to_expr());
+    }
+
+    #[test]
+    fn exception_handlers() {
+        let scm = "
+            (with-exception-handler
+              (lambda (c) 42)
+              (lambda () (+ (raise-continuable 'oops) 1)))
+            (call/cc
+              (lambda (k)
+                (with-exception-handler
+                  (lambda (e) (k (list 'caught e)))
+                  (lambda () (raise 'boom)))))
+            (define trail '())
+            (guard (e (#t trail))
+              (dynamic-wind
+                (lambda () #t)
+                (lambda () (raise 'fail))
+                (lambda () (set! trail (cons 'after trail)))))";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(
+            evalulated.first().unwrap().to_owned(),
+            Atom(Number(43.into()))
+        );
+        assert_eq!(evalulated.get(1).unwrap().printable(), "'(caught boom)");
+        assert_eq!(evalulated.get(3).unwrap().printable(), "'(after)");
+    }
+
+    #[test]
+    fn guard_reraise() {
+        let scm = "
+            (with-exception-handler
+              (lambda (e) 42)
+              (lambda () (+ 1 (guard (e (#f 0)) (raise 'x)))))
+            (with-exception-handler
+              (lambda (e) 42)
+              (lambda () (+ 1 (guard (e (#f 0)) (raise-continuable 'x)))))
+            (define trail '())
+            (guard (e (#t (cons e trail)))
+              (guard (e ((string? e) 'inner))
+                (dynamic-wind
+                  (lambda () (set! trail (cons 'in trail)))
+                  (lambda () (raise 'sym))
+                  (lambda () (set! trail (cons 'out trail))))))
+            (guard (e (#t e))
+              (define x 1)
+              (define (f) (+ x 1))
+              (f))
+            (define calls 0)
+            (guard (e (#t calls))
+              (with-exception-handler
+                (lambda (e) (set! calls (+ calls 1)) 'ignored)
+                (lambda () (guard (e (#f 0)) (raise 'x)))))";
+
+        let evalulated = eval_err_test(scm);
+        assert!(matches!(
+            evalulated.first().unwrap(),
+            Err(EvalErr::Raised(_))
+        ));
+        assert_eq!(
+            evalulated.get(1).unwrap().as_ref().unwrap().to_owned(),
+            Atom(Number(43.into()))
+        );
+        assert_eq!(
+            evalulated.get(3).unwrap().as_ref().unwrap().printable(),
+            "'(sym out in out in)"
+        );
+        assert_eq!(
+            evalulated.get(4).unwrap().as_ref().unwrap().to_owned(),
+            Atom(Number(2.into()))
+        );
+        assert_eq!(
+            evalulated.get(6).unwrap().as_ref().unwrap().to_owned(),
+            Atom(Number(1.into()))
+        );
+    }
+
+    #[test]
+    fn uncaught_exceptions() {
+        let scm = "
+            (raise 'unhandled)
+            (with-exception-handler
+              (lambda (e) 'ignored)
+              (lambda () (car 1)))
+            (guard (e ((string? e) e))
+              (undefined-proc))";
+
+        let evalulated = eval_err_test(scm);
+        assert_eq!(
+            evalulated.first().unwrap().to_owned(),
+            Err(EvalErr::Raised("unhandled".to_expr()))
+        );
+        assert!(matches!(
+            evalulated.get(1).unwrap(),
+            Err(EvalErr::TypeError(_, _))
+        ));
+        assert!(matches!(
+            evalulated.get(2).unwrap(),
+            Err(EvalErr::UnboundVar(_))
+        ));
+    }
+
+    #[test]
+    fn syntax_rules_macros() {
+        let scm = "
+            (define-syntax while
+              (syntax-rules ()
+                ((_ c body ...)
+                 (let ((loop #f))
+                   (set! loop (lambda () (if c (begin body ... (loop)) #f)))
+                   (loop)))))
+            (define-syntax my-or
+              (syntax-rules ()
+                ((_) #f)
+                ((_ e) e)
+                ((_ e r ...) (let ((t e)) (if t t (my-or r ...))))))
+            (define-syntax for
+              (syntax-rules (in)
+                ((_ x in lst body ...) (map (lambda (x) body ...) lst))))
+            (define-syntax flatten
+              (syntax-rules ()
+                ((_ (a b ...) ...) '(a ... b ... ...))))
+            (define i 0)
+            (define total 0)
+            (while (< i 5) (set! total (+ total i)) (set! i (+ i 1)))
+            total
+            (my-or #f #f 3)
+            (for x in '(1 2 3) (* x x))
+            (flatten (1 2 3) (4 5))";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(evalulated.get(7).unwrap(), &Atom(Number(10.into())));
+        assert_eq!(evalulated.get(8).unwrap(), &Atom(Number(3.into())));
+        assert_eq!(evalulated.get(9).unwrap().printable(), "'(1 4 9)");
+        assert_eq!(evalulated.get(10).unwrap().printable(), "'(1 4 2 3 5)");
+    }
+
+    #[test]
+    fn macro_hygiene() {
+        let scm = "
+            (define-syntax swap!
+              (syntax-rules ()
+                ((_ a b) (let ((tmp a)) (set! a b) (set! b tmp)))))
+            (define-syntax my-or
+              (syntax-rules ()
+                ((_ a b) (let ((t a)) (if t t b)))))
+            (define-syntax my-if
+              (syntax-rules ()
+                ((_ c a b) (cond (c a) (else b)))))
+            (define-syntax first
+              (syntax-rules ()
+                ((_ ls) (car ls))))
+            (define tmp 1)
+            (define other 2)
+            (swap! tmp other)
+            (list tmp other)
+            (define t 5)
+            (my-or #f t)
+            (let ((else #f)) (my-if #f 1 2))
+            (let ((if list)) (my-if #t 1 2))
+            (let ((car cdr)) (first '(1 2)))
+            (define x 10)
+            (define-syntax set-x
+              (syntax-rules ()
+                ((_ v) (set! x v))))
+            (let ((x 20)) (set-x 30) x)
+            x";
+
+        let evalulated = eval_test(scm);
+        assert_eq!(evalulated.get(7).unwrap().printable(), "'(2 1)");
+        assert_eq!(evalulated.get(9).unwrap(), &Atom(Number(5.into())));
+        assert_eq!(evalulated.get(10).unwrap(), &Atom(Number(2.into())));
+        assert_eq!(evalulated.get(11).unwrap(), &Atom(Number(1.into())));
+        assert_eq!(evalulated.get(12).unwrap(), &Atom(Number(1.into())));
+        assert_eq!(evalulated.get(15).unwrap(), &Atom(Number(20.into())));
+        assert_eq!(evalulated.get(16).unwrap(), &Atom(Number(30.into())));
+    }
+
+    #[test]
+    fn local_macros() {
+        let scm = "
+            (define (f x)
+              (define-syntax twice
+                (syntax-rules ()
+                  ((_ e) (begin e e))))
+              (define count 0)
+              (twice (set! count (+ count x)))
+              count)
+            (f 3)
+            (let ((x 'outer))
+              (let-syntax ((get-x (syntax-rules () ((_) x))))
+                (let ((x 'inner))
+                  (get-x))))
+            (letrec-syntax
+                ((my-and (syntax-rules ()
+                           ((_) #t)
+                           ((_ e) e)
+                           ((_ e r ...) (if e (my-and r ...) #f)))))
+              (my-and #t #t 'done))
+            (let-syntax ((bad (syntax-rules () ((_ x) x))))
+              (bad))";
+
+        let evalulated = eval_err_test(scm);
+        assert_eq!(evalulated.get(1).unwrap(), &Ok(Atom(Number(6.into()))));
+        assert_eq!(evalulated.get(2).unwrap(), &Ok("outer".to_expr()));
+        assert_eq!(evalulated.get(3).unwrap(), &Ok("done".to_expr()));
+        assert!(matches!(
+            evalulated.get(4).unwrap(),
+            Err(EvalErr::InvalidExpr(_))
+        ));
+    }
+
+    #[test]
+    fn quasiquote() {
+        let scm = "
+            (define x 2)
+            (define ls '(3 4))
+            `(1 ,x ,@ls 5)
+            `(1 ,@'() (nested ,(+ x 1)))
+            (quasiquote (a (unquote x) (unquote-splicing ls)))
+            `(a `(b ,(c ,x)))
+            `(config (name ,(car ls)) '(quoted ,x))
+            (let ((cons list)) `(,x))
+            `,@ls
+            `(1 . ,x)
+            `(1 ,@ls . 5)
+            `(0 ,x . ,ls)
+            `(1 . ,@ls)";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(2), "'(1 2 3 4 5)");
+        assert_eq!(printed(4), "'(a 2 3 4)");
+        assert_eq!(printed(3), "'(1 '(nested 3))");
+        assert_eq!(printed(5), "'(a '(quasiquote '(b '(unquote '(c 2)))))");
+        assert_eq!(printed(6), "'(config '(name 3) '(quote '(quoted 2)))");
+        assert_eq!(printed(7), "'(2)");
+        assert!(matches!(
+            evalulated.get(8).unwrap(),
+            Err(EvalErr::InvalidExpr(_))
+        ));
+        assert_eq!(printed(9), "(1 . 2)");
+        assert_eq!(printed(10), "(1 3 4 . 5)");
+        assert_eq!(printed(11), "'(0 2 3 4)");
+        assert!(matches!(
+            evalulated.get(12).unwrap(),
+            Err(EvalErr::InvalidExpr(_))
+        ));
+    }
+
+    #[test]
+    fn rest_params() {
+        let scm = "
+            (define (count . args) (length args))
+            (define (tagged tag . items) (cons tag items))
+            ((lambda args args) 1 2 3)
+            (count)
+            (count 1 2 3)
+            (tagged 'a 1 2)
+            (tagged 'a)
+            ((compose (lambda (x) (* x 2)) +) 1 2 3)
+            (define (printf fmt . args) (apply list fmt args))
+            (printf 'x 1 2)
+            (tagged)
+            '(1 . 2)
+            '(1 2 . (3 4))";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(2), "'(1 2 3)");
+        assert_eq!(printed(3), "0");
+        assert_eq!(printed(4), "3");
+        assert_eq!(printed(5), "'(a 1 2)");
+        assert_eq!(printed(6), "'(a)");
+        assert_eq!(printed(7), "12");
+        assert_eq!(printed(9), "'(x 1 2)");
+        assert!(matches!(
+            evalulated.get(10).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert_eq!(printed(11), "(1 . 2)");
+        assert_eq!(printed(12), "'(1 2 3 4)");
+    }
+
+    #[test]
+    fn dotted_macro_patterns() {
+        let scm = "
+            (define-syntax my-list
+              (syntax-rules ()
+                ((_ first . rest) (cons first 'rest))))
+            (my-list 1 2 3)
+            (my-list 1)
+            (define-syntax pair-up
+              (syntax-rules ()
+                ((_ (a . b) ...) '((b . a) ...))))
+            (pair-up (1 . 2) (3 4))";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(1), "'(1 2 3)");
+        assert_eq!(printed(2), "'(1)");
+        assert_eq!(printed(4), "'((2 . 1) ('(4) . 3))");
+    }
+
+    #[test]
+    fn named_let_and_do() {
+        let scm = "
+            (let loop ((i 0) (acc '()))
+              (if (= i 3) acc (loop (+ i 1) (cons i acc))))
+            (let count ((n 10000)) (if (= n 0) 'done (count (- n 1))))
+            (define loop 5)
+            (let sum ((loop loop) (total 0))
+              (if (= loop 0) total (sum (- loop 1) (+ total loop))))
+            (do ((i 0 (+ i 1)) (acc '() (cons i acc))) ((= i 3) acc))
+            (define total 0)
+            (do ((i 1 (+ i 1))) ((> i 4)) (set! total (+ total i)))
+            total
+            (do ((loop 0 (+ loop 1))) ((= loop 2) loop))";
+
+        let evalulated = eval_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().printable();
+        assert_eq!(printed(0), "'(2 1 0)");
+        assert_eq!(printed(1), "done");
+        assert_eq!(printed(3), "15");
+        assert_eq!(printed(4), "'(2 1 0)");
+        assert_eq!(printed(7), "10");
+        assert_eq!(printed(8), "2");
+    }
+
+    #[test]
+    fn letrec_and_internal_defines() {
+        let scm = "
+            (letrec ((even? (lambda (n) (if (= n 0) #t (odd? (- n 1)))))
+                     (odd? (lambda (n) (if (= n 0) #f (even? (- n 1))))))
+              (even? 100))
+            (letrec* ((a 1) (b (+ a 1))) (list a b))
+            (define x 'outer)
+            (define (shadowed)
+              (define y x)
+              (define x 'inner)
+              y)
+            (shadowed)
+            (define (mutual n)
+              (define (ping n) (if (= n 0) 'ping (pong (- n 1))))
+              (define (pong n) (if (= n 0) 'pong (ping (- n 1))))
+              (ping n))
+            (mutual 3)
+            (letrec ((a b) (b 1)) a)";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(0), "#t");
+        assert_eq!(printed(1), "'(1 2)");
+        assert_eq!(
+            evalulated.get(4).unwrap(),
+            &Err(EvalErr::UnassignedVar("x".to_string()))
+        );
+        assert_eq!(printed(6), "pong");
+        assert!(matches!(
+            evalulated.get(7).unwrap(),
+            Err(EvalErr::UnassignedVar(_))
+        ));
+    }
+
+    #[test]
+    fn case_when_unless() {
+        let scm = "
+            (define (classify x)
+              (case (* x 2)
+                ((2 3 5 7) 'prime)
+                ((1 4 9) => (lambda (n) (list 'square n)))
+                (else 'other)))
+            (classify 1)
+            (classify 2)
+            (classify 10)
+            (case 'b ((a) 1) (else => (lambda (k) k)))
+            (case 'z ((a) 1))
+            (cond ((= 1 2) => not) (else 'none))
+            (cond (#f => car) ((= 1 1) => (lambda (t) (list t))))
+            (define n 0)
+            (when (= n 0) (set! n 1) 'ran)
+            (unless (= n 0) (set! n 2) 'ran)
+            (when #f 'never)
+            n";
+
+        let evalulated = eval_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().printable();
+        assert_eq!(printed(1), "prime");
+        assert_eq!(printed(2), "'(square 4)");
+        assert_eq!(printed(3), "other");
+        assert_eq!(printed(4), "b");
+        assert_eq!(printed(5), "");
+        assert_eq!(printed(6), "none");
+        assert_eq!(printed(7), "'(#t)");
+        assert_eq!(printed(9), "ran");
+        assert_eq!(printed(10), "ran");
+        assert_eq!(printed(11), "");
+        assert_eq!(printed(12), "2");
+    }
+
+    #[test]
+    fn case_lambda() {
+        let scm = "
+            (define area
+              (case-lambda
+                ((r) (* 3 r r))
+                ((w h) (* w h))
+                ((w h . more) (list w h more))))
+            (area 2)
+            (area 2 3)
+            (area 1 2 3 4)
+            (define no-args (case-lambda ((x) x)))
+            (no-args)";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(1), "12");
+        assert_eq!(printed(2), "6");
+        assert_eq!(printed(3), "'(1 2 '(3 4))");
+        assert!(matches!(
+            evalulated.get(5).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+    }
+
+    #[test]
+    fn truthiness() {
+        let scm = "
+            (if '() 'yes 'no)
+            (if 0 'yes 'no)
+            (and 1 2 'three)
+            (and 1 #f 3)
+            (or #f '(a) 2)
+            (or #f #f)
+            (define alist '((a 1) (b 2)))
+            (or (assq 'c alist) 'default)
+            (if (memq 'b '(a b c)) 'found 'missing)
+            (cond ((assv 'b alist) => cadr) (else 'none))
+            (not 0)
+            (not #f)";
+
+        let evalulated = eval_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().printable();
+        assert_eq!(printed(0), "yes");
+        assert_eq!(printed(1), "yes");
+        assert_eq!(printed(2), "three");
+        assert_eq!(printed(3), "#f");
+        assert_eq!(printed(4), "'(a)");
+        assert_eq!(printed(5), "#f");
+        assert_eq!(printed(7), "default");
+        assert_eq!(printed(8), "found");
+        assert_eq!(printed(9), "2");
+        assert_eq!(printed(10), "#f");
+        assert_eq!(printed(11), "#t");
+    }
+
+    #[test]
+    fn numeric_tower() {
+        let scm = "
+            (define (fact n) (if (= n 0) 1 (* n (fact (- n 1)))))
+            (fact 25)
+            (/ 1 3)
+            (+ (/ 1 3) (/ 2 3))
+            (/ 6 4)
+            (exact? (/ 6 3))
+            (inexact (/ 1 4))
+            (inexact 2)
+            (* 3.0 1/2)
+            (exact 0.25)
+            (list (quotient -7 2) (remainder -7 2) (modulo -7 2))
+            (modulo (inexact 7) 2)
+            (list (< 1 2 3) (< 1 3 2) (= 1 (inexact 1)) (equal? 1 (inexact 1)))
+            (list (numerator (/ 6 4)) (denominator (/ 6 4)))
+            (- (/ 1 2))
+            (/ 1 0)
+            (remainder (/ 1 2) 2)
+            (list (= 9007199254740993 9007199254740992.0) (< 9007199254740992.0 9007199254740993))
+            (list (> 1/3 0.3333333333333333) (> +inf.0 (fact 200)) (< -inf.0 (- (fact 200))))";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(1), "15511210043330985984000000");
+        assert_eq!(printed(2), "1/3");
+        assert_eq!(printed(3), "1");
+        assert_eq!(printed(4), "3/2");
+        assert_eq!(printed(5), "#t");
+        assert_eq!(printed(6), "0.25");
+        assert_eq!(printed(7), "2.0");
+        assert_eq!(printed(8), "1.5");
+        assert_eq!(printed(9), "1/4");
+        assert_eq!(printed(10), "'(-3 -1 1)");
+        assert_eq!(printed(11), "1.0");
+        assert_eq!(printed(12), "'(#t #f #t #f)");
+        assert_eq!(printed(13), "'(3 2)");
+        assert_eq!(printed(14), "-1/2");
+        assert!(matches!(
+            evalulated.get(15).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            evalulated.get(16).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert_eq!(printed(17), "'(#f #t)");
+        assert_eq!(printed(18), "'(#t #t #t)");
+    }
+
+    #[test]
+    fn characters() {
+        let scm = r"
+            (char? #\a)
+            (char->integer #\A)
+            (integer->char 955)
+            (list (char-upcase #\a) (char-downcase #\Z) (char-upcase #\1))
+            (list (char<? #\a #\b #\c) (char<? #\a #\c #\b) (char=? #\x41 #\A))
+            (list (char-alphabetic? #\a) (char-numeric? #\7) (char-whitespace? #\tab))
+            (digit-value #\7)
+            (digit-value #\a)
+            #\space
+            (equal? #\a #\a)
+            (integer->char -1)";
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(0), "#t");
+        assert_eq!(printed(1), "65");
+        assert_eq!(printed(2), "#\\λ");
+        assert_eq!(printed(3), "'(#\\A #\\z #\\1)");
+        assert_eq!(printed(4), "'(#t #f #t)");
+        assert_eq!(printed(5), "'(#t #t #t)");
+        assert_eq!(printed(6), "7");
+        assert_eq!(printed(7), "#f");
+        assert_eq!(printed(8), "#\\space");
+        assert_eq!(printed(9), "#t");
+        assert!(matches!(
+            evalulated.get(10).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+    }
+
+    #[test]
+    fn strings() {
+        let scm = r#"
+            (string-length "héllo")
+            (string-ref "héllo" 1)
+            (list (substring "hello world" 6) (substring "hello" 1 3) (string-copy "abc" 1))
+            (string-append "foo" "bar" "" "baz")
+            (list (string=? "a" "a" "a") (string<? "abc" "abd") (string>? "a" "b"))
+            (string->list "abc")
+            (list->string (list #\a #\b))
+            (list (string->symbol "sym") (symbol->string 'sym))
+            (list (string->number "1/2") (string->number "ff" 16) (string->number "nope"))
+            (list (number->string 2.5) (number->string 255 16))
+            (string-upcase "Hello")
+            (list (string-index "hello" #\l) (string-index "hello" #\z))
+            (string-split "a,b,,c" #\,)
+            (string-join (list "a" "b" "c") ", ")
+            (define s (make-string 3 #\x))
+            (define alias s)
+            (string-set! s 1 #\y)
+            (string-copy alias)
+            (string-fill! s #\z 2)
+            s
+            (string-ref "abc" 3)
+            (equal? "abc" 'abc)"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(0), "5");
+        assert_eq!(printed(1), "#\\é");
+        assert_eq!(printed(2), r#"'("world" "el" "bc")"#);
+        assert_eq!(printed(3), r#""foobarbaz""#);
+        assert_eq!(printed(4), "'(#t #t #f)");
+        assert_eq!(printed(5), "'(#\\a #\\b #\\c)");
+        assert_eq!(printed(6), r#""ab""#);
+        assert_eq!(printed(7), r#"'(sym "sym")"#);
+        assert_eq!(printed(8), "'(1/2 255 #f)");
+        assert_eq!(printed(9), r#"'("2.5" "ff")"#);
+        assert_eq!(printed(10), r#""HELLO""#);
+        assert_eq!(printed(11), "'(2 #f)");
+        assert_eq!(printed(12), r#"'("a" "b" "" "c")"#);
+        assert_eq!(printed(13), r#""a, b, c""#);
+        assert_eq!(printed(17), r#""xyx""#);
+        assert_eq!(printed(19), r#""xyz""#);
+        assert!(matches!(
+            evalulated.get(20).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert_eq!(printed(21), "#f");
+    }
+
+    #[test]
+    fn string_literals() {
+        let scm = r#"
+            (define (f) "abc")
+            (string-set! (f) 0 #\z)
+            (string-fill! (f) #\z)
+            (f)
+            (define s (string-copy (f)))
+            (string-set! s 0 #\z)
+            (list s (f))"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert!(matches!(
+            evalulated.get(1).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            evalulated.get(2).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert_eq!(printed(3), r#""abc""#);
+        assert_eq!(printed(6), r#"'("zbc" "abc")"#);
+    }
+
+    #[test]
+    fn vectors() {
+        let scm = r#"
+            #(1 "two" (3 4) #(5))
+            (vector? #(1))
+            (define v (make-vector 3 0))
+            (define alias v)
+            (vector-set! v 0 'a)
+            (list (vector-ref alias 0) (vector-length alias))
+            (vector->list (vector 1 2 3) 1)
+            (list->vector '(1 2))
+            (vector-map (lambda (x) (* x x)) #(1 2 3))
+            (define total 0)
+            (vector-for-each (lambda (x) (set! total (+ total x))) #(1 2 3))
+            total
+            (define c (vector-copy v))
+            (vector-fill! v 'z 1)
+            (list v c)
+            (vector-ref v 3)
+            (vector-map + #(1 2 3) #(10 20) #(100 200 300))
+            (define pairs '())
+            (vector-for-each (lambda (x y) (set! pairs (cons (list x y) pairs))) #(1 2) #(a b c))
+            pairs"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(0), r#"#(1 "two" '(3 4) #(5))"#);
+        assert_eq!(printed(1), "#t");
+        assert_eq!(printed(5), "'(a 3)");
+        assert_eq!(printed(6), "'(2 3)");
+        assert_eq!(printed(7), "#(1 2)");
+        assert_eq!(printed(8), "#(1 4 9)");
+        assert_eq!(printed(11), "6");
+        assert_eq!(printed(14), "'(#(a z z) #(a 0 0))");
+        assert!(matches!(
+            evalulated.get(15).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert_eq!(printed(16), "#(111 222)");
+        assert_eq!(printed(19), "'('(2 b) '(1 a))");
+    }
+
+    #[test]
+    fn vector_literals() {
+        let scm = r#"
+            (define (f) #(1 2))
+            (vector-set! (f) 0 'z)
+            (vector-fill! (f) 'z)
+            (f)
+            (define v (vector-copy (f)))
+            (vector-set! v 0 'z)
+            (list v (f))
+            (define x 2)
+            (define ls '(3 4))
+            `#(1 ,x ,@ls)
+            `(a #(b (c ,x)))
+            (define-syntax vector-sum
+              (syntax-rules ()
+                ((_ #(n ...)) (+ n ...))))
+            (vector-sum #(1 2 3))
+            (define-syntax swap-vector
+              (syntax-rules ()
+                ((_ #(a b)) '#(b a k))))
+            (swap-vector #(1 2))"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert!(matches!(
+            evalulated.get(1).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert!(matches!(
+            evalulated.get(2).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        assert_eq!(printed(3), "#(1 2)");
+        assert_eq!(printed(6), "'(#(z 2) #(1 2))");
+        assert_eq!(printed(9), "#(1 2 3 4)");
+        assert_eq!(printed(10), "'(a #(b '(c 2)))");
+        assert_eq!(printed(12), "6");
+        assert_eq!(printed(14), "#(2 1 k)");
+    }
+
+    #[test]
+    fn bytevectors() {
+        let scm = r#"
+            #u8(1 2 255)
+            (define b (make-bytevector 3 7))
+            (bytevector-u8-set! b 0 1)
+            (list (bytevector-u8-ref b 0) (bytevector-length b) (bytevector? b))
+            (bytevector-append #u8(1) (bytevector 2 3) (bytevector-copy #u8(4 5 6) 1 2))
+            (utf8->string (string->utf8 "héllo"))
+            (string->utf8 "é")
+            (bytevector-copy! b 1 #u8(9 8) 1)
+            b
+            (bytevector 256)"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(0), "#u8(1 2 255)");
+        assert_eq!(printed(3), "'(1 3 #t)");
+        assert_eq!(printed(4), "#u8(1 2 3 5)");
+        assert_eq!(printed(5), r#""héllo""#);
+        assert_eq!(printed(6), "#u8(195 169)");
+        assert_eq!(printed(8), "#u8(1 8 7)");
+        assert!(matches!(
+            evalulated.get(9).unwrap(),
+            Err(EvalErr::TypeError("byte", _))
+        ));
+    }
+
+    #[test]
+    fn binary_ports() {
+        let path = std::env::temp_dir().join("schemeish_binary_ports.bin");
+        let scm = format!(
+            r#"
+            (define in (open-input-bytevector #u8(1 2 3)))
+            (list (peek-u8 in) (read-u8 in) (read-bytevector 5 in) (eof-object? (read-u8 in)))
+            (define out (open-output-bytevector))
+            (write-u8 65 out)
+            (write-bytevector #u8(66 67 68) out 1)
+            (get-output-bytevector out)
+            (define file (open-binary-output-file "{0}"))
+            (write-bytevector #u8(202 254) file)
+            (close-port file)
+            (define file (open-binary-input-file "{0}"))
+            (list (read-u8 file) (read-u8 file) (read-u8 file))
+            (write-u8 1 file)"#,
+            path.display()
+        );
+
+        let evalulated = eval_err_test(&scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(1), "'(1 1 #u8(2 3) #t)");
+        assert_eq!(printed(5), "#u8(65 67 68)");
+        assert_eq!(printed(10), "'(202 254 #<eof>)");
+        assert!(matches!(
+            evalulated.get(11).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hash_tables() {
+        let scm = r#"
+            (define table (make-hash-table))
+            (hash-table-set! table '(1 "a") 'list)
+            (hash-table-set! table "key" 'string)
+            (hash-table-set! table 2 'two)
+            (list (hash-table-ref table (list 1 "a")) (hash-table-ref/default table 'x 'none))
+            (hash-table-ref table 'missing (lambda () 'thunk))
+            (hash-table-update! table 2 (lambda (v) (list v v)))
+            (hash-table-update!/default table 'count (lambda (n) (+ n 1)) 0)
+            (list (hash-table-ref table 2) (hash-table-ref table 'count) (hash-table-count table))
+            (hash-table-delete! table "key")
+            (list (hash-table-contains? table "key") (length (hash-table-keys table)))
+            (define ids (make-hash-table eqv?))
+            (hash-table-set! ids "key" 1)
+            (hash-table-contains? ids "key")
+            (define names (make-hash-table string=?))
+            (hash-table-set! names "a" 1)
+            (hash-table-set! names "a" 2)
+            (hash-table->alist names)
+            (define total 0)
+            (hash-table-walk table (lambda (k v) (set! total (+ total 1))))
+            total
+            (hash-table-ref table 'missing)
+            (hash-table-set! names 'a 1)
+            (make-hash-table (lambda (x y) (equal? x y)))"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(4), "'(list none)");
+        assert_eq!(printed(5), "thunk");
+        assert_eq!(printed(8), "'('(two two) 1 4)");
+        assert_eq!(printed(10), "'(#f 3)");
+        assert_eq!(printed(13), "#f");
+        assert_eq!(printed(17), r#"'(("a" . 2))"#);
+        assert_eq!(printed(20), "3");
+        assert!(matches!(
+            evalulated.get(21).unwrap(),
+            Err(EvalErr::RuntimeException(..))
+        ));
+        assert!(matches!(
+            evalulated.get(22).unwrap(),
+            Err(EvalErr::TypeError("string", _))
+        ));
+        assert!(matches!(
+            evalulated.get(23).unwrap(),
+            Err(EvalErr::InvalidArgs(_))
+        ));
+    }
+
+    #[test]
+    fn equivalence() {
+        let scm = r#"
+            (define s "abc")
+            (list (eqv? s s) (eqv? "abc" (string-copy s)) (equal? "abc" (string-copy s)))
+            (list (eqv? '(1 2) (list 1 2)) (equal? '(1 (2 #(3))) (list 1 (list 2 (vector 3)))))
+            (list (eq? 'a 'a) (eqv? 1.5 1.5) (equal? #u8(1 2) (bytevector 1 2)))
+            (member (list 2) '((1) (2) (3)))
+            (list (eqv? 0.0 -0.0) (eqv? +nan.0 +nan.0) (equal? 0.0 -0.0) (eqv? 0.0 0.0))
+            (list (= 0.0 -0.0) (= +nan.0 +nan.0) (eqv? 1 1.0))"#;
+
+        let evalulated = eval_test(scm);
+        assert_eq!(evalulated.get(1).unwrap().printable(), "'(#t #f #t)");
+        assert_eq!(evalulated.get(2).unwrap().printable(), "'(#f #t)");
+        assert_eq!(evalulated.get(3).unwrap().printable(), "'(#t #t #t)");
+        assert_eq!(evalulated.get(4).unwrap().printable(), "'(2)");
+        assert_eq!(evalulated.get(5).unwrap().printable(), "'(#f #t #f #t)");
+        assert_eq!(evalulated.get(6).unwrap().printable(), "'(#t #f #f)");
+    }
+
+    #[test]
+    fn circular_equality() {
+        let scm = "
+            (define (cycle . items)
+              (let ((ls (apply list items)))
+                (set-cdr! (list-tail ls (- (length ls) 1)) ls)
+                ls))
+            (define a (cycle 1 2))
+            (define b (cycle 1 2 1 2))
+            (define c (cycle 1 2 3))
+            (equal? a b)
+            (equal? a c)
+            (define v (vector 1 #f))
+            (vector-set! v 1 v)
+            (define w (vector 1 #f))
+            (vector-set! w 1 w)
+            (equal? v w)
+            (define table (make-hash-table))
+            (hash-table-set! table a 'first)
+            (hash-table-set! table c 'second)
+            (hash-table-ref/default table b 'missing)
+            (hash-table-ref/default table c 'missing)
+            (hash-table-ref/default table (cycle 3 1 2) 'missing)
+            (hash-table-set! table b 'replaced)
+            (hash-table-count table)
+            (hash-table-ref/default table a 'missing)";
+
+        let evalulated = eval_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().printable();
+        assert_eq!(printed(4), "#t");
+        assert_eq!(printed(5), "#f");
+        assert_eq!(printed(10), "#t");
+        assert_eq!(printed(14), "first");
+        assert_eq!(printed(15), "second");
+        assert_eq!(printed(16), "missing");
+        assert_eq!(printed(18), "2");
+        assert_eq!(printed(19), "replaced");
+    }
+
+    #[test]
+    fn records() {
+        let scm = r#"
+            (define-record-type <point>
+              (make-point x y)
+              point?
+              (x point-x set-point-x!)
+              (y point-y))
+            (define p (make-point 1 2))
+            (list (point? p) (point? '(1 2)) (point-x p) (point-y p))
+            (set-point-x! p 10)
+            p
+            (define-record-type pare (kons a) pare? (a kar) (d kdr set-kdr!))
+            (kons 1)
+            (point-x (kons 1))"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(2), "'(#t #f 1 2)");
+        assert_eq!(printed(4), "#<record point x=10 y=2>");
+        assert_eq!(printed(6), "#<record pare a=1 d=#f>");
+        assert!(matches!(
+            evalulated.get(7).unwrap(),
+            Err(EvalErr::TypeError(..))
+        ));
+    }
+
+    #[test]
+    fn symbols_are_interned() {
+        let scm = r#"
+            (eq? 'abc (string->symbol "abc"))
+            (eq? 'abc 'abd)
+            (eq? (string->symbol (string-append "ab" "c")) 'abc)
+            (symbol->string (string->symbol "hello world"))
+            (let ((h (make-hash-table eq?)))
+              (hash-table-set! h 'key 1)
+              (hash-table-ref/default h (string->symbol "key") 0))"#;
+
+        let evalulated = eval_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().printable();
+        assert_eq!(printed(0), "#t");
+        assert_eq!(printed(1), "#f");
+        assert_eq!(printed(2), "#t");
+        assert_eq!(printed(3), "\"hello world\"");
+        assert_eq!(printed(4), "1");
+    }
+
+    #[test]
+    fn shared_code() {
+        let scm = r#"
+            (define (make-adder n) (lambda (x) (+ x n)))
+            (define add1 (make-adder 1))
+            (define add10 (make-adder 10))
+            (list (add1 1) (add10 1) (add1 2))
+            (define (count-up)
+              (define k #f)
+              (define n 0)
+              (define trace '())
+              (set! trace (append trace '(a)))
+              (call/cc (lambda (c) (set! k c)))
+              (set! trace (append trace '(b)))
+              (set! n (+ n 1))
+              (if (< n 3) (k #f))
+              trace)
+            (count-up)
+            (count-up)
+            (lambda (1) 1)"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(3), "'(2 11 3)");
+        assert_eq!(printed(5), "'(a b b b)");
+        assert_eq!(printed(6), "'(a b b b)");
+        assert!(evalulated.get(7).unwrap().is_err());
+    }
+
+    #[test]
+    fn lexical_addressing() {
+        let scm = r#"
+            (define (later) (not-yet-defined 1))
+            (define (not-yet-defined x) (+ x 1))
+            (later)
+            (define (counter)
+              (define n 0)
+              (lambda () (set! n (+ n 1)) n))
+            (define c (counter))
+            (begin (c) (c))
+            (define (capture x)
+              (let ((get (lambda () x)))
+                (set! x 10)
+                (get)))
+            (capture 1)
+            (define (shadow x)
+              (let ((y x))
+                (let ((x 2))
+                  (+ x y))))
+            (shadow 1)
+            (define (early)
+              (define a b)
+              (define b 1)
+              a)
+            (early)
+            (set! never-defined 1)
+            (guard (e (#t (list e (shadow 5))))
+              (raise 'oops))"#;
+
+        let evalulated = eval_err_test(scm);
+        let printed = |i: usize| evalulated.get(i).unwrap().as_ref().unwrap().printable();
+        assert_eq!(printed(2), "2");
+        assert_eq!(printed(5), "2");
+        assert_eq!(printed(7), "10");
+        assert_eq!(printed(9), "3");
+        assert!(matches!(
+            evalulated.get(11).unwrap(),
+            Err(EvalErr::UnassignedVar(_))
+        ));
+        assert!(matches!(
+            evalulated.get(12).unwrap(),
+            Err(EvalErr::UnboundVar(_))
+        ));
+        assert_eq!(printed(13), "'(oops 7)");
+    }
+
+    #[test]
+    fn gc_frees_cycles() {
+        let scm = "
+            (define (make-cycle)
+              (let ((ls (list 1 2)))
+                (set-cdr! (cdr ls) ls)
+                #t))
+            (define (make-counter)
+              (define n 0)
+              (define (next) (set! n (+ n 1)) n)
+              next)
+            (define x (list 1 2 3))
+            (set-cdr! (cddr x) x)
+            (define counter (make-counter))
+            (counter)
+            (gc)
+            (make-cycle)
+            (make-cycle)
+            (>= (gc) 4)
+            (begin (make-counter) #t)
+            (gc)
+            (gc)
+            (counter)
+            (car (cdr (cdr (cdr x))))
+            (>= (cdr (assq 'collections (gc-statistics))) 3)";
+
+        let evalulated = eval_test(scm);
+        let res = |i: usize| evalulated.get(i).unwrap().to_owned();
+        assert_eq!(res(9), true.to_expr());
+        assert_ne!(res(11), 0.to_expr());
+        assert_eq!(res(12), 0.to_expr());
+        assert_eq!(res(13), 2.to_expr());
+        assert_eq!(res(14), 1.to_expr());
+        assert_eq!(res(15), true.to_expr());
+    }
+
     #[test]
     fn read_file() {
         let mut path = vec!["".to_string(), "./test_scm/factorial.scm".to_string()].into_iter();
         let scm = match read(&mut path).unwrap() {
-            Runtime::File(f) => f,
-            Runtime::Repl => panic!("expected file"),
+            (Runtime::File(f), _) => f,
+            (Runtime::Repl, _) => panic!("expected file"),
         };
         let evalulated = eval_test(&scm);
         let res = evalulated.get(3).unwrap().to_owned();
-        assert_eq!(res, Atom(Number(3628800.0)));
+        assert_eq!(res, Atom(Number(3628800.into())));
     }
+
+    #[test]
+    fn read_bytecode_flag() {
+        let mut args = vec!["".to_string(), "--bytecode".to_string()].into_iter();
+        assert!(matches!(
+            read(&mut args).unwrap(),
+            (Runtime::Repl, Engine::Bytecode)
+        ));
+        let mut args = vec!["".to_string(), "./test_scm/factorial.scm".to_string()].into_iter();
+        assert!(matches!(
+            read(&mut args).unwrap(),
+            (Runtime::File(_), Engine::Tree)
+        ));
+        let mut args = vec![
+            "".to_string(),
+            "./test_scm/factorial.scm".to_string(),
+            "--bytecode".to_string(),
+        ]
+        .into_iter();
+        assert!(matches!(
+            read(&mut args).unwrap(),
+            (Runtime::File(_), Engine::Bytecode)
+        ));
+        let mut args = vec!["".to_string(), "--bytcode".to_string()].into_iter();
+        assert!(read(&mut args).is_err());
+    }
+
+    // Runs each of the tests above again on the bytecode VM.
+    macro_rules! on_bytecode {
+        ($($test:ident),* $(,)?) => {
+            mod bytecode {
+                use super::*;
+
+                $(
+                    #[test]
+                    fn $test() {
+                        ENGINE.set(Engine::Bytecode);
+                        super::$test();
+                    }
+                )*
+            }
+        };
+    }
+
+    on_bytecode!(
+        arithemtic,
+        if_cmp,
+        simple_let,
+        curry_and_comment,
+        nested_proc_w_comments,
+        factorial,
+        iter_list,
+        map_list,
+        reassign_var,
+        or_true,
+        or_false,
+        and_true,
+        and_false,
+        cond_t,
+        cond_f,
+        else_ls,
+        let_star,
+        coin_combo,
+        substitute,
+        type_error,
+        reassign_unassigned,
+        mut_list_uses_ref,
+        mut_list_can_create_cycle_without_panic,
+        cyclic_lists,
+        improper_lists,
+        missplaced_else,
+        tail_call_loop,
+        tail_call_through_derived_forms,
+        call_cc_early_exit,
+        call_cc_reentry,
+        call_cc_generator,
+        dynamic_wind_normal_exit,
+        dynamic_wind_continuation_exit_and_reentry,
+        dynamic_wind_error_exit,
+        dynamic_wind_raise_during_exit,
+        guard_error_objects,
+        exception_handlers,
+        guard_reraise,
+        uncaught_exceptions,
+        syntax_rules_macros,
+        macro_hygiene,
+        local_macros,
+        quasiquote,
+        rest_params,
+        dotted_macro_patterns,
+        named_let_and_do,
+        letrec_and_internal_defines,
+        case_when_unless,
+        case_lambda,
+        truthiness,
+        numeric_tower,
+        characters,
+        strings,
+        string_literals,
+        vectors,
+        vector_literals,
+        bytevectors,
+        binary_ports,
+        hash_tables,
+        equivalence,
+        circular_equality,
+        records,
+        symbols_are_interned,
+        shared_code,
+        lexical_addressing,
+        gc_frees_cycles,
+        read_file,
+    );
 }
@@ -0,0 +1,335 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{FromPrimitive, Num, Pow, ToPrimitive, Zero};
+
+use crate::error::EvalErr;
+
+// The numeric tower. Exact numbers are arbitrary precision integers and rationals, and inexact
+// numbers are flonums. A rational is always kept in lowest terms, and one with a denominator of 1
+// is stored as an integer instead, so each exact value only has the one representation.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Number {
+    Integer(BigInt),
+    Rational(BigRational),
+    Real(f64),
+}
+
+impl Number {
+    // Reads a number written in the R7RS syntax, like `-12`, `1/2`, `3.14`, `1e10`, `+inf.0`, or
+    // `#x1F` and `#e1.5` with radix and exactness prefixes. Decimals are inexact unless `#e` is
+    // given, in which case they are read exactly rather than by way of a flonum.
+    pub fn parse(literal: &str) -> Option<Number> {
+        let mut radix = None;
+        let mut exact = None;
+        let mut rest = literal;
+        while let Some(prefixed) = rest.strip_prefix('#') {
+            let mut chars = prefixed.chars();
+            match chars.next()?.to_ascii_lowercase() {
+                'x' if radix.is_none() => radix = Some(16),
+                'd' if radix.is_none() => radix = Some(10),
+                'o' if radix.is_none() => radix = Some(8),
+                'b' if radix.is_none() => radix = Some(2),
+                'e' if exact.is_none() => exact = Some(true),
+                'i' if exact.is_none() => exact = Some(false),
+                _ => return None,
+            }
+            rest = chars.as_str();
+        }
+        let radix = radix.unwrap_or(10);
+
+        // Infinities and NaN have no exact value.
+        let special = match rest.to_ascii_lowercase().as_str() {
+            "+inf.0" => Some(f64::INFINITY),
+            "-inf.0" => Some(f64::NEG_INFINITY),
+            "+nan.0" | "-nan.0" => Some(f64::NAN),
+            _ => None,
+        };
+        if let Some(n) = special {
+            return (exact != Some(true)).then_some(Number::Real(n));
+        }
+
+        let (negative, unsigned) = match rest.as_bytes().first()? {
+            b'+' => (false, &rest[1..]),
+            b'-' => (true, &rest[1..]),
+            _ => (false, rest),
+        };
+        let ratio = match unsigned.split_once('/') {
+            Some((numer, denom)) => {
+                let denom = parse_uinteger(denom, radix)?;
+                if denom.is_zero() {
+                    return None;
+                }
+                BigRational::new(parse_uinteger(numer, radix)?, denom)
+            }
+            None => match (parse_uinteger(unsigned, radix), radix) {
+                (Some(n), _) => BigRational::from_integer(n),
+                (None, 10) => {
+                    let (digits, exponent) = parse_decimal(unsigned)?;
+                    return match exact {
+                        Some(true) => Some(Number::rational(scale(digits, exponent, negative))),
+                        // Rust's own float parsing rounds correctly, once the syntax is checked.
+                        _ => rest.parse().ok().map(Number::Real),
+                    };
+                }
+                (None, _) => return None,
+            },
+        };
+        let number = Number::rational(if negative { -ratio } else { ratio });
+        match exact {
+            Some(false) => Some(number.inexact()),
+            _ => Some(number),
+        }
+    }
+
+    pub fn rational(ratio: BigRational) -> Number {
+        match ratio.is_integer() {
+            true => Number::Integer(ratio.to_integer()),
+            false => Number::Rational(ratio),
+        }
+    }
+
+    pub fn is_exact(&self) -> bool {
+        !matches!(self, Number::Real(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        match self {
+            Number::Integer(_) => true,
+            Number::Rational(_) => false,
+            Number::Real(n) => n.is_finite() && n.fract() == 0.0,
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        match self {
+            Number::Integer(n) => n.is_zero(),
+            Number::Rational(n) => n.is_zero(),
+            Number::Real(n) => *n == 0.0,
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Number::Integer(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Rational(n) => n.to_f64().unwrap_or(f64::NAN),
+            Number::Real(n) => *n,
+        }
+    }
+
+    pub fn exact(&self) -> Result<Number, EvalErr> {
+        match self {
+            Number::Real(n) => {
+                BigRational::from_f64(*n)
+                    .map(Number::rational)
+                    .ok_or(EvalErr::InvalidArgs(
+                        "'exact'. number has no exact representation",
+                    ))
+            }
+            n => Ok(n.clone()),
+        }
+    }
+
+    pub fn inexact(&self) -> Number {
+        Number::Real(self.to_f64())
+    }
+
+    // Exact arguments give an exact result, but any inexact one makes the whole result inexact.
+    fn combine(
+        &self,
+        other: &Number,
+        exact: fn(&BigRational, &BigRational) -> BigRational,
+        inexact: fn(f64, f64) -> f64,
+    ) -> Number {
+        match (self.to_ratio(), other.to_ratio()) {
+            (Some(x), Some(y)) => Number::rational(exact(&x, &y)),
+            _ => Number::Real(inexact(self.to_f64(), other.to_f64())),
+        }
+    }
+
+    fn to_ratio(&self) -> Option<BigRational> {
+        match self {
+            Number::Integer(n) => Some(BigRational::from_integer(n.clone())),
+            Number::Rational(n) => Some(n.clone()),
+            Number::Real(_) => None,
+        }
+    }
+
+    pub fn add(&self, other: &Number) -> Number {
+        match (self, other) {
+            (Number::Integer(x), Number::Integer(y)) => Number::Integer(x + y),
+            _ => self.combine(other, |x, y| x + y, |x, y| x + y),
+        }
+    }
+
+    pub fn sub(&self, other: &Number) -> Number {
+        match (self, other) {
+            (Number::Integer(x), Number::Integer(y)) => Number::Integer(x - y),
+            _ => self.combine(other, |x, y| x - y, |x, y| x - y),
+        }
+    }
+
+    pub fn mul(&self, other: &Number) -> Number {
+        match (self, other) {
+            (Number::Integer(x), Number::Integer(y)) => Number::Integer(x * y),
+            _ => self.combine(other, |x, y| x * y, |x, y| x * y),
+        }
+    }
+
+    pub fn div(&self, other: &Number) -> Result<Number, EvalErr> {
+        if other.is_exact() && other.is_zero() {
+            return Err(EvalErr::InvalidArgs("'/'. division by zero"));
+        }
+        Ok(self.combine(other, |x, y| x / y, |x, y| x / y))
+    }
+
+    // Integer division, rounding towards zero for `quotient` and `remainder` and towards negative
+    // infinity for `modulo`. Inexact integers give inexact results.
+    pub fn integer_div(&self, other: &Number, op: IntegerDiv) -> Result<Number, EvalErr> {
+        if !self.is_integer() || !other.is_integer() {
+            return Err(EvalErr::InvalidArgs(
+                "integer division requires integer arguments",
+            ));
+        }
+        if other.is_zero() {
+            return Err(EvalErr::InvalidArgs("integer division by zero"));
+        }
+        match (self, other) {
+            (Number::Integer(x), Number::Integer(y)) => Ok(Number::Integer(match op {
+                IntegerDiv::Quotient => x / y,
+                IntegerDiv::Remainder => x % y,
+                IntegerDiv::Modulo => x.mod_floor(y),
+            })),
+            _ => {
+                let (x, y) = (self.to_f64(), other.to_f64());
+                Ok(Number::Real(match op {
+                    IntegerDiv::Quotient => (x / y).trunc(),
+                    IntegerDiv::Remainder => x % y,
+                    IntegerDiv::Modulo => x - y * (x / y).floor(),
+                }))
+            }
+        }
+    }
+
+    pub fn numerator(&self) -> Result<Number, EvalErr> {
+        match self {
+            Number::Rational(n) => Ok(Number::Integer(n.numer().clone())),
+            Number::Real(_) => Ok(self.exact()?.numerator()?.inexact()),
+            n => Ok(n.clone()),
+        }
+    }
+
+    pub fn denominator(&self) -> Result<Number, EvalErr> {
+        match self {
+            Number::Integer(_) => Ok(Number::Integer(BigInt::from(1))),
+            Number::Rational(n) => Ok(Number::Integer(n.denom().clone())),
+            Number::Real(_) => Ok(self.exact()?.denominator()?.inexact()),
+        }
+    }
+
+    // Numeric comparison across exactness, unlike `==` which tells exact and inexact apart.
+    pub fn compare(&self, other: &Number) -> Option<Ordering> {
+        match (self.to_ratio(), other.to_ratio()) {
+            (Some(x), Some(y)) => Some(x.cmp(&y)),
+            (None, Some(y)) => compare_inexact(self.to_f64(), &y),
+            (Some(x), None) => compare_inexact(other.to_f64(), &x).map(Ordering::reverse),
+            (None, None) => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+}
+
+// Every finite flonum is an exact rational, so it is compared as one rather than rounding the
+// exact number to the nearest flonum, which could make two different numbers equal.
+fn compare_inexact(x: f64, y: &BigRational) -> Option<Ordering> {
+    match BigRational::from_f64(x) {
+        Some(x) => Some(x.cmp(y)),
+        None if x.is_nan() => None,
+        None if x > 0.0 => Some(Ordering::Greater),
+        None => Some(Ordering::Less),
+    }
+}
+
+fn parse_uinteger(digits: &str, radix: u32) -> Option<BigInt> {
+    match !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix)) {
+        true => BigInt::from_str_radix(digits, radix).ok(),
+        false => None,
+    }
+}
+
+// `digits[.digits][e[sign]digits]`, with at least one digit on either side of the point. Gives
+// the digits with the point removed, along with the power of ten they need to be scaled by.
+fn parse_decimal(literal: &str) -> Option<(BigInt, i64)> {
+    let (mantissa, exponent) = match literal.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => {
+            let (negative, digits) = match exponent.as_bytes().first()? {
+                b'+' => (false, &exponent[1..]),
+                b'-' => (true, &exponent[1..]),
+                _ => (false, exponent),
+            };
+            let exponent = parse_uinteger(digits, 10)?.to_i64()?;
+            (mantissa, if negative { -exponent } else { exponent })
+        }
+        None => (literal, 0),
+    };
+    let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let all_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if whole.len() + fraction.len() == 0 || !all_digits(whole) || !all_digits(fraction) {
+        return None;
+    }
+    let digits = BigInt::from_str_radix(&format!("{whole}{fraction}"), 10).ok()?;
+    Some((digits, exponent.checked_sub(fraction.len() as i64)?))
+}
+
+fn scale(digits: BigInt, exponent: i64, negative: bool) -> BigRational {
+    let digits = if negative { -digits } else { digits };
+    let power = BigInt::from(10).pow(exponent.unsigned_abs());
+    match exponent < 0 {
+        true => BigRational::new(digits, power),
+        false => BigRational::from_integer(digits * power),
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IntegerDiv {
+    Quotient,
+    Remainder,
+    Modulo,
+}
+
+impl From<i64> for Number {
+    fn from(n: i64) -> Self {
+        Number::Integer(BigInt::from(n))
+    }
+}
+
+impl From<BigInt> for Number {
+    fn from(n: BigInt) -> Self {
+        Number::Integer(n)
+    }
+}
+
+impl From<f64> for Number {
+    fn from(n: f64) -> Self {
+        Number::Real(n)
+    }
+}
+
+impl fmt::Display for Number {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Number::Integer(n) => write!(f, "{n}"),
+            Number::Rational(n) => write!(f, "{}/{}", n.numer(), n.denom()),
+            Number::Real(n) if n.is_nan() => write!(f, "+nan.0"),
+            Number::Real(n) if n.is_infinite() => match n.is_sign_positive() {
+                true => write!(f, "+inf.0"),
+                false => write!(f, "-inf.0"),
+            },
+            // Inexact integers keep their decimal point so they can be told apart from exact ones.
+            Number::Real(n) if n.fract() == 0.0 => write!(f, "{n:.1}"),
+            Number::Real(n) => write!(f, "{n}"),
+        }
+    }
+}
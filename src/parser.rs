@@ -1,33 +1,79 @@
+use std::cell::RefCell;
 use std::iter::Peekable;
 use std::rc::Rc;
 use std::vec::IntoIter;
 
 use crate::error::EvalErr;
+use crate::expander::{dotted, Alias};
+use crate::heap;
 use crate::lexer::Token;
+use crate::primitives::hash_table::HashTable;
 use crate::primitives::pair::Pair;
+use crate::primitives::port::Port;
+use crate::primitives::record::{Record, RecordType};
+use crate::primitives::vector::Vector;
 use crate::print::Printable;
 use crate::procedure::Proc;
-use crate::special_form::{And, Assignment, Begin, Define, If, Lambda, Or, SpecialForm};
-use crate::utils::{OwnIterVals, ToExpr};
+use crate::special_form::SpecialForm;
+use crate::symbol::Symbol;
+use crate::syntax_rules::Macro;
+use crate::utils::{byte_arg, ToExpr};
 
 // We treat any list that is expected to be evaluated as a procedure during parsing as a vector
 // of expressions rather than a proper list of pairs to simplify and reduce the cost of the parsing process.
+//
+// The parser only reads the structure of the program. Keywords are left as symbols for the
+// expander to resolve before evaluation, which is what lets macros define new syntax.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     Call(Vec<Expr>),
-    Pair(Rc<Pair>), //TODO: Maybe Rc -> Rc<RefCell>? unsafe mutation seems to be ok for now...
+    Pair(Rc<Pair>),
+    Vector(Rc<Vector>),
+    Bytevector(Rc<RefCell<Vec<u8>>>),
+    Port(Rc<RefCell<Port>>),
+    HashTable(Rc<RefCell<HashTable>>),
+    RecordType(Rc<RecordType>),
+    Record(Rc<Record>),
     Proc(Rc<Proc>),
     SpecialForm(Rc<SpecialForm>),
     Quoted(Box<Expr>),
+    Condition(Rc<EvalErr>),
+    Macro(Rc<Macro>),
+    Alias(Rc<Alias>),
     Atom(Token),
     EmptyList,
     Void,
+    Unassigned,
+    Eof,
 }
 
 impl Expr {
+    pub fn vector(items: Vec<Expr>) -> Expr {
+        Expr::tracked_vector(Vector::new(items))
+    }
+
+    pub fn literal_vector(items: Vec<Expr>) -> Expr {
+        Expr::tracked_vector(Vector::literal(items))
+    }
+
+    fn tracked_vector(vector: Vector) -> Expr {
+        let vector = Rc::new(vector);
+        heap::track(&vector);
+        Expr::Vector(vector)
+    }
+
+    pub fn bytevector(bytes: Vec<u8>) -> Expr {
+        Expr::Bytevector(Rc::new(RefCell::new(bytes)))
+    }
+
     pub fn into_call(self) -> Result<Expr, EvalErr> {
         Ok(vec![self].to_expr())
     }
+
+    // Every value besides #f counts as true in a test.
+    pub fn is_true(&self) -> bool {
+        !matches!(self, Expr::Atom(Token::Boolean(false)))
+    }
 }
 
 pub struct Parser {
@@ -54,47 +100,7 @@ impl Parser {
     fn parse_from_token(&mut self) -> Result<Expr, EvalErr> {
         match self.next_or_err(EvalErr::UnexpectedEnd)? {
             Token::LParen => match self.peek_or_err(EvalErr::UnexpectedEnd)? {
-                Token::If => {
-                    self.tokens.next();
-                    self.parse_if()
-                }
-                Token::Lambda => {
-                    self.tokens.next();
-                    self.parse_lambda()
-                }
-                Token::Define => {
-                    self.tokens.next();
-                    self.parse_define()
-                }
-                Token::And => {
-                    self.tokens.next();
-                    self.parse_and()
-                }
-                Token::Or => {
-                    self.tokens.next();
-                    self.parse_or()
-                }
-                Token::Assignment => {
-                    self.tokens.next();
-                    self.parse_assignment()
-                }
-                Token::Begin => {
-                    self.tokens.next();
-                    self.parse_begin()
-                }
-                Token::Cond => {
-                    self.tokens.next();
-                    self.parse_cond()
-                }
-                Token::Let => {
-                    self.tokens.next();
-                    self.parse_let()
-                }
-                Token::LetStar => {
-                    self.tokens.next();
-                    self.parse_letstar()
-                }
-                Token::QuoteProc => {
+                Token::Symbol(Symbol::QUOTE) => {
                     self.tokens.next();
                     let quoted = self.parse_quote()?;
                     self.next_or_err(EvalErr::UnexpectedEnd)?; // consume remaining paren
@@ -103,8 +109,14 @@ impl Parser {
                 _ => self.parse_proc_call(),
             },
             Token::QuoteTick => Ok(Expr::Quoted(Box::new(self.parse_quote()?))),
+            Token::HashParen => self.parse_vector(),
+            Token::U8Paren => self.parse_bytevector(),
+            t @ (Token::Backquote | Token::Comma | Token::CommaAt) => {
+                Ok(vec![quasi_keyword(&t).to_expr(), self.parse_from_token()?].to_expr())
+            }
             x @ Token::Number(_)
             | x @ Token::Str(_)
+            | x @ Token::Char(_)
             | x @ Token::Boolean(_)
             | x @ Token::Symbol(_) => Ok(Expr::Atom(x)),
             t => Err(EvalErr::UnexpectedToken(t.printable())),
@@ -112,20 +124,19 @@ impl Parser {
     }
 
     fn parse_proc_call(&mut self) -> Result<Expr, EvalErr> {
-        let list = self.parse_inner_call()?;
-        match !list.is_empty() {
-            true => Ok(list.to_expr()),
-            false => Ok(Expr::EmptyList),
-        }
-    }
-
-    fn parse_inner_call(&mut self) -> Result<Vec<Expr>, EvalErr> {
         let mut parsed_exprs: Vec<Expr> = vec![];
         while let Some(t) = self.tokens.peek() {
             match t {
                 Token::RParen => {
                     self.tokens.next();
-                    return Ok(parsed_exprs);
+                    return Ok(dotted(parsed_exprs, Expr::EmptyList));
+                }
+                // `(a b . c)`, which is only valid in a handful of places like parameter lists.
+                Token::Symbol(Symbol::DOT) if !parsed_exprs.is_empty() => {
+                    self.tokens.next();
+                    let tail = self.parse_from_token()?;
+                    self.close_dotted()?;
+                    return Ok(dotted(parsed_exprs, tail));
                 }
                 _ => parsed_exprs.push(self.parse_from_token()?),
             }
@@ -133,111 +144,6 @@ impl Parser {
         Err(EvalErr::UnexpectedEnd)
     }
 
-    fn parse_if(&mut self) -> Result<Expr, EvalErr> {
-        let (predicate, consequence, alternative) =
-            self.parse_inner_call()?.into_iter().own_three_or_else(|| {
-                EvalErr::InvalidArgs(
-                    "'if' expression. expected condition, predicate, and consequence",
-                )
-            })?;
-        If::new(predicate, consequence, alternative)
-            .to_expr()
-            .into_call()
-    }
-
-    fn parse_cond(&mut self) -> Result<Expr, EvalErr> {
-        let clauses = self.parse_inner_call()?;
-        match !clauses.is_empty() {
-            true => cond_to_if(&mut clauses.into_iter().peekable()),
-            false => Err(EvalErr::InvalidArgs("'cond' expression. expected clauses.")),
-        }
-    }
-
-    fn parse_lambda(&mut self) -> Result<Expr, EvalErr> {
-        let (params, body) = self
-            .parse_inner_call()?
-            .into_iter()
-            .own_one_and_rest_or_else(|| {
-                EvalErr::InvalidArgs("'lambda' expression. expected parameters and body")
-            })?;
-        Lambda::new(params, body.collect()).to_expr().into_call()
-    }
-
-    fn parse_define(&mut self) -> Result<Expr, EvalErr> {
-        let (identifier, mut body) = self
-            .parse_inner_call()?
-            .into_iter()
-            .own_one_and_rest_or_else(|| {
-                EvalErr::InvalidArgs("'define' expression. expected identifier and value")
-            })?;
-
-        match identifier {
-            Expr::Call(args) => {
-                let (identifier, params) = args.into_iter().own_one_and_rest_or_else(|| {
-                    EvalErr::InvalidArgs("'define' procedure. expected parameters and body")
-                })?;
-                let proc = Lambda::new(params.collect::<Vec<Expr>>().to_expr(), body.collect())
-                    .to_expr()
-                    .into_call()?;
-                Define::new(identifier, proc).to_expr().into_call()
-            }
-            identifier => Define::new(
-                identifier,
-                body.own_one_or_else(|| {
-                    EvalErr::InvalidArgs("'define' expression. expected identifier and value")
-                })?,
-            )
-            .to_expr()
-            .into_call(),
-        }
-    }
-
-    fn parse_assignment(&mut self) -> Result<Expr, EvalErr> {
-        let (first, second) = self.parse_inner_call()?.into_iter().own_two_or_else(|| {
-            EvalErr::InvalidArgs("'set!' expression. expected identifier and value")
-        })?;
-        Assignment::new(first, second).to_expr().into_call()
-    }
-
-    fn parse_let(&mut self) -> Result<Expr, EvalErr> {
-        let (bindings, body) = self
-            .parse_inner_call()?
-            .into_iter()
-            .own_one_and_rest_or_else(|| {
-                EvalErr::InvalidArgs("'let' expression. expected bindings and body")
-            })?;
-        Ok(let_to_lambda(bindings, body.collect())?.to_expr())
-    }
-
-    fn parse_letstar(&mut self) -> Result<Expr, EvalErr> {
-        let (bindings, body) = self
-            .parse_inner_call()?
-            .into_iter()
-            .own_one_and_rest_or_else(|| {
-                EvalErr::InvalidArgs("'let*' expression. expected bindings and body")
-            })?;
-
-        match bindings {
-            Expr::Call(bindings) => letstar_to_lambda(&mut bindings.into_iter(), body.collect()),
-            Expr::EmptyList => letstar_to_lambda(&mut vec![].into_iter(), body.collect()),
-            _ => Err(EvalErr::InvalidArgs(
-                "'let*' expression. expected list of binding",
-            )),
-        }
-    }
-
-    fn parse_begin(&mut self) -> Result<Expr, EvalErr> {
-        Begin::new(self.parse_inner_call()?).to_expr().into_call()
-    }
-
-    fn parse_and(&mut self) -> Result<Expr, EvalErr> {
-        And::new(self.parse_inner_call()?).to_expr().into_call()
-    }
-
-    fn parse_or(&mut self) -> Result<Expr, EvalErr> {
-        Or::new(self.parse_inner_call()?).to_expr().into_call()
-    }
-
     // We treat a quoted expression as a normal expression behind an extra indrection, with the
     // addtional major differnce being we parse lists as pairs instead of vectors. this way they
     // can be accessed at runtime rather than evaluated as procedures.
@@ -252,21 +158,18 @@ impl Parser {
                 Ok(res)
             }
 
-            t @ Token::Assignment
-            | t @ Token::Lambda
-            | t @ Token::Define
-            | t @ Token::Let
-            | t @ Token::LetStar
-            | t @ Token::If
-            | t @ Token::And
-            | t @ Token::Cond
-            | t @ Token::QuoteTick
-            | t @ Token::QuoteProc
-            | t @ Token::Begin
-            | t @ Token::Or => Ok(t.printable().to_expr()),
+            Token::HashParen => self.parse_vector(),
+            Token::U8Paren => self.parse_bytevector(),
+            t @ Token::QuoteTick => Ok(t.printable().to_expr()),
+            t @ (Token::Backquote | Token::Comma | Token::CommaAt) => Ok(Pair::new(
+                quasi_keyword(&t).to_expr(),
+                Pair::new(self.parse_quote()?, Expr::EmptyList).to_expr(),
+            )
+            .to_expr()),
 
             x @ Token::Number(_)
             | x @ Token::Str(_)
+            | x @ Token::Char(_)
             | x @ Token::Boolean(_)
             | x @ Token::Symbol(_) => Ok(Expr::Atom(x)),
             p @ Token::RParen => Err(EvalErr::UnexpectedToken(p.printable())),
@@ -277,6 +180,15 @@ impl Parser {
         match self.tokens.peek() {
             Some(t) => match t {
                 Token::RParen => Ok(Expr::EmptyList),
+                Token::Symbol(Symbol::DOT) => {
+                    self.tokens.next();
+                    let tail = self.parse_quote()?;
+                    match self.tokens.peek() {
+                        Some(Token::RParen) => Ok(tail),
+                        Some(t) => Err(EvalErr::UnexpectedToken(t.printable())),
+                        None => Err(EvalErr::UnexpectedEnd),
+                    }
+                }
                 _ => {
                     let current = self.parse_quote()?;
                     let next = self.parse_inner_quote()?;
@@ -287,113 +199,51 @@ impl Parser {
         }
     }
 
-    fn next_or_err(&mut self, err: EvalErr) -> Result<Token, EvalErr> {
-        self.tokens.next().map_or_else(|| Err(err), Ok)
+    // Vector literals are data like quoted lists are, and evaluate to themselves.
+    fn parse_vector(&mut self) -> Result<Expr, EvalErr> {
+        let mut items = vec![];
+        while self.peek_or_err(EvalErr::UnexpectedEnd)? != &Token::RParen {
+            items.push(self.parse_quote()?);
+        }
+        self.tokens.next(); // consume remaining paren
+        Ok(Expr::literal_vector(items))
     }
 
-    fn peek_or_err(&mut self, err: EvalErr) -> Result<&Token, EvalErr> {
-        self.tokens.peek().map_or_else(|| Err(err), Ok)
+    fn parse_bytevector(&mut self) -> Result<Expr, EvalErr> {
+        let mut bytes = vec![];
+        while self.peek_or_err(EvalErr::UnexpectedEnd)? != &Token::RParen {
+            bytes.push(byte_arg(self.parse_quote()?)?);
+        }
+        self.tokens.next(); // consume remaining paren
+        Ok(Expr::bytevector(bytes))
     }
-}
 
-//TODO: handle EmptyList
-fn let_to_lambda(bindings: Expr, body: Vec<Expr>) -> Result<Vec<Expr>, EvalErr> {
-    match bindings {
-        Expr::Call(bindings) => {
-            let (params, mut values) = try_unzip_list(bindings)?;
-            values.insert(
-                0,
-                Lambda::new(params.to_expr(), body).to_expr().into_call()?,
-            );
-            Ok(values)
+    // Only one expression may follow the dot in an improper list.
+    fn close_dotted(&mut self) -> Result<(), EvalErr> {
+        match self.next_or_err(EvalErr::UnexpectedEnd)? {
+            Token::RParen => Ok(()),
+            t => Err(EvalErr::UnexpectedToken(t.printable())),
         }
-        expr => Err(EvalErr::TypeError("list", expr.clone())),
     }
-}
 
-fn letstar_to_lambda(
-    bindings: &mut std::vec::IntoIter<Expr>,
-    body: Vec<Expr>,
-) -> Result<Expr, EvalErr> {
-    match bindings.next() {
-        Some(binding) => match letstar_to_lambda(bindings, body)? {
-            Expr::Call(body) => make_single_let(binding, body),
-            expr => Err(EvalErr::TypeError("list", expr.clone())),
-        },
-        None => Ok(body.to_expr()),
+    fn next_or_err(&mut self, err: EvalErr) -> Result<Token, EvalErr> {
+        self.tokens.next().map_or_else(|| Err(err), Ok)
     }
-}
 
-fn make_single_let(binding: Expr, body: Vec<Expr>) -> Result<Expr, EvalErr> {
-    match binding {
-        Expr::Call(binding) => {
-            let (param, val) = binding.into_iter().own_two_or_else(|| {
-                EvalErr::InvalidArgs("'let*' expression. expected parameter and value pair")
-            })?;
-
-            Ok(vec![
-                Lambda::new(param.into_call()?, body)
-                    .to_expr()
-                    .into_call()?,
-                val,
-            ]
-            .to_expr())
-        }
-        expr => Err(EvalErr::TypeError("list", expr.clone())),
+    fn peek_or_err(&mut self, err: EvalErr) -> Result<&Token, EvalErr> {
+        self.tokens.peek().map_or_else(|| Err(err), Ok)
     }
 }
 
-fn cond_to_if(exprs: &mut Peekable<std::vec::IntoIter<Expr>>) -> Result<Expr, EvalErr> {
-    match exprs.next() {
-        Some(expr) => match expr {
-            Expr::Call(expr) => {
-                let (predicate, consequence) = expr.into_iter().own_one_and_rest_or_else(|| {
-                    EvalErr::InvalidArgs(
-                        "'cond' expression. clauses expcted two be lists of two values",
-                    )
-                })?;
-
-                let consequence = Begin::new(consequence.collect()).to_expr().into_call()?;
-
-                if exprs.peek().is_some() {
-                    If::new(predicate, consequence, cond_to_if(exprs)?)
-                        .to_expr()
-                        .into_call()
-                } else {
-                    match predicate {
-                        Expr::Atom(Token::Symbol(s)) if s == "else" => Ok(consequence),
-                        _ => If::new(predicate, consequence, cond_to_if(exprs)?)
-                            .to_expr()
-                            .into_call(),
-                    }
-                }
-            }
-            expr => Err(EvalErr::UnexpectedToken(expr.printable())),
-        },
-        None => Ok(Expr::Void),
+// The reader abbreviations `x, ,x and ,@x stand for these forms wrapped around x.
+fn quasi_keyword(token: &Token) -> Symbol {
+    match token {
+        Token::Backquote => Symbol::QUASIQUOTE,
+        Token::Comma => Symbol::UNQUOTE,
+        _ => Symbol::UNQUOTE_SPLICING,
     }
 }
 
-//TODO: handle EmptyList
-fn try_unzip_list(exprs: Vec<Expr>) -> Result<(Vec<Expr>, Vec<Expr>), EvalErr> {
-    exprs
-        .into_iter()
-        .try_fold((vec![], vec![]), |prev, expr_pair| {
-            let (mut params, mut values) = prev;
-            match expr_pair {
-                Expr::Call(binding) => {
-                    let (param, value) = binding.into_iter().own_two_or_else(|| {
-                        EvalErr::InvalidArgs("'let' expression. expected parameter and value pair")
-                    })?;
-                    params.push(param);
-                    values.push(value);
-                    Ok((params, values))
-                }
-                expr => Err(EvalErr::TypeError("list", expr.clone())),
-            }
-        })
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -403,11 +253,11 @@ mod test {
     fn valid_parse() {
         let scm = "1 (+ 1 (+ 1 2))";
         let res: Vec<Expr> = vec![
-            1.0.to_expr(),
+            1.to_expr(),
             vec![
                 "+".to_string().to_expr(),
-                1.0.to_expr(),
-                vec!["+".to_string().to_expr(), 1.0.to_expr(), 2.0.to_expr()].to_expr(),
+                1.to_expr(),
+                vec!["+".to_string().to_expr(), 1.to_expr(), 2.to_expr()].to_expr(),
             ]
             .to_expr(),
         ];
@@ -422,7 +272,7 @@ mod test {
         let res: Vec<Expr> = vec![Expr::Quoted(Box::new(
             Pair::new(
                 "+".to_string().to_expr(),
-                Pair::new(1.0.to_expr(), Expr::EmptyList).to_expr(),
+                Pair::new(1.to_expr(), Expr::EmptyList).to_expr(),
             )
             .to_expr(),
         ))];
@@ -436,8 +286,8 @@ mod test {
         let scm = "(quote (+ 1))";
         let res: Vec<Expr> = vec![Expr::Quoted(Box::new(
             Pair::new(
-                Expr::Atom(Token::Symbol("+".to_string())),
-                Pair::new(1.0.to_expr(), Expr::EmptyList).to_expr(),
+                Expr::Atom(Token::Symbol("+".into())),
+                Pair::new(1.to_expr(), Expr::EmptyList).to_expr(),
             )
             .to_expr(),
         ))];
@@ -446,6 +296,45 @@ mod test {
         assert_eq!(res, exprs);
     }
 
+    #[test]
+    fn quasiquoted() {
+        let scm = "`(a ,b)";
+        let res: Vec<Expr> = vec![vec![
+            "quasiquote".to_expr(),
+            vec![
+                "a".to_expr(),
+                vec!["unquote".to_expr(), "b".to_expr()].to_expr(),
+            ]
+            .to_expr(),
+        ]
+        .to_expr()];
+        let tokens = TokenStream::new(scm).collect_tokens().unwrap();
+        let exprs = Parser::new(tokens).parse().unwrap();
+        assert_eq!(res, exprs);
+    }
+
+    #[test]
+    fn dotted_params() {
+        let scm = "(lambda (a . rest) rest)";
+        let res: Vec<Expr> = vec![vec![
+            "lambda".to_expr(),
+            Pair::new("a".to_expr(), "rest".to_expr()).to_expr(),
+            "rest".to_expr(),
+        ]
+        .to_expr()];
+        let tokens = TokenStream::new(scm).collect_tokens().unwrap();
+        let exprs = Parser::new(tokens).parse().unwrap();
+        assert_eq!(res, exprs);
+    }
+
+    #[test]
+    #[should_panic]
+    fn dotted_extra_tail() {
+        let scm = "(a . b c)";
+        let tokens = TokenStream::new(scm).collect_tokens().unwrap();
+        Parser::new(tokens).parse().unwrap();
+    }
+
     #[test]
     #[should_panic]
     fn extra_paren() {
@@ -0,0 +1,134 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    lexer::Token,
+    parser::Expr,
+    utils::{byte_arg, index_arg, range_args, OwnIterVals, ToExpr},
+};
+
+pub fn make_bytevector(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let k = index_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'make-bytevector'. expected a length"))?,
+    )?;
+    let fill = args.next().map(byte_arg).transpose()?.unwrap_or(0);
+    Ok(Expr::bytevector(vec![fill; k]))
+}
+
+pub fn bytevector(args: Args) -> Result<Expr, EvalErr> {
+    let bytes = args
+        .into_iter()
+        .map(byte_arg)
+        .collect::<Result<Vec<u8>, EvalErr>>()?;
+    Ok(Expr::bytevector(bytes))
+}
+
+pub fn bytevector_length(args: Args) -> Result<Expr, EvalErr> {
+    let b =
+        bytevector_arg(args.into_iter().own_one_or_else(|| {
+            EvalErr::InvalidArgs("'bytevector-length'. expected a bytevector")
+        })?)?;
+    let len = b.borrow().len();
+    Ok((len as i64).to_expr())
+}
+
+pub fn u8_ref(args: Args) -> Result<Expr, EvalErr> {
+    let (b, k) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'bytevector-u8-ref'. expected a bytevector and an index")
+    })?;
+    let (b, k) = (bytevector_arg(b)?, index_arg(k)?);
+    let byte = b.borrow().get(k).copied();
+    byte.map(|byte| i64::from(byte).to_expr())
+        .ok_or(EvalErr::InvalidArgs(
+            "'bytevector-u8-ref'. index out of range",
+        ))
+}
+
+pub fn u8_set(args: Args) -> Result<Expr, EvalErr> {
+    let (b, k, byte) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'bytevector-u8-set!'. expected a bytevector, an index and a byte")
+    })?;
+    let (b, k, byte) = (bytevector_arg(b)?, index_arg(k)?, byte_arg(byte)?);
+    *b.borrow_mut().get_mut(k).ok_or(EvalErr::InvalidArgs(
+        "'bytevector-u8-set!'. index out of range",
+    ))? = byte;
+    Ok(Expr::Void)
+}
+
+pub fn bytevector_copy(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let b =
+        bytevector_arg(args.own_one_or_else(|| {
+            EvalErr::InvalidArgs("'bytevector-copy'. expected a bytevector")
+        })?)?;
+    let bytes = b.borrow();
+    let (start, end) = range_args(args, bytes.len(), "'bytevector-copy'. index out of range")?;
+    Ok(Expr::bytevector(bytes[start..end].to_vec()))
+}
+
+// `(bytevector-copy! to at from [start [end]])` copies part of one bytevector over another, which
+// may be the same one.
+pub fn bytevector_copy_to(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let (to, at, from) = args.own_three_or_else(|| {
+        EvalErr::InvalidArgs("'bytevector-copy!'. expected a bytevector, an index and a bytevector")
+    })?;
+    let (to, at) = (bytevector_arg(to)?, index_arg(at)?);
+    let source = bytevector_arg(from)?.borrow().clone();
+    let (start, end) = range_args(args, source.len(), "'bytevector-copy!'. index out of range")?;
+    let mut target = to.borrow_mut();
+    let dest = target
+        .get_mut(at..at + (end - start))
+        .ok_or(EvalErr::InvalidArgs(
+            "'bytevector-copy!'. index out of range",
+        ))?;
+    dest.copy_from_slice(&source[start..end]);
+    Ok(Expr::Void)
+}
+
+pub fn bytevector_append(args: Args) -> Result<Expr, EvalErr> {
+    let mut bytes = vec![];
+    for b in args {
+        bytes.extend_from_slice(&bytevector_arg(b)?.borrow());
+    }
+    Ok(Expr::bytevector(bytes))
+}
+
+pub fn utf8_to_string(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let b = bytevector_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'utf8->string'. expected a bytevector"))?,
+    )?;
+    let bytes = b.borrow();
+    let (start, end) = range_args(args, bytes.len(), "'utf8->string'. index out of range")?;
+    match std::str::from_utf8(&bytes[start..end]) {
+        Ok(s) => Ok(Expr::Atom(Token::string(s))),
+        Err(_) => Err(EvalErr::InvalidArgs(
+            "'utf8->string'. bytes are not valid UTF-8",
+        )),
+    }
+}
+
+// The range, if given, counts characters of the string rather than bytes.
+pub fn string_to_utf8(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let s =
+        match args.own_one_or_else(|| EvalErr::InvalidArgs("'string->utf8'. expected a string"))? {
+            Expr::Atom(Token::Str(s)) => s,
+            expr => return Err(EvalErr::TypeError("string", expr)),
+        };
+    let chars = s.chars();
+    let (start, end) = range_args(args, chars.len(), "'string->utf8'. index out of range")?;
+    let s: String = chars[start..end].iter().collect();
+    Ok(Expr::bytevector(s.into_bytes()))
+}
+
+pub fn bytevector_arg(expr: Expr) -> Result<Rc<RefCell<Vec<u8>>>, EvalErr> {
+    match expr {
+        Expr::Bytevector(b) => Ok(b),
+        expr => Err(EvalErr::TypeError("bytevector", expr)),
+    }
+}
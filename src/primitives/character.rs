@@ -0,0 +1,118 @@
+use num_traits::ToPrimitive;
+
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    lexer::Token,
+    number::Number,
+    parser::Expr,
+    utils::{IterInnerVal, OwnIterVals, ToExpr},
+};
+
+pub fn char_to_integer(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char->integer'. expected a character")?;
+    Ok(i64::from(u32::from(c)).to_expr())
+}
+
+pub fn integer_to_char(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'integer->char'. expected an integer"))?
+    {
+        Expr::Atom(Token::Number(Number::Integer(n))) => n
+            .to_u32()
+            .and_then(char::from_u32)
+            .map(|c| c.to_expr())
+            .ok_or(EvalErr::InvalidArgs(
+                "'integer->char'. not a unicode scalar value",
+            )),
+        expr => Err(EvalErr::TypeError("exact integer", expr)),
+    }
+}
+
+// Case conversions that would give more than one character, like the German sharp s, leave the
+// character as it is.
+pub fn upcase(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-upcase'. expected a character")?;
+    Ok(single(c.to_uppercase()).unwrap_or(c).to_expr())
+}
+
+pub fn downcase(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-downcase'. expected a character")?;
+    Ok(single(c.to_lowercase()).unwrap_or(c).to_expr())
+}
+
+fn single(mut chars: impl Iterator<Item = char>) -> Option<char> {
+    let c = chars.next()?;
+    chars.next().is_none().then_some(c)
+}
+
+pub fn char_eq(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x == y)
+}
+
+pub fn char_lt(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x < y)
+}
+
+pub fn char_gt(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x > y)
+}
+
+pub fn char_le(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x <= y)
+}
+
+pub fn char_ge(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x >= y)
+}
+
+fn cmp_pairwise(args: Args, cmp: fn(char, char) -> bool) -> Result<Expr, EvalErr> {
+    let chars = args.into_chars()?;
+    if chars.is_empty() {
+        return Err(EvalErr::InvalidArgs(
+            "character comparison requires at least one argument",
+        ));
+    }
+    Ok(chars.windows(2).all(|pair| cmp(pair[0], pair[1])).to_expr())
+}
+
+pub fn alphabetic(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-alphabetic?'. expected a character")?;
+    Ok(c.is_alphabetic().to_expr())
+}
+
+pub fn numeric(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-numeric?'. expected a character")?;
+    Ok(c.is_numeric().to_expr())
+}
+
+pub fn whitespace(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-whitespace?'. expected a character")?;
+    Ok(c.is_whitespace().to_expr())
+}
+
+pub fn upper_case(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-upper-case?'. expected a character")?;
+    Ok(c.is_uppercase().to_expr())
+}
+
+pub fn lower_case(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'char-lower-case?'. expected a character")?;
+    Ok(c.is_lowercase().to_expr())
+}
+
+// The value of a decimal digit, or #f for anything else.
+pub fn digit_value(args: Args) -> Result<Expr, EvalErr> {
+    let c = one_char(args, "'digit-value'. expected a character")?;
+    match c.to_digit(10) {
+        Some(d) => Ok(i64::from(d).to_expr()),
+        None => Ok(false.to_expr()),
+    }
+}
+
+fn one_char(args: Args, err: &'static str) -> Result<char, EvalErr> {
+    args.into_chars()?
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs(err))
+}
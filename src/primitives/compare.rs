@@ -1,7 +1,11 @@
+use std::collections::HashSet;
+use std::rc::Rc;
+
 use crate::{
     error::EvalErr,
     evaluator::Args,
     lexer::Token,
+    number::Number,
     parser::Expr,
     utils::{OwnIterVals, ToExpr},
 };
@@ -16,16 +20,81 @@ pub fn not(args: Args) -> Result<Expr, EvalErr> {
     }
 }
 
+pub fn eqv(args: Args) -> Result<Expr, EvalErr> {
+    let (x, y) = args
+        .into_iter()
+        .own_two_or_else(|| EvalErr::InvalidArgs("'eqv?'. expected two arguments."))?;
+    Ok(is_eqv(&x, &y).to_expr())
+}
+
 pub fn equal(args: Args) -> Result<Expr, EvalErr> {
-    let first_two = args
+    let (x, y) = args
         .into_iter()
         .own_two_or_else(|| EvalErr::InvalidArgs("'equal?'. expected two arguments."))?;
-    match first_two {
+    Ok(is_equal(&x, &y).to_expr())
+}
+
+// Atoms are the same when they have the same value, with numbers also agreeing on exactness.
+// Flonums are compared bit for bit, so 0.0 and -0.0 are told apart while a NaN is the same as
+// itself. Strings and every other object are only the same as themselves.
+pub fn is_eqv(x: &Expr, y: &Expr) -> bool {
+    match (x, y) {
         (
-            Expr::Atom(Token::Str(x) | Token::Symbol(x)),
-            Expr::Atom(Token::Str(y) | Token::Symbol(y)),
-        ) => Ok((x == y).to_expr()),
-        (Expr::Atom(Token::Number(x)), Expr::Atom(Token::Number(y))) => Ok((x == y).to_expr()),
-        _ => Ok(false.to_expr()),
+            Expr::Atom(Token::Number(Number::Real(x))),
+            Expr::Atom(Token::Number(Number::Real(y))),
+        ) => x.to_bits() == y.to_bits(),
+        (Expr::Atom(Token::Str(x)), Expr::Atom(Token::Str(y))) => Rc::ptr_eq(x, y),
+        (Expr::Atom(x), Expr::Atom(y)) => x == y,
+        (Expr::Pair(x), Expr::Pair(y)) => Rc::ptr_eq(x, y),
+        (Expr::Vector(x), Expr::Vector(y)) => Rc::ptr_eq(x, y),
+        (Expr::Bytevector(x), Expr::Bytevector(y)) => Rc::ptr_eq(x, y),
+        (Expr::Port(x), Expr::Port(y)) => Rc::ptr_eq(x, y),
+        (Expr::HashTable(x), Expr::HashTable(y)) => Rc::ptr_eq(x, y),
+        (Expr::RecordType(x), Expr::RecordType(y)) => Rc::ptr_eq(x, y),
+        (Expr::Record(x), Expr::Record(y)) => Rc::ptr_eq(x, y),
+        (Expr::Proc(x), Expr::Proc(y)) => Rc::ptr_eq(x, y),
+        (Expr::Condition(x), Expr::Condition(y)) => Rc::ptr_eq(x, y),
+        (Expr::EmptyList, Expr::EmptyList) | (Expr::Void, Expr::Void) | (Expr::Eof, Expr::Eof) => {
+            true
+        }
+        _ => false,
+    }
+}
+
+// Compares strings, bytevectors, and the contents of pairs and vectors, and anything else as
+// `eqv?` does.
+pub fn is_equal(x: &Expr, y: &Expr) -> bool {
+    equal_contents(x, y, &mut HashSet::new())
+}
+
+// Circular structure is compared by taking any two pairs or vectors that are already being
+// compared to be equal, so that each is only gone through once. If they aren't, the difference
+// turns up somewhere else along the way.
+fn equal_contents(x: &Expr, y: &Expr, compared: &mut HashSet<(usize, usize)>) -> bool {
+    let (mut x, mut y) = (x.clone(), y.clone());
+    // Walks down the cdrs of lists rather than recursing, so long lists don't exhaust the stack.
+    while let (Expr::Pair(p), Expr::Pair(q)) = (&x, &y) {
+        if !compared.insert((Rc::as_ptr(p) as usize, Rc::as_ptr(q) as usize)) {
+            return true;
+        }
+        if !equal_contents(&p.car(), &q.car(), compared) {
+            return false;
+        }
+        (x, y) = (p.cdr(), q.cdr());
+    }
+    match (&x, &y) {
+        (Expr::Atom(Token::Str(x)), Expr::Atom(Token::Str(y))) => x == y,
+        (Expr::Vector(v), Expr::Vector(w)) => {
+            if !compared.insert((Rc::as_ptr(v) as usize, Rc::as_ptr(w) as usize)) {
+                return true;
+            }
+            let (v, w) = (v.items(), w.items());
+            v.len() == w.len()
+                && v.iter()
+                    .zip(w.iter())
+                    .all(|(x, y)| equal_contents(x, y, compared))
+        }
+        (Expr::Bytevector(x), Expr::Bytevector(y)) => x == y,
+        (x, y) => is_eqv(x, y),
     }
 }
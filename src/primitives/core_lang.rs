@@ -1,28 +1,56 @@
-use crate::{error::EvalErr, evaluator, parser::Expr, procedure::Proc, utils::OwnIterVals};
+use std::rc::Rc;
 
-use super::pair::OwnPtrInner;
+use crate::{
+    continuation::{Continuation, Frame, Winder},
+    error::EvalErr,
+    evaluator::{Args, Step},
+    parser::Expr,
+    procedure::Proc,
+    utils::{OwnIterVals, ToExpr},
+};
 
-pub fn apply(args: evaluator::Args) -> Result<Expr, EvalErr> {
+use super::pair::list_to_vec;
+
+// `(apply proc arg ... list)`, where any arguments before the final list are passed first.
+pub fn apply(args: Args, _k: &mut Continuation) -> Result<Step, EvalErr> {
     let env = args.env()?;
-    let (op, args) = args.into_iter().own_two_or_else(|| {
-        EvalErr::InvalidArgs("'apply'. expected operation and list of arguments")
-    })?;
+    let mut args = args.into_iter().collect::<Vec<Expr>>();
+    let (Some(last), false) = (args.pop(), args.is_empty()) else {
+        return Err(EvalErr::InvalidArgs(
+            "'apply'. expected operation and list of arguments",
+        ));
+    };
+    let op = args.remove(0);
 
-    let args = match args {
-        Expr::Call(ls) => Ok(ls),
-        Expr::Pair(p) => Ok(p.inner_to_owned().into_iter().collect()),
-        _ => Err(EvalErr::InvalidArgs(
-            "'apply'. expected list as second argument",
-        )),
-    }?;
+    match last {
+        Expr::Call(ls) => args.extend(ls),
+        list @ Expr::Pair(_) => args.extend(list_to_vec(list)?),
+        Expr::EmptyList => (),
+        _ => {
+            return Err(EvalErr::InvalidArgs(
+                "'apply'. expected list as last argument",
+            ))
+        }
+    };
 
-    let args = evaluator::Args::new(args, &env)?;
+    Ok(Step::Apply(op, Args::new(args, &env)?))
+}
 
-    match op {
-        Expr::Proc(proc) => match proc.as_ref() {
-            Proc::Primitive(proc) => proc.call(args.eval()?),
-            Proc::Compound(proc) => proc.call(args.eval()?),
-        },
-        op => Err(EvalErr::TypeError("procedure", op)),
-    }
+pub fn call_cc(args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+    let env = args.env()?;
+    let receiver = args.into_iter().own_one_or_else(|| {
+        EvalErr::InvalidArgs("'call-with-current-continuation'. expected procedure")
+    })?;
+    let cont = Proc::Continuation(k.clone()).to_expr();
+    Ok(Step::Apply(receiver, Args::new(vec![cont], &env)?))
+}
+
+pub fn dynamic_wind(args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+    let env = args.env()?;
+    let (before, thunk, after) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'dynamic-wind'. expected before, thunk, and after procedures")
+    })?;
+    let winder = Rc::new(Winder::new(before.clone(), after, env.clone_rc()?));
+    k.push(Frame::WindEnter { thunk, winder });
+    Ok(Step::Apply(before, Args::new(vec![], &env)?))
 }
@@ -0,0 +1,66 @@
+use crate::{
+    continuation::{Continuation, Frame, Handler},
+    error::EvalErr,
+    evaluator::{Args, Step},
+    lexer::Token,
+    parser::Expr,
+    utils::{OwnIterVals, ToExpr},
+};
+
+use super::pair::vec_to_list;
+
+pub fn with_exception_handler(args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+    let env = args.env()?;
+    let (handler, thunk) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'with-exception-handler'. expected handler and thunk")
+    })?;
+    let handlers = k
+        .handlers()
+        .with(Handler::Procedure(handler, env.clone_rc()?));
+    k.push(Frame::Handlers(handlers));
+    Ok(Step::Apply(thunk, Args::new(vec![], &env)?))
+}
+
+pub fn raise(args: Args) -> Result<Expr, EvalErr> {
+    let obj = args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'raise'. expected argument"))?;
+    Err(EvalErr::from_raised(obj))
+}
+
+pub fn raise_continuable(args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+    let obj = args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'raise-continuable'. expected argument"))?;
+    k.raise(obj, true)
+}
+
+pub fn error_object(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'error-object?'. expected argument"))?
+    {
+        Expr::Condition(_) => Ok(true.to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
+pub fn error_object_message(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'error-object-message'. expected argument"))?
+    {
+        Expr::Condition(err) => Ok(Expr::Atom(Token::string(err.message()))),
+        x => Err(EvalErr::TypeError("error object", x)),
+    }
+}
+
+pub fn error_object_irritants(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'error-object-irritants'. expected argument"))?
+    {
+        Expr::Condition(err) => Ok(vec_to_list(err.irritants())),
+        x => Err(EvalErr::TypeError("error object", x)),
+    }
+}
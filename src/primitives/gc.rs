@@ -0,0 +1,37 @@
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    heap,
+    parser::Expr,
+    primitives::pair::{vec_to_list, Pair},
+    utils::ToExpr,
+};
+
+// `(gc)` runs a collection straight away, giving the number of objects it freed.
+pub fn gc(args: Args) -> Result<Expr, EvalErr> {
+    if !args.is_empty() {
+        return Err(EvalErr::InvalidArgs("'gc'. expected no arguments"));
+    }
+    Ok((heap::collect() as i64).to_expr())
+}
+
+// An association list of the heap's live object count, the objects allocated so far, and how many
+// collections have been run and objects freed by them.
+pub fn gc_statistics(args: Args) -> Result<Expr, EvalErr> {
+    if !args.is_empty() {
+        return Err(EvalErr::InvalidArgs(
+            "'gc-statistics'. expected no arguments",
+        ));
+    }
+    let stats = heap::stats();
+    let entries = [
+        ("objects", stats.objects),
+        ("allocations", stats.allocations),
+        ("collections", stats.collections),
+        ("collected", stats.collected),
+    ]
+    .into_iter()
+    .map(|(name, n)| Pair::new(name.to_expr(), (n as i64).to_expr()).to_expr())
+    .collect();
+    Ok(vec_to_list(entries))
+}
@@ -0,0 +1,359 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use num_bigint::BigInt;
+use num_rational::BigRational;
+
+use crate::{
+    continuation::Continuation,
+    error::EvalErr,
+    evaluator::{Args, Step},
+    heap::{self, Trace, Tracer},
+    lexer::Token,
+    number::Number,
+    parser::Expr,
+    primitives::{compare, pair::vec_to_list, pair::Pair, string},
+    procedure::{PSig, Proc},
+    symbol::Symbol,
+    utils::{OwnIterVals, ToExpr},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Equivalence {
+    Equal,
+    Eqv,
+    String,
+}
+
+// Each entry keeps the key it was stored under along with its value, so the keys can be handed
+// back out as they were given. Entries are filed under a summary of their key, and the entries
+// filed together are told apart with the table's equivalence.
+#[derive(Debug)]
+pub struct HashTable {
+    equivalence: Equivalence,
+    entries: HashMap<Key, Vec<(Expr, Expr)>>,
+}
+
+// Tables are only ever equal to themselves.
+impl PartialEq for HashTable {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl HashTable {
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn get(&self, key: &Expr) -> Result<Option<&Expr>, EvalErr> {
+        let bucket = self.entries.get(&self.key(key)?);
+        Ok(bucket
+            .and_then(|bucket| bucket.iter().find(|(k, _)| self.equivalence.holds(k, key)))
+            .map(|(_, val)| val))
+    }
+
+    fn insert(&mut self, key: Expr, val: Expr) -> Result<(), EvalErr> {
+        let hashed = self.key(&key)?;
+        let equivalence = self.equivalence;
+        let bucket = self.entries.entry(hashed).or_default();
+        match bucket.iter_mut().find(|(k, _)| equivalence.holds(k, &key)) {
+            Some(entry) => *entry = (key, val),
+            None => bucket.push((key, val)),
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &Expr) -> Result<(), EvalErr> {
+        let hashed = self.key(key)?;
+        let equivalence = self.equivalence;
+        if let Some(bucket) = self.entries.get_mut(&hashed) {
+            bucket.retain(|(k, _)| !equivalence.holds(k, key));
+            if bucket.is_empty() {
+                self.entries.remove(&hashed);
+            }
+        }
+        Ok(())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(Expr, Expr)> {
+        self.entries.values().flatten()
+    }
+
+    fn key(&self, expr: &Expr) -> Result<Key, EvalErr> {
+        let mut size = KEY_SIZE;
+        Key::new(expr, self.equivalence, &mut size)
+    }
+}
+
+impl Equivalence {
+    fn holds(self, x: &Expr, y: &Expr) -> bool {
+        match (self, x, y) {
+            (Equivalence::Equal, x, y) => compare::is_equal(x, y),
+            (Equivalence::Eqv, x, y) => compare::is_eqv(x, y),
+            (Equivalence::String, Expr::Atom(Token::Str(x)), Expr::Atom(Token::Str(y))) => x == y,
+            (Equivalence::String, _, _) => false,
+        }
+    }
+}
+
+impl Trace for RefCell<HashTable> {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(table) = self.try_borrow() {
+            for (key, val) in table.iter() {
+                tracer.expr(key);
+                tracer.expr(val);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut table) = self.try_borrow_mut() {
+            table.entries.clear();
+        }
+    }
+}
+
+// A hashable summary of a value. Two values have the same key whenever the table's equivalence
+// procedure holds for them: `equal?` keys are built from the contents of strings, pairs and
+// vectors, while `eqv?` keys only tell those objects apart by where they live. Only so much of a
+// structure goes into its key, which keeps circular structures from going on forever.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Integer(BigInt),
+    Rational(BigRational),
+    Real(u64),
+    Boolean(bool),
+    Char(char),
+    Symbol(Symbol),
+    Str(String),
+    Bytes(Vec<u8>),
+    List(Vec<Key>, Box<Key>),
+    Vector(Vec<Key>),
+    Object(usize),
+    // Whatever is left of a structure once its key is big enough.
+    Rest,
+    EmptyList,
+    Void,
+    Eof,
+}
+
+// The number of items of pairs and vectors that go into a key.
+const KEY_SIZE: usize = 64;
+
+impl Key {
+    fn new(expr: &Expr, equivalence: Equivalence, size: &mut usize) -> Result<Key, EvalErr> {
+        let structural = equivalence == Equivalence::Equal;
+        Ok(match expr {
+            Expr::Atom(Token::Str(s)) if equivalence != Equivalence::Eqv => Key::Str(s.to_string()),
+            _ if equivalence == Equivalence::String => {
+                return Err(EvalErr::TypeError("string", expr.clone()))
+            }
+            Expr::Atom(Token::Str(s)) => Key::Object(Rc::as_ptr(s) as *const () as usize),
+            Expr::Atom(Token::Number(n)) => match n {
+                Number::Integer(n) => Key::Integer(n.clone()),
+                Number::Rational(n) => Key::Rational(n.clone()),
+                // Zero and negative zero are `=` to each other, so they share a key.
+                Number::Real(n) if *n == 0.0 => Key::Real(0),
+                Number::Real(n) => Key::Real(n.to_bits()),
+            },
+            Expr::Atom(Token::Boolean(b)) => Key::Boolean(*b),
+            Expr::Atom(Token::Char(c)) => Key::Char(*c),
+            Expr::Atom(Token::Symbol(s)) => Key::Symbol(*s),
+            Expr::Pair(_) | Expr::Vector(_) if structural && *size == 0 => Key::Rest,
+            Expr::Pair(p) if structural => {
+                *size -= 1;
+                let mut items = vec![Key::new(&p.car(), equivalence, size)?];
+                let mut rest = p.cdr();
+                while let Expr::Pair(p) = &rest {
+                    if *size == 0 {
+                        break;
+                    }
+                    *size -= 1;
+                    items.push(Key::new(&p.car(), equivalence, size)?);
+                    rest = p.cdr();
+                }
+                Key::List(items, Box::new(Key::new(&rest, equivalence, size)?))
+            }
+            Expr::Vector(v) if structural => {
+                *size -= 1;
+                Key::Vector(
+                    v.items()
+                        .iter()
+                        .map(|item| Key::new(item, equivalence, size))
+                        .collect::<Result<Vec<Key>, EvalErr>>()?,
+                )
+            }
+            Expr::Bytevector(b) if structural => Key::Bytes(b.borrow().clone()),
+            Expr::Pair(p) => Key::Object(Rc::as_ptr(p) as *const () as usize),
+            Expr::Vector(v) => Key::Object(Rc::as_ptr(v) as *const () as usize),
+            Expr::Bytevector(b) => Key::Object(Rc::as_ptr(b) as *const () as usize),
+            Expr::Port(p) => Key::Object(Rc::as_ptr(p) as *const () as usize),
+            Expr::HashTable(t) => Key::Object(Rc::as_ptr(t) as *const () as usize),
+            Expr::RecordType(r) => Key::Object(Rc::as_ptr(r) as *const () as usize),
+            Expr::Record(r) => Key::Object(Rc::as_ptr(r) as *const () as usize),
+            Expr::Proc(p) => Key::Object(Rc::as_ptr(p) as *const () as usize),
+            Expr::Condition(c) => Key::Object(Rc::as_ptr(c) as *const () as usize),
+            Expr::EmptyList => Key::EmptyList,
+            Expr::Void => Key::Void,
+            Expr::Eof => Key::Eof,
+            expr => return Err(EvalErr::TypeError("hashable value", expr.clone())),
+        })
+    }
+}
+
+// `(make-hash-table [equiv])` where the equivalence is one of `equal?`, `eqv?`, `eq?` or
+// `string=?`, defaulting to `equal?`. Keys are filed by a summary that has to agree with the
+// equivalence, so any other procedure is rejected, even one that only wraps one of these.
+pub fn make_hash_table(args: Args) -> Result<Expr, EvalErr> {
+    let equivalence = match args.into_iter().next() {
+        None => Equivalence::Equal,
+        Some(Expr::Proc(p)) => match p.as_ref() {
+            Proc::Primitive(p) if std::ptr::fn_addr_eq(p.inner(), compare::equal as PSig) => {
+                Equivalence::Equal
+            }
+            Proc::Primitive(p) if std::ptr::fn_addr_eq(p.inner(), compare::eqv as PSig) => {
+                Equivalence::Eqv
+            }
+            Proc::Primitive(p) if std::ptr::fn_addr_eq(p.inner(), string::string_eq as PSig) => {
+                Equivalence::String
+            }
+            _ => {
+                return Err(EvalErr::InvalidArgs(
+                    "'make-hash-table'. expected equal?, eqv?, eq? or string=?",
+                ))
+            }
+        },
+        Some(expr) => return Err(EvalErr::TypeError("procedure", expr)),
+    };
+    Ok(table_expr(HashTable {
+        equivalence,
+        entries: HashMap::new(),
+    }))
+}
+
+// Calls the optional thunk when the key is missing, and raises an error when there isn't one.
+pub fn hash_table_ref(args: Args, _k: &mut Continuation) -> Result<Step, EvalErr> {
+    let env = args.env()?;
+    let mut args = args.into_iter();
+    let (table, key) = args.own_two_or_else(|| {
+        EvalErr::InvalidArgs("'hash-table-ref'. expected a hash table and a key")
+    })?;
+    let table = table_arg(table)?;
+    let table = table.borrow();
+    match (table.get(&key)?, args.next()) {
+        (Some(val), _) => Ok(Step::Done(val.clone())),
+        (None, Some(thunk)) => Ok(Step::Apply(thunk, Args::new(vec![], &env)?)),
+        (None, None) => Err(EvalErr::RuntimeException(
+            "hash-table-ref: key not found".to_string(),
+            vec![key],
+        )),
+    }
+}
+
+pub fn hash_table_ref_default(args: Args) -> Result<Expr, EvalErr> {
+    let (table, key, default) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'hash-table-ref/default'. expected a hash table, a key and a default")
+    })?;
+    let table = table_arg(table)?;
+    let table = table.borrow();
+    match table.get(&key)? {
+        Some(val) => Ok(val.clone()),
+        None => Ok(default),
+    }
+}
+
+pub fn hash_table_set(args: Args) -> Result<Expr, EvalErr> {
+    let (table, key, val) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'hash-table-set!'. expected a hash table, a key and a value")
+    })?;
+    let table = table_arg(table)?;
+    table.borrow_mut().insert(key, val)?;
+    Ok(Expr::Void)
+}
+
+pub fn hash_table_delete(args: Args) -> Result<Expr, EvalErr> {
+    let (table, key) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'hash-table-delete!'. expected a hash table and a key")
+    })?;
+    let table = table_arg(table)?;
+    table.borrow_mut().remove(&key)?;
+    Ok(Expr::Void)
+}
+
+pub fn hash_table_contains(args: Args) -> Result<Expr, EvalErr> {
+    let (table, key) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'hash-table-contains?'. expected a hash table and a key")
+    })?;
+    let table = table_arg(table)?;
+    let table = table.borrow();
+    Ok(table.get(&key)?.is_some().to_expr())
+}
+
+pub fn hash_table_count(args: Args) -> Result<Expr, EvalErr> {
+    let table = one_table(args, "'hash-table-count'. expected a hash table")?;
+    let len = table.borrow().len();
+    Ok((len as i64).to_expr())
+}
+
+pub fn hash_table_keys(args: Args) -> Result<Expr, EvalErr> {
+    let table = one_table(args, "'hash-table-keys'. expected a hash table")?;
+    let keys = table.borrow().iter().map(|(k, _)| k.clone()).collect();
+    Ok(vec_to_list(keys))
+}
+
+pub fn hash_table_values(args: Args) -> Result<Expr, EvalErr> {
+    let table = one_table(args, "'hash-table-values'. expected a hash table")?;
+    let vals = table.borrow().iter().map(|(_, v)| v.clone()).collect();
+    Ok(vec_to_list(vals))
+}
+
+pub fn hash_table_to_alist(args: Args) -> Result<Expr, EvalErr> {
+    let table = one_table(args, "'hash-table->alist'. expected a hash table")?;
+    let entries = table
+        .borrow()
+        .iter()
+        .map(|(k, v)| Pair::new(k.clone(), v.clone()).to_expr())
+        .collect();
+    Ok(vec_to_list(entries))
+}
+
+pub fn hash_table_copy(args: Args) -> Result<Expr, EvalErr> {
+    let table = one_table(args, "'hash-table-copy'. expected a hash table")?;
+    let table = table.borrow();
+    Ok(table_expr(HashTable {
+        equivalence: table.equivalence,
+        entries: table.entries.clone(),
+    }))
+}
+
+pub fn hash_table_clear(args: Args) -> Result<Expr, EvalErr> {
+    let table = one_table(args, "'hash-table-clear!'. expected a hash table")?;
+    table.borrow_mut().entries.clear();
+    Ok(Expr::Void)
+}
+
+fn table_expr(table: HashTable) -> Expr {
+    let table = Rc::new(RefCell::new(table));
+    heap::track(&table);
+    Expr::HashTable(table)
+}
+
+fn table_arg(expr: Expr) -> Result<Rc<RefCell<HashTable>>, EvalErr> {
+    match expr {
+        Expr::HashTable(t) => Ok(t),
+        expr => Err(EvalErr::TypeError("hash table", expr)),
+    }
+}
+
+fn one_table(args: Args, err: &'static str) -> Result<Rc<RefCell<HashTable>>, EvalErr> {
+    table_arg(
+        args.into_iter()
+            .own_one_or_else(|| EvalErr::InvalidArgs(err))?,
+    )
+}
@@ -11,11 +11,14 @@ pub fn display(args: Args) -> Result<Expr, EvalErr> {
 }
 
 pub fn error(args: Args) -> Result<Expr, EvalErr> {
-    let expr = args
+    let (expr, irritants) = args
         .into_iter()
-        .own_one_or_else(|| EvalErr::InvalidArgs("'error'. expected argument"))?;
+        .own_one_and_rest_or_else(|| EvalErr::InvalidArgs("'error'. expected argument"))?;
     match expr {
-        Expr::Atom(Token::Str(msg)) => Err(EvalErr::RuntimeException(msg)),
+        Expr::Atom(Token::Str(msg)) => Err(EvalErr::RuntimeException(
+            msg.to_string(),
+            irritants.collect(),
+        )),
         _ => Err(EvalErr::InvalidArgs(
             "'error'. expected string as argument.",
         )),
@@ -1,7 +1,16 @@
+pub mod bytevector;
+pub mod character;
 pub mod compare;
 pub mod core_lang;
+pub mod exception;
+pub mod gc;
+pub mod hash_table;
 pub mod io;
 pub mod numeric;
 pub mod pair;
+pub mod port;
 pub mod prelude;
+pub mod record;
+pub mod string;
 pub mod typecheck;
+pub mod vector;
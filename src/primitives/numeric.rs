@@ -1,24 +1,34 @@
-use core::f64;
+use std::cmp::Ordering;
 
 use crate::{
     error::EvalErr,
     evaluator::Args,
+    number::{IntegerDiv, Number},
     parser::Expr,
-    utils::{IterInnerVal, OwnIterVals, SoftIter, ToExpr},
+    utils::{IterInnerVal, OwnIterVals, ToExpr},
 };
 
 pub fn add(args: Args) -> Result<Expr, EvalErr> {
-    Ok(args.into_nums()?.iter().sum::<f64>().to_expr())
+    let sum = args
+        .into_nums()?
+        .iter()
+        .fold(Number::from(0), |sum, num| sum.add(num));
+    Ok(sum.to_expr())
 }
 
 pub fn multiply(args: Args) -> Result<Expr, EvalErr> {
-    Ok(args.into_nums()?.iter().product::<f64>().to_expr())
+    let product = args
+        .into_nums()?
+        .iter()
+        .fold(Number::from(1), |product, num| product.mul(num));
+    Ok(product.to_expr())
 }
 
 pub fn subtract(args: Args) -> Result<Expr, EvalErr> {
     let mut nums = args.into_nums()?.into_iter();
     match nums.next() {
-        Some(first) => Ok(nums.fold(first, |diff, num| diff - num).to_expr()),
+        Some(first) if nums.len() == 0 => Ok(Number::from(0).sub(&first).to_expr()),
+        Some(first) => Ok(nums.fold(first, |diff, num| diff.sub(&num)).to_expr()),
         None => Err(EvalErr::InvalidArgs(
             "'-'. procedure requires at least one argument",
         )),
@@ -28,7 +38,8 @@ pub fn subtract(args: Args) -> Result<Expr, EvalErr> {
 pub fn divide(args: Args) -> Result<Expr, EvalErr> {
     let mut nums = args.into_nums()?.into_iter();
     match nums.next() {
-        Some(first) => Ok(nums.fold(first, |quot, num| quot / num).to_expr()),
+        Some(first) if nums.len() == 0 => Ok(Number::from(1).div(&first)?.to_expr()),
+        Some(first) => Ok(nums.try_fold(first, |quot, num| quot.div(&num))?.to_expr()),
         None => Err(EvalErr::InvalidArgs(
             "'/'. procedure requires at least one argument",
         )),
@@ -36,57 +47,129 @@ pub fn divide(args: Args) -> Result<Expr, EvalErr> {
 }
 
 pub fn equality(args: Args) -> Result<Expr, EvalErr> {
-    let nums: Vec<f64> = args.into_nums()?;
-    match nums.first() {
-        Some(first) => Ok(nums.iter().all(|num| num == first).to_expr()),
-        None => Err(EvalErr::InvalidArgs(
-            "'='. procedure requires at least one argument",
-        )),
-    }
+    cmp_pairwise(
+        args,
+        "'='. procedure requires at least one argument",
+        |ord| ord == Ordering::Equal,
+    )
+}
+
+pub fn quotient(args: Args) -> Result<Expr, EvalErr> {
+    integer_div(
+        args,
+        IntegerDiv::Quotient,
+        "'quotient'. procedure requires two arguments",
+    )
 }
 
 pub fn remainder(args: Args) -> Result<Expr, EvalErr> {
-    let (x, y) = args.into_nums()?.into_iter().own_two_or_else(|| {
-        EvalErr::InvalidArgs("'remainder'. procedure requires two arguments")
-    })?;
-    Ok((x % y).to_expr())
+    integer_div(
+        args,
+        IntegerDiv::Remainder,
+        "'remainder'. procedure requires two arguments",
+    )
 }
 
 pub fn modulo(args: Args) -> Result<Expr, EvalErr> {
+    integer_div(
+        args,
+        IntegerDiv::Modulo,
+        "'modulo'. procedure requires two arguments",
+    )
+}
+
+fn integer_div(args: Args, op: IntegerDiv, err: &'static str) -> Result<Expr, EvalErr> {
     let (x, y) = args
         .into_nums()?
         .into_iter()
-        .own_two_or_else(|| EvalErr::InvalidArgs("'modulo'. procedure requires two arguments"))?;
-    Ok(((x % y + y) % y).to_expr())
+        .own_two_or_else(|| EvalErr::InvalidArgs(err))?;
+    Ok(x.integer_div(&y, op)?.to_expr())
 }
 
 pub fn greater_than(args: Args) -> Result<Expr, EvalErr> {
-    cmp_first_to_rest(args, |first, rest| first > rest)
+    cmp_pairwise(
+        args,
+        "'>'. procedure requires at least one argument",
+        |ord| ord == Ordering::Greater,
+    )
 }
 
 pub fn greater_than_or_eq(args: Args) -> Result<Expr, EvalErr> {
-    cmp_first_to_rest(args, |first, rest| first >= rest)
+    cmp_pairwise(
+        args,
+        "'>='. procedure requires at least one argument",
+        |ord| ord != Ordering::Less,
+    )
 }
 
 pub fn less_than(args: Args) -> Result<Expr, EvalErr> {
-    cmp_first_to_rest(args, |first, rest| first < rest)
+    cmp_pairwise(
+        args,
+        "'<'. procedure requires at least one argument",
+        |ord| ord == Ordering::Less,
+    )
 }
 
 pub fn less_than_or_eq(args: Args) -> Result<Expr, EvalErr> {
-    cmp_first_to_rest(args, |first, rest| first <= rest)
+    cmp_pairwise(
+        args,
+        "'<='. procedure requires at least one argument",
+        |ord| ord != Ordering::Greater,
+    )
 }
 
-fn cmp_first_to_rest<F>(args: Args, cmp: F) -> Result<Expr, EvalErr>
+// Comparisons hold when they hold for every pair of neighbouring arguments. Nothing compares to
+// NaN, so any comparison involving one is false.
+fn cmp_pairwise<F>(args: Args, err: &'static str, cmp: F) -> Result<Expr, EvalErr>
 where
-    F: Fn(f64, f64) -> bool,
+    F: Fn(Ordering) -> bool,
 {
-    let mut nums = args.into_nums()?.into_iter().peekable();
-    let err = EvalErr::InvalidArgs("Procedure requires at least two arguments");
-    match nums.next() {
-        Some(first) => {
-            let sum_rest = nums.has_next().ok_or(err)?.sum();
-            Ok(cmp(first, sum_rest).to_expr())
-        }
-        None => Err(err),
+    let nums = args.into_nums()?;
+    if nums.is_empty() {
+        return Err(EvalErr::InvalidArgs(err));
     }
+    let holds = nums
+        .windows(2)
+        .all(|pair| pair[0].compare(&pair[1]).is_some_and(&cmp));
+    Ok(holds.to_expr())
+}
+
+pub fn exact(args: Args) -> Result<Expr, EvalErr> {
+    Ok(one_num(args, "'exact'. expected a number")?
+        .exact()?
+        .to_expr())
+}
+
+pub fn inexact(args: Args) -> Result<Expr, EvalErr> {
+    Ok(one_num(args, "'inexact'. expected a number")?
+        .inexact()
+        .to_expr())
+}
+
+pub fn is_exact(args: Args) -> Result<Expr, EvalErr> {
+    Ok(one_num(args, "'exact?'. expected a number")?
+        .is_exact()
+        .to_expr())
+}
+
+pub fn is_inexact(args: Args) -> Result<Expr, EvalErr> {
+    Ok((!one_num(args, "'inexact?'. expected a number")?.is_exact()).to_expr())
+}
+
+pub fn numerator(args: Args) -> Result<Expr, EvalErr> {
+    Ok(one_num(args, "'numerator'. expected a number")?
+        .numerator()?
+        .to_expr())
+}
+
+pub fn denominator(args: Args) -> Result<Expr, EvalErr> {
+    Ok(one_num(args, "'denominator'. expected a number")?
+        .denominator()?
+        .to_expr())
+}
+
+fn one_num(args: Args, err: &'static str) -> Result<Number, EvalErr> {
+    args.into_nums()?
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs(err))
 }
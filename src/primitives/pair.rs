@@ -1,5 +1,5 @@
+use std::cell::RefCell;
 use std::iter::IntoIterator;
-use std::mem;
 use std::rc::Rc;
 use std::vec;
 
@@ -7,45 +7,75 @@ use core::iter::Peekable;
 
 use crate::error::EvalErr;
 use crate::evaluator::Args;
+use crate::heap::{Trace, Tracer};
 use crate::parser::Expr;
 use crate::utils::{OwnIterVals, ToExpr};
 
+// The car and cdr are cells so that `set-car!` and `set-cdr!` can change a pair in place, with
+// the change seen through every reference to it.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Pair {
-    pub car: Expr,
-    pub cdr: Expr,
+    car: RefCell<Expr>,
+    cdr: RefCell<Expr>,
 }
 
 impl Pair {
     pub fn new(car: Expr, cdr: Expr) -> Pair {
-        Pair { car, cdr }
+        Pair {
+            car: RefCell::new(car),
+            cdr: RefCell::new(cdr),
+        }
     }
 
-    pub fn try_list(&self) -> MaybeList {
-        match self.check_if_list() {
-            Some(ls) => MaybeList::List(ls),
-            None => MaybeList::Pair(self),
-        }
+    pub fn car(&self) -> Expr {
+        self.car.borrow().clone()
     }
 
-    fn check_if_list(&self) -> Option<PairList> {
-        match &self.cdr {
-            Expr::Pair(next) => {
-                let cdr = next.check_if_list()?;
-                let node = Some(Box::new(Node::new(&self.car, cdr)));
-                Some(node)
-            }
-            Expr::EmptyList => {
-                let node = Some(Box::new(Node::new(&self.car, None)));
-                Some(node)
+    pub fn cdr(&self) -> Expr {
+        self.cdr.borrow().clone()
+    }
+
+    pub fn set_car(&self, car: Expr) {
+        self.car.replace(car);
+    }
+
+    pub fn set_cdr(&self, cdr: Expr) {
+        self.cdr.replace(cdr);
+    }
+
+    // The items of the list starting at this pair, or None when it doesn't end in the empty list.
+    // A circular list never ends, so a tortoise is left on a pair for the hare walking the list to
+    // run into, moving up to the hare each time it has gone twice as far as the last time.
+    pub fn to_vec(&self) -> Option<Vec<Expr>> {
+        let mut items = vec![self.car()];
+        let mut rest = self.cdr();
+        let mut tortoise: Option<Rc<Pair>> = None;
+        let mut lap = 1;
+        loop {
+            match rest {
+                Expr::Pair(next) => {
+                    if tortoise
+                        .as_ref()
+                        .is_some_and(|pair| Rc::ptr_eq(pair, &next))
+                    {
+                        return None;
+                    }
+                    if items.len() == lap {
+                        tortoise = Some(Rc::clone(&next));
+                        lap *= 2;
+                    }
+                    items.push(next.car());
+                    rest = next.cdr();
+                }
+                Expr::EmptyList => return Some(items),
+                _ => return None,
             }
-            _ => None,
         }
     }
 
     fn pop(&mut self) -> Option<Expr> {
-        let current = mem::replace(&mut self.car, Expr::EmptyList);
-        let next = mem::replace(&mut self.cdr, Expr::EmptyList);
+        let current = self.car.replace(Expr::EmptyList);
+        let next = self.cdr.replace(Expr::EmptyList);
         match next {
             Expr::Pair(next) => {
                 let next = next.inner_to_owned();
@@ -53,8 +83,7 @@ impl Pair {
                 self.cdr = next.cdr;
             }
             x => {
-                self.car = x;
-                self.cdr = Expr::EmptyList;
+                self.car = RefCell::new(x);
             }
         };
         match current {
@@ -64,6 +93,24 @@ impl Pair {
     }
 }
 
+impl Trace for Pair {
+    fn trace(&self, tracer: &mut Tracer) {
+        for cell in [&self.car, &self.cdr] {
+            if let Ok(expr) = cell.try_borrow() {
+                tracer.expr(&expr);
+            }
+        }
+    }
+
+    fn clear(&self) {
+        for cell in [&self.car, &self.cdr] {
+            if let Ok(mut expr) = cell.try_borrow_mut() {
+                *expr = Expr::EmptyList;
+            }
+        }
+    }
+}
+
 pub trait OwnPtrInner<T: Clone> {
     fn inner_to_owned(self) -> T;
 }
@@ -96,40 +143,21 @@ impl IntoIterator for Pair {
     }
 }
 
-type PairList<'a> = Option<Box<Node<'a>>>;
-
-pub enum MaybeList<'a> {
-    List(PairList<'a>),
-    Pair(&'a Pair),
-}
-
-pub struct Node<'a> {
-    pub car: &'a Expr,
-    pub cdr: PairList<'a>,
-}
-
-impl<'a> Node<'a> {
-    fn new(car: &'a Expr, cdr: PairList<'a>) -> Self {
-        Node { car, cdr }
-    }
-
-    pub fn iter(&self) -> Iter {
-        Iter { next: Some(self) }
-    }
-}
-
-pub struct Iter<'a> {
-    next: Option<&'a Node<'a>>,
-}
-
-impl<'a> Iterator for Iter<'a> {
-    type Item = &'a Expr;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        self.next.take().map(|next| {
-            self.next = next.cdr.as_deref();
-            next.car
-        })
+pub fn vec_to_list(exprs: Vec<Expr>) -> Expr {
+    exprs
+        .into_iter()
+        .rev()
+        .fold(Expr::EmptyList, |cdr, car| Pair::new(car, cdr).to_expr())
+}
+
+// The items of a proper list, for primitives that take one as an argument.
+pub fn list_to_vec(list: Expr) -> Result<Vec<Expr>, EvalErr> {
+    match &list {
+        Expr::Pair(p) => p
+            .to_vec()
+            .ok_or_else(|| EvalErr::TypeError("proper list", list.clone())),
+        Expr::EmptyList => Ok(vec![]),
+        _ => Err(EvalErr::TypeError("list", list)),
     }
 }
 
@@ -147,7 +175,7 @@ pub fn car(args: Args) -> Result<Expr, EvalErr> {
         .own_one_or_else(|| EvalErr::InvalidArgs("'car'. expected argument"))?;
 
     match expr {
-        Expr::Pair(p) => Ok(p.inner_to_owned().car),
+        Expr::Pair(p) => Ok(p.car()),
         Expr::EmptyList => Err(EvalErr::InvalidArgs("cannot access car of empty list")),
         x => Err(EvalErr::TypeError("pair", x)),
     }
@@ -159,25 +187,22 @@ pub fn cdr(args: Args) -> Result<Expr, EvalErr> {
         .own_one_or_else(|| EvalErr::InvalidArgs("'cdr'. expected argument"))?;
 
     match expr {
-        Expr::Pair(p) => Ok(p.inner_to_owned().cdr),
+        Expr::Pair(p) => Ok(p.cdr()),
         Expr::EmptyList => Err(EvalErr::InvalidArgs("cannot access cdr of empty list")),
         x => Err(EvalErr::TypeError("pair", x)),
     }
 }
 
-// TODO: UNSAFE!!! really need to think about this more lol. maybe use rc<refcell> but that could
-// complicate a lot of other things... particularly printing on lists unless we just clone the list
-// and consume the clone to print it which actually is totally fine.
 pub fn set_car(args: Args) -> Result<Expr, EvalErr> {
     let (target, source) = args
         .into_iter()
         .own_two_or_else(|| EvalErr::InvalidArgs("'car'. expected argument"))?;
 
     match target {
-        Expr::Pair(p) => unsafe {
-            (*(Rc::into_raw(p) as *mut Pair)).car = source;
+        Expr::Pair(p) => {
+            p.set_car(source);
             Ok(Expr::Void)
-        },
+        }
         expr => Err(EvalErr::TypeError("pair", expr)),
     }
 }
@@ -188,10 +213,10 @@ pub fn set_cdr(args: Args) -> Result<Expr, EvalErr> {
         .own_two_or_else(|| EvalErr::InvalidArgs("'cdr'. expected argument"))?;
 
     match target {
-        Expr::Pair(p) => unsafe {
-            (*(Rc::into_raw(p) as *mut Pair)).cdr = source;
+        Expr::Pair(p) => {
+            p.set_cdr(source);
             Ok(Expr::Void)
-        },
+        }
         // Expr::EmptyList => Ok(Pair::new(first, second).to_expr())
         expr => Err(EvalErr::TypeError("pair", expr)),
     }
@@ -212,3 +237,15 @@ pub fn list(args: Args) -> Result<Expr, EvalErr> {
 
     Ok(map_to_list(first, rest.peekable()))
 }
+
+// Every list but the last is copied, the last one becomes the tail of the result as it is.
+pub fn append(args: Args) -> Result<Expr, EvalErr> {
+    let mut lists = args.into_iter().rev();
+    let last = lists.next().unwrap_or(Expr::EmptyList);
+    lists.try_fold(last, |tail, list| {
+        Ok(list_to_vec(list)?
+            .into_iter()
+            .rev()
+            .fold(tail, |cdr, car| Pair::new(car, cdr).to_expr()))
+    })
+}
@@ -0,0 +1,222 @@
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::rc::Rc;
+
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    lexer::Token,
+    parser::Expr,
+    primitives::bytevector::bytevector_arg,
+    utils::{byte_arg, index_arg, range_args, OwnIterVals, ToExpr},
+};
+
+// Binary ports. An input port holds all of its bytes up front, whether they come from a
+// bytevector or a file, and reads through them with a cursor.
+#[derive(Debug)]
+pub enum Port {
+    Input { bytes: Vec<u8>, pos: usize },
+    Output(Vec<u8>),
+    File(BufWriter<File>),
+    Closed,
+}
+
+// Ports are only ever equal to themselves.
+impl PartialEq for Port {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Port {
+    fn input(&mut self) -> Result<(&[u8], &mut usize), EvalErr> {
+        match self {
+            Port::Input { bytes, pos } => Ok((bytes, pos)),
+            _ => Err(EvalErr::InvalidArgs("expected an open input port")),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<(), EvalErr> {
+        match self {
+            Port::Output(buf) => buf.write_all(bytes).map_err(io_err),
+            Port::File(file) => file.write_all(bytes).map_err(io_err),
+            _ => Err(EvalErr::InvalidArgs("expected an open output port")),
+        }
+    }
+}
+
+fn io_err(err: std::io::Error) -> EvalErr {
+    EvalErr::IoError(err.to_string())
+}
+
+fn port_expr(port: Port) -> Expr {
+    Expr::Port(Rc::new(RefCell::new(port)))
+}
+
+pub fn open_input_bytevector(args: Args) -> Result<Expr, EvalErr> {
+    let b = bytevector_arg(args.into_iter().own_one_or_else(|| {
+        EvalErr::InvalidArgs("'open-input-bytevector'. expected a bytevector")
+    })?)?;
+    let bytes = b.borrow().clone();
+    Ok(port_expr(Port::Input { bytes, pos: 0 }))
+}
+
+pub fn open_output_bytevector(_: Args) -> Result<Expr, EvalErr> {
+    Ok(port_expr(Port::Output(vec![])))
+}
+
+pub fn get_output_bytevector(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'get-output-bytevector'. expected a port")?;
+    let port = port.borrow();
+    match &*port {
+        Port::Output(bytes) => Ok(Expr::bytevector(bytes.clone())),
+        _ => Err(EvalErr::InvalidArgs(
+            "'get-output-bytevector'. expected a bytevector output port",
+        )),
+    }
+}
+
+pub fn open_binary_input_file(args: Args) -> Result<Expr, EvalErr> {
+    let path = path_arg(args, "'open-binary-input-file'. expected a file name")?;
+    let bytes = fs::read(path).map_err(io_err)?;
+    Ok(port_expr(Port::Input { bytes, pos: 0 }))
+}
+
+pub fn open_binary_output_file(args: Args) -> Result<Expr, EvalErr> {
+    let path = path_arg(args, "'open-binary-output-file'. expected a file name")?;
+    let file = File::create(path).map_err(io_err)?;
+    Ok(port_expr(Port::File(BufWriter::new(file))))
+}
+
+// Flushes anything still buffered for a file, closing an already closed port does nothing.
+pub fn close_port(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'close-port'. expected a port")?;
+    let closed = std::mem::replace(&mut *port.borrow_mut(), Port::Closed);
+    if let Port::File(mut file) = closed {
+        file.flush().map_err(io_err)?;
+    }
+    Ok(Expr::Void)
+}
+
+pub fn read_u8(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'read-u8'. expected a port")?;
+    let mut port = port.borrow_mut();
+    let (bytes, pos) = port.input()?;
+    match bytes.get(*pos).copied() {
+        Some(byte) => {
+            *pos += 1;
+            Ok(i64::from(byte).to_expr())
+        }
+        None => Ok(Expr::Eof),
+    }
+}
+
+pub fn peek_u8(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'peek-u8'. expected a port")?;
+    let mut port = port.borrow_mut();
+    let (bytes, pos) = port.input()?;
+    match bytes.get(*pos) {
+        Some(byte) => Ok(i64::from(*byte).to_expr()),
+        None => Ok(Expr::Eof),
+    }
+}
+
+// Every byte of an input port is already in memory, so reading never blocks.
+pub fn u8_ready(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'u8-ready?'. expected a port")?;
+    port.borrow_mut().input()?;
+    Ok(true.to_expr())
+}
+
+// Reads up to `k` bytes, or gives the eof object when there are none left.
+pub fn read_bytevector(args: Args) -> Result<Expr, EvalErr> {
+    let (k, port) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'read-bytevector'. expected a length and a port")
+    })?;
+    let (k, port) = (index_arg(k)?, port_arg(port)?);
+    let mut port = port.borrow_mut();
+    let (bytes, pos) = port.input()?;
+    if k > 0 && *pos == bytes.len() {
+        return Ok(Expr::Eof);
+    }
+    let end = bytes.len().min(*pos + k);
+    let read = bytes[*pos..end].to_vec();
+    *pos = end;
+    Ok(Expr::bytevector(read))
+}
+
+pub fn write_u8(args: Args) -> Result<Expr, EvalErr> {
+    let (byte, port) = args
+        .into_iter()
+        .own_two_or_else(|| EvalErr::InvalidArgs("'write-u8'. expected a byte and a port"))?;
+    let (byte, port) = (byte_arg(byte)?, port_arg(port)?);
+    port.borrow_mut().write(&[byte])?;
+    Ok(Expr::Void)
+}
+
+pub fn write_bytevector(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let (b, port) = args.own_two_or_else(|| {
+        EvalErr::InvalidArgs("'write-bytevector'. expected a bytevector and a port")
+    })?;
+    let (b, port) = (bytevector_arg(b)?, port_arg(port)?);
+    let bytes = b.borrow().clone();
+    let (start, end) = range_args(args, bytes.len(), "'write-bytevector'. index out of range")?;
+    port.borrow_mut().write(&bytes[start..end])?;
+    Ok(Expr::Void)
+}
+
+pub fn eof_object(_: Args) -> Result<Expr, EvalErr> {
+    Ok(Expr::Eof)
+}
+
+pub fn is_eof_object(args: Args) -> Result<Expr, EvalErr> {
+    let expr = args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'eof-object?' expected argument"))?;
+    Ok((expr == Expr::Eof).to_expr())
+}
+
+pub fn is_port(args: Args) -> Result<Expr, EvalErr> {
+    let expr = args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'port?' expected argument"))?;
+    Ok(matches!(expr, Expr::Port(_)).to_expr())
+}
+
+pub fn is_input_port(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'input-port?'. expected a port")?;
+    let is_input = matches!(*port.borrow(), Port::Input { .. });
+    Ok(is_input.to_expr())
+}
+
+pub fn is_output_port(args: Args) -> Result<Expr, EvalErr> {
+    let port = one_port(args, "'output-port?'. expected a port")?;
+    let is_output = matches!(*port.borrow(), Port::Output(_) | Port::File(_));
+    Ok(is_output.to_expr())
+}
+
+fn port_arg(expr: Expr) -> Result<Rc<RefCell<Port>>, EvalErr> {
+    match expr {
+        Expr::Port(p) => Ok(p),
+        expr => Err(EvalErr::TypeError("port", expr)),
+    }
+}
+
+fn one_port(args: Args, err: &'static str) -> Result<Rc<RefCell<Port>>, EvalErr> {
+    port_arg(
+        args.into_iter()
+            .own_one_or_else(|| EvalErr::InvalidArgs(err))?,
+    )
+}
+
+fn path_arg(args: Args, err: &'static str) -> Result<String, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs(err))?
+    {
+        Expr::Atom(Token::Str(path)) => Ok(path.to_string()),
+        expr => Err(EvalErr::TypeError("string", expr)),
+    }
+}
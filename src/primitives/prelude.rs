@@ -1,10 +1,128 @@
+pub const SYNTAX: &str = "
+;; derived expression types, written in terms of the core special forms
+
+(define-syntax let
+  (syntax-rules ()
+    ((_ ((name val) ...) body1 body2 ...)
+     ((lambda (name ...) body1 body2 ...) val ...))
+    ((_ tag ((name val) ...) body1 body2 ...)
+     ((letrec ((tag (lambda (name ...) body1 body2 ...))) tag)
+      val ...))))
+
+(define-syntax let*
+  (syntax-rules ()
+    ((_ () body1 body2 ...)
+     (let () body1 body2 ...))
+    ((_ ((name1 val1) (name2 val2) ...) body1 body2 ...)
+     (let ((name1 val1))
+       (let* ((name2 val2) ...) body1 body2 ...)))))
+
+;; internal definitions already bind every name before any of the values are evaluated, and
+;; letrec may initialise in order just like letrec*
+(define-syntax letrec*
+  (syntax-rules ()
+    ((_ ((name val) ...) body1 body2 ...)
+     (let () (define name val) ... (let () body1 body2 ...)))))
+
+(define-syntax letrec
+  (syntax-rules ()
+    ((_ ((name val) ...) body1 body2 ...)
+     (letrec* ((name val) ...) body1 body2 ...))))
+
+(define-syntax cond
+  (syntax-rules (else =>)
+    ((_ (else result1 result2 ...))
+     (begin result1 result2 ...))
+    ((_ (test => receiver))
+     (let ((temp test))
+       (if temp (receiver temp))))
+    ((_ (test => receiver) clause1 clause2 ...)
+     (let ((temp test))
+       (if temp
+           (receiver temp)
+           (cond clause1 clause2 ...))))
+    ((_ (test))
+     test)
+    ((_ (test) clause1 clause2 ...)
+     (or test (cond clause1 clause2 ...)))
+    ((_ (test result1 result2 ...))
+     (if test (begin result1 result2 ...)))
+    ((_ (test result1 result2 ...) clause1 clause2 ...)
+     (if test
+         (begin result1 result2 ...)
+         (cond clause1 clause2 ...)))))
+
+(define-syntax case
+  (syntax-rules (else =>)
+    ((_ (key ...) clause1 clause2 ...)
+     (let ((atom-key (key ...)))
+       (case atom-key clause1 clause2 ...)))
+    ((_ key (else => receiver))
+     (receiver key))
+    ((_ key (else result1 result2 ...))
+     (begin result1 result2 ...))
+    ((_ key ((atoms ...) => receiver))
+     (if (or (eqv? key 'atoms) ...)
+         (receiver key)))
+    ((_ key ((atoms ...) => receiver) clause1 clause2 ...)
+     (if (or (eqv? key 'atoms) ...)
+         (receiver key)
+         (case key clause1 clause2 ...)))
+    ((_ key ((atoms ...) result1 result2 ...))
+     (if (or (eqv? key 'atoms) ...)
+         (begin result1 result2 ...)))
+    ((_ key ((atoms ...) result1 result2 ...) clause1 clause2 ...)
+     (if (or (eqv? key 'atoms) ...)
+         (begin result1 result2 ...)
+         (case key clause1 clause2 ...)))))
+
+(define-syntax when
+  (syntax-rules ()
+    ((_ test result1 result2 ...)
+     (if test (begin result1 result2 ...)))))
+
+(define-syntax unless
+  (syntax-rules ()
+    ((_ test result1 result2 ...)
+     (if test (if #f #f) (begin result1 result2 ...)))))
+
+(define-syntax do
+  (syntax-rules ()
+    ((_ ((var init step ...) ...) (test expr ...) command ...)
+     (let loop ((var init) ...)
+       (if test
+           (begin (if #f #f) expr ...)
+           (begin command ... (loop (do \"step\" var step ...) ...)))))
+    ((_ \"step\" x) x)
+    ((_ \"step\" x y) y)))
+
+;; records, each field is given as (field accessor) or (field accessor modifier)
+(define-syntax define-record-type
+  (syntax-rules ()
+    ((_ type (constructor cfield ...) pred (field accessor . modifier) ...)
+     (begin
+       (define type (make-record-type 'type '(field ...)))
+       (define (constructor cfield ...) (make-record type '(cfield ...) cfield ...))
+       (define (pred obj) (record-is? type obj))
+       (define-record-field type field accessor . modifier) ...))))
+
+(define-syntax define-record-field
+  (syntax-rules ()
+    ((_ type field accessor)
+     (define (accessor obj) (record-ref type obj 'field)))
+    ((_ type field accessor modifier)
+     (begin
+       (define (accessor obj) (record-ref type obj 'field))
+       (define (modifier obj val) (record-set! type obj 'field val))))))
+";
+
 pub const PRELUDE: &str = "
 ;; some functions are from:
 ;; https://en.wikibooks.org/wiki/Write_Yourself_a_Scheme_in_48_Hours/Towards_a_Standard_Library
 
 (define (id x) x)
-(define (curry func arg1) (lambda (arg) (apply func (cons arg1 arg))))
-(define (compose f g) (lambda (arg) (f (apply g arg))))
+(define (curry func arg1) (lambda args (apply func (cons arg1 args))))
+(define (compose f g) (lambda args (f (apply g args))))
 
 ;; folds
 (define (foldr func end lst)
@@ -41,6 +159,34 @@ pub const PRELUDE: &str = "
 (define (list-head lst n) (if (<= n 0) '() (cons (car lst) (list-head (cdr lst) (- n 1)))))
 
 
+;; vectors, which are walked up to the end of the shortest one
+(define (vector-for-each func vec . vecs)
+  (let* ((vecs (cons vec vecs))
+         (len (fold (lambda (len v) (if (< (vector-length v) len) (vector-length v) len))
+                    (vector-length vec)
+                    vecs)))
+    (do ((i 0 (+ i 1)))
+        ((= i len))
+      (apply func (map (lambda (v) (vector-ref v i)) vecs)))))
+(define (vector-map func vec . vecs)
+  (let ((items '()))
+    (apply vector-for-each
+           (lambda args (set! items (cons (apply func args) items)))
+           vec
+           vecs)
+    (list->vector (fold (lambda (rest x) (cons x rest)) '() items))))
+
+;; hash tables
+(define (hash-table-update! table key func . thunk)
+  (hash-table-set! table key (func (apply hash-table-ref table key thunk))))
+(define (hash-table-update!/default table key func default)
+  (hash-table-set! table key (func (hash-table-ref/default table key default))))
+(define (hash-table-walk table func)
+  (let loop ((entries (hash-table->alist table)))
+    (unless (null? entries)
+      (func (caar entries) (cdar entries))
+      (loop (cdr entries)))))
+
 (define (mem-helper pred op) (lambda (acc next) (if (and (not acc) (pred (op next))) next acc)))
 (define (memq obj lst)       (fold (mem-helper (curry eq? obj) id) #f lst))
 (define (memv obj lst)       (fold (mem-helper (curry eqv? obj) id) #f lst))
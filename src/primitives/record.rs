@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    heap::{self, Trace, Tracer},
+    lexer::Token,
+    parser::Expr,
+    primitives::pair::list_to_vec,
+    symbol::Symbol,
+    utils::{IterInnerVal, OwnIterVals, ToExpr},
+};
+
+// The primitives here sit underneath `define-record-type` in the prelude, which wraps them up in
+// a constructor, predicate, accessors and modifiers for each record type.
+
+#[derive(Debug)]
+pub struct RecordType {
+    pub name: String,
+    pub fields: Vec<Symbol>,
+}
+
+#[derive(Debug)]
+pub struct Record {
+    pub rtd: Rc<RecordType>,
+    pub values: RefCell<Vec<Expr>>,
+}
+
+// Record types and records are only ever equal to themselves, even when another one has the same
+// name and fields.
+impl PartialEq for RecordType {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl Trace for Record {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(values) = self.values.try_borrow() {
+            values.iter().for_each(|val| tracer.expr(val));
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut values) = self.values.try_borrow_mut() {
+            values.clear();
+        }
+    }
+}
+
+impl RecordType {
+    fn field_index(&self, field: Symbol) -> Result<usize, EvalErr> {
+        self.fields
+            .iter()
+            .position(|f| *f == field)
+            .ok_or(EvalErr::InvalidArgs("record type has no such field"))
+    }
+}
+
+// `(make-record-type name (field ...))`. A name written as `<point>` is known as just `point`.
+pub fn make_record_type(args: Args) -> Result<Expr, EvalErr> {
+    let (name, fields) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'make-record-type'. expected a name and a list of fields")
+    })?;
+    let name = match name {
+        Expr::Atom(Token::Symbol(name)) => name.as_str(),
+        expr => return Err(EvalErr::TypeError("symbol", expr)),
+    };
+    let name = match name.strip_prefix('<').and_then(|n| n.strip_suffix('>')) {
+        Some(inner) if !inner.is_empty() => inner.to_string(),
+        _ => name.to_string(),
+    };
+    let fields = list_to_vec(fields)?.into_symbols()?;
+    Ok(Expr::RecordType(Rc::new(RecordType { name, fields })))
+}
+
+// `(make-record type (field ...) value ...)` sets each of the named fields to its value, and leaves
+// any others as #f.
+pub fn make_record(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let (rtd, fields) = args.own_two_or_else(|| {
+        EvalErr::InvalidArgs("'make-record'. expected a record type and a list of fields")
+    })?;
+    let rtd = rtd_arg(rtd)?;
+    let fields = list_to_vec(fields)?.into_symbols()?;
+    if fields.len() != args.len() {
+        return Err(EvalErr::InvalidArgs(
+            "'make-record'. expected a value for each field",
+        ));
+    }
+    let mut values = vec![false.to_expr(); rtd.fields.len()];
+    for (field, val) in fields.iter().zip(args) {
+        values[rtd.field_index(*field)?] = val;
+    }
+    let record = Rc::new(Record {
+        rtd,
+        values: RefCell::new(values),
+    });
+    heap::track(&record);
+    Ok(Expr::Record(record))
+}
+
+pub fn record_is(args: Args) -> Result<Expr, EvalErr> {
+    let (rtd, obj) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'record-is?'. expected a record type and an object")
+    })?;
+    let rtd = rtd_arg(rtd)?;
+    Ok(matches!(obj, Expr::Record(r) if Rc::ptr_eq(&r.rtd, &rtd)).to_expr())
+}
+
+pub fn record_ref(args: Args) -> Result<Expr, EvalErr> {
+    let (rtd, obj, field) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'record-ref'. expected a record type, a record and a field")
+    })?;
+    let (record, i) = record_field(rtd, obj, field)?;
+    let val = record.values.borrow()[i].clone();
+    Ok(val)
+}
+
+pub fn record_set(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let (rtd, obj, field) = args.own_three_or_else(|| {
+        EvalErr::InvalidArgs("'record-set!'. expected a record type, a record, a field and a value")
+    })?;
+    let val = args.own_one_or_else(|| {
+        EvalErr::InvalidArgs("'record-set!'. expected a record type, a record, a field and a value")
+    })?;
+    let (record, i) = record_field(rtd, obj, field)?;
+    record.values.borrow_mut()[i] = val;
+    Ok(Expr::Void)
+}
+
+// The record and the position of one of its fields, so long as the record is of the given type.
+fn record_field(rtd: Expr, obj: Expr, field: Expr) -> Result<(Rc<Record>, usize), EvalErr> {
+    let rtd = rtd_arg(rtd)?;
+    let record = match obj {
+        Expr::Record(r) if Rc::ptr_eq(&r.rtd, &rtd) => r,
+        expr => return Err(EvalErr::TypeError("record of the accessor's type", expr)),
+    };
+    let field = match field {
+        Expr::Atom(Token::Symbol(field)) => field,
+        expr => return Err(EvalErr::TypeError("symbol", expr)),
+    };
+    let i = rtd.field_index(field)?;
+    Ok((record, i))
+}
+
+fn rtd_arg(expr: Expr) -> Result<Rc<RecordType>, EvalErr> {
+    match expr {
+        Expr::RecordType(rtd) => Ok(rtd),
+        expr => Err(EvalErr::TypeError("record type", expr)),
+    }
+}
@@ -0,0 +1,355 @@
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    lexer::Token,
+    number::Number,
+    parser::Expr,
+    primitives::pair::{list_to_vec, vec_to_list},
+    utils::{index_arg, range_args, IterInnerVal, OwnIterVals, ToExpr},
+};
+use std::cell::{Ref, RefCell, RefMut};
+use std::fmt;
+use std::rc::Rc;
+
+// Strings are indexed by character rather than by byte, so that indices line up with
+// `string->list` and `string-length` whatever the characters are. The characters are stored one to
+// a slot, so indexing and mutation don't have to walk the string.
+//
+// A string in the source is part of the code it appears in, so it can't be changed. Otherwise a
+// procedure returning a literal would return whatever the last `string-set!` left there.
+#[derive(Clone)]
+pub struct Str {
+    chars: RefCell<Vec<char>>,
+    literal: bool,
+}
+
+impl Str {
+    pub fn new(chars: Vec<char>) -> Str {
+        Str {
+            chars: RefCell::new(chars),
+            literal: false,
+        }
+    }
+
+    pub fn literal(chars: Vec<char>) -> Str {
+        Str {
+            chars: RefCell::new(chars),
+            literal: true,
+        }
+    }
+
+    pub fn chars(&self) -> Ref<'_, Vec<char>> {
+        self.chars.borrow()
+    }
+
+    fn chars_mut(&self, err: &'static str) -> Result<RefMut<'_, Vec<char>>, EvalErr> {
+        match self.literal {
+            true => Err(EvalErr::InvalidArgs(err)),
+            false => Ok(self.chars.borrow_mut()),
+        }
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Str) -> bool {
+        *self.chars() == *other.chars()
+    }
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.chars().iter().try_for_each(|c| write!(f, "{c}"))
+    }
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.to_string())
+    }
+}
+
+pub fn make_string(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let k = index_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'make-string'. expected a length"))?,
+    )?;
+    let fill = match args.next() {
+        Some(Expr::Atom(Token::Char(c))) => c,
+        Some(expr) => return Err(EvalErr::TypeError("char", expr)),
+        None => ' ',
+    };
+    Ok(chars_expr(vec![fill; k]))
+}
+
+pub fn string(args: Args) -> Result<Expr, EvalErr> {
+    Ok(chars_expr(args.into_chars()?))
+}
+
+pub fn string_length(args: Args) -> Result<Expr, EvalErr> {
+    let s = one_string(args, "'string-length'. expected a string")?;
+    let len = s.chars().len();
+    Ok((len as i64).to_expr())
+}
+
+pub fn string_ref(args: Args) -> Result<Expr, EvalErr> {
+    let (s, k) = args
+        .into_iter()
+        .own_two_or_else(|| EvalErr::InvalidArgs("'string-ref'. expected a string and an index"))?;
+    let k = index_arg(k)?;
+    let c = str_arg(s)?.chars().get(k).copied();
+    c.map(|c| c.to_expr())
+        .ok_or(EvalErr::InvalidArgs("'string-ref'. index out of range"))
+}
+
+pub fn string_set(args: Args) -> Result<Expr, EvalErr> {
+    let (s, k, c) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'string-set!'. expected a string, an index and a character")
+    })?;
+    let (s, k, c) = (str_arg(s)?, index_arg(k)?, char_arg(c)?);
+    *s.chars_mut("'string-set!'. string literals can't be changed")?
+        .get_mut(k)
+        .ok_or(EvalErr::InvalidArgs("'string-set!'. index out of range"))? = c;
+    Ok(Expr::Void)
+}
+
+pub fn string_fill(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let (s, c) = args.own_two_or_else(|| {
+        EvalErr::InvalidArgs("'string-fill!'. expected a string and a character")
+    })?;
+    let (s, c) = (str_arg(s)?, char_arg(c)?);
+    let mut chars = s.chars_mut("'string-fill!'. string literals can't be changed")?;
+    let (start, end) = range_args(args, chars.len(), "'string-fill!'. index out of range")?;
+    chars[start..end].fill(c);
+    Ok(Expr::Void)
+}
+
+pub fn substring(args: Args) -> Result<Expr, EvalErr> {
+    copy(args, "'substring'. expected a string and a start index")
+}
+
+pub fn string_copy(args: Args) -> Result<Expr, EvalErr> {
+    copy(args, "'string-copy'. expected a string")
+}
+
+// `(substring s start [end])` and `(string-copy s [start [end]])` give a new string either way.
+fn copy(args: Args, err: &'static str) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let s = str_arg(args.own_one_or_else(|| EvalErr::InvalidArgs(err))?)?;
+    let chars = s.chars();
+    let (start, end) = range_args(args, chars.len(), "string index out of range")?;
+    Ok(chars_expr(chars[start..end].to_vec()))
+}
+
+pub fn string_append(args: Args) -> Result<Expr, EvalErr> {
+    Ok(string_expr(strings(args)?.concat()))
+}
+
+pub fn string_eq(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x == y)
+}
+
+pub fn string_lt(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x < y)
+}
+
+pub fn string_gt(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x > y)
+}
+
+pub fn string_le(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x <= y)
+}
+
+pub fn string_ge(args: Args) -> Result<Expr, EvalErr> {
+    cmp_pairwise(args, |x, y| x >= y)
+}
+
+fn cmp_pairwise(args: Args, cmp: fn(&str, &str) -> bool) -> Result<Expr, EvalErr> {
+    let strs = strings(args)?;
+    if strs.is_empty() {
+        return Err(EvalErr::InvalidArgs(
+            "string comparison requires at least one argument",
+        ));
+    }
+    Ok(strs
+        .windows(2)
+        .all(|pair| cmp(&pair[0], &pair[1]))
+        .to_expr())
+}
+
+pub fn string_to_list(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let s = str_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'string->list'. expected a string"))?,
+    )?;
+    let chars = s.chars();
+    let (start, end) = range_args(args, chars.len(), "'string->list'. index out of range")?;
+    Ok(vec_to_list(
+        chars[start..end].iter().map(|c| c.to_expr()).collect(),
+    ))
+}
+
+pub fn list_to_string(args: Args) -> Result<Expr, EvalErr> {
+    let list = args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'list->string'. expected a list"))?;
+    let chars = list_to_vec(list)?.into_chars()?;
+    Ok(chars_expr(chars))
+}
+
+pub fn string_to_symbol(args: Args) -> Result<Expr, EvalErr> {
+    let s = one_string(args, "'string->symbol'. expected a string")?;
+    let name = s.to_string();
+    Ok(Expr::Atom(Token::Symbol(name.into())))
+}
+
+pub fn symbol_to_string(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'symbol->string'. expected a symbol"))?
+    {
+        Expr::Atom(Token::Symbol(name)) => Ok(string_expr(name.as_str())),
+        expr => Err(EvalErr::TypeError("symbol", expr)),
+    }
+}
+
+// Anything that doesn't read as a number gives #f rather than an error.
+pub fn string_to_number(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let s = str_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'string->number'. expected a string"))?,
+    )?;
+    let prefix = match radix(args.next())? {
+        2 => "#b",
+        8 => "#o",
+        16 => "#x",
+        _ => "",
+    };
+    let literal = format!("{prefix}{s}");
+    match Number::parse(&literal) {
+        Some(n) => Ok(n.to_expr()),
+        None => Ok(false.to_expr()),
+    }
+}
+
+// Only exact integers can be written in a radix other than 10.
+pub fn number_to_string(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let n = match args
+        .own_one_or_else(|| EvalErr::InvalidArgs("'number->string'. expected a number"))?
+    {
+        Expr::Atom(Token::Number(n)) => n,
+        expr => return Err(EvalErr::TypeError("number", expr)),
+    };
+    match (n, radix(args.next())?) {
+        (n, 10) => Ok(string_expr(n.to_string())),
+        (Number::Integer(n), radix) => Ok(string_expr(n.to_str_radix(radix))),
+        _ => Err(EvalErr::InvalidArgs(
+            "'number->string'. only exact integers can be written in another radix",
+        )),
+    }
+}
+
+fn radix(expr: Option<Expr>) -> Result<u32, EvalErr> {
+    match expr {
+        None => Ok(10),
+        Some(expr) => match index_arg(expr)? {
+            r @ (2 | 8 | 10 | 16) => Ok(r as u32),
+            _ => Err(EvalErr::InvalidArgs("radix must be 2, 8, 10 or 16")),
+        },
+    }
+}
+
+pub fn string_upcase(args: Args) -> Result<Expr, EvalErr> {
+    let s = one_string(args, "'string-upcase'. expected a string")?;
+    let upper = s.to_string().to_uppercase();
+    Ok(string_expr(upper))
+}
+
+pub fn string_downcase(args: Args) -> Result<Expr, EvalErr> {
+    let s = one_string(args, "'string-downcase'. expected a string")?;
+    let lower = s.to_string().to_lowercase();
+    Ok(string_expr(lower))
+}
+
+// The index of the first occurrence of a character, or #f when there isn't one.
+pub fn string_index(args: Args) -> Result<Expr, EvalErr> {
+    let (s, c) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'string-index'. expected a string and a character")
+    })?;
+    let (s, c) = (str_arg(s)?, char_arg(c)?);
+    let position = s.chars().iter().position(|x| *x == c);
+    match position {
+        Some(i) => Ok((i as i64).to_expr()),
+        None => Ok(false.to_expr()),
+    }
+}
+
+// Splits on every occurrence of a delimiter, which may be a character or a string, keeping any
+// empty fields between delimiters that are next to each other.
+pub fn string_split(args: Args) -> Result<Expr, EvalErr> {
+    let (s, delimiter) = args.into_iter().own_two_or_else(|| {
+        EvalErr::InvalidArgs("'string-split'. expected a string and a delimiter")
+    })?;
+    let s = str_arg(s)?;
+    let delimiter = match delimiter {
+        Expr::Atom(Token::Char(c)) => c.to_string(),
+        Expr::Atom(Token::Str(d)) if !d.chars().is_empty() => d.to_string(),
+        expr => return Err(EvalErr::TypeError("char or non-empty string", expr)),
+    };
+    let fields = s
+        .to_string()
+        .split(delimiter.as_str())
+        .map(string_expr)
+        .collect();
+    Ok(vec_to_list(fields))
+}
+
+// Joins a list of strings with a delimiter between each of them, a space by default.
+pub fn string_join(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let list =
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'string-join'. expected a list of strings"))?;
+    let delimiter = match args.next() {
+        Some(d) => str_arg(d)?.to_string(),
+        None => " ".to_string(),
+    };
+    Ok(string_expr(strings(list_to_vec(list)?)?.join(&delimiter)))
+}
+
+fn string_expr(s: impl Into<String>) -> Expr {
+    Expr::Atom(Token::string(s))
+}
+
+fn chars_expr(chars: Vec<char>) -> Expr {
+    Expr::Atom(Token::Str(Rc::new(Str::new(chars))))
+}
+
+fn str_arg(expr: Expr) -> Result<Rc<Str>, EvalErr> {
+    match expr {
+        Expr::Atom(Token::Str(s)) => Ok(s),
+        expr => Err(EvalErr::TypeError("string", expr)),
+    }
+}
+
+fn char_arg(expr: Expr) -> Result<char, EvalErr> {
+    match expr {
+        Expr::Atom(Token::Char(c)) => Ok(c),
+        expr => Err(EvalErr::TypeError("char", expr)),
+    }
+}
+
+fn one_string(args: Args, err: &'static str) -> Result<Rc<Str>, EvalErr> {
+    str_arg(
+        args.into_iter()
+            .own_one_or_else(|| EvalErr::InvalidArgs(err))?,
+    )
+}
+
+fn strings(exprs: impl IntoIterator<Item = Expr>) -> Result<Vec<String>, EvalErr> {
+    exprs
+        .into_iter()
+        .map(|expr| Ok(str_arg(expr)?.to_string()))
+        .collect()
+}
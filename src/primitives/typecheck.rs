@@ -26,6 +26,46 @@ pub fn string(args: Args) -> Result<Expr, EvalErr> {
     }
 }
 
+pub fn char(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'char?' expected argument"))?
+    {
+        Expr::Atom(Token::Char(_)) => Ok(true.to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
+pub fn vector(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'vector?' expected argument"))?
+    {
+        Expr::Vector(_) => Ok(true.to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
+pub fn bytevector(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'bytevector?' expected argument"))?
+    {
+        Expr::Bytevector(_) => Ok(true.to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
+pub fn hash_table(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'hash-table?' expected argument"))?
+    {
+        Expr::HashTable(_) => Ok(true.to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
 pub fn number(args: Args) -> Result<Expr, EvalErr> {
     match args
         .into_iter()
@@ -36,6 +76,26 @@ pub fn number(args: Args) -> Result<Expr, EvalErr> {
     }
 }
 
+pub fn integer(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'integer?' expected argument"))?
+    {
+        Expr::Atom(Token::Number(n)) => Ok(n.is_integer().to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
+pub fn rational(args: Args) -> Result<Expr, EvalErr> {
+    match args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'rational?' expected argument"))?
+    {
+        Expr::Atom(Token::Number(n)) => Ok(n.to_f64().is_finite().to_expr()),
+        _ => Ok(false.to_expr()),
+    }
+}
+
 pub fn null(args: Args) -> Result<Expr, EvalErr> {
     match args
         .into_iter()
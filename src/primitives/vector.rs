@@ -0,0 +1,159 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::rc::Rc;
+
+use crate::{
+    error::EvalErr,
+    evaluator::Args,
+    heap::{Trace, Tracer},
+    parser::Expr,
+    primitives::pair::{list_to_vec, vec_to_list},
+    utils::{index_arg, range_args, OwnIterVals, ToExpr},
+};
+
+// Like a string literal, a vector written in the source is part of the code it appears in, so it
+// can't be changed.
+#[derive(Debug, Clone)]
+pub struct Vector {
+    items: RefCell<Vec<Expr>>,
+    literal: bool,
+}
+
+impl Vector {
+    pub fn new(items: Vec<Expr>) -> Vector {
+        Vector {
+            items: RefCell::new(items),
+            literal: false,
+        }
+    }
+
+    pub fn literal(items: Vec<Expr>) -> Vector {
+        Vector {
+            items: RefCell::new(items),
+            literal: true,
+        }
+    }
+
+    pub fn items(&self) -> Ref<'_, Vec<Expr>> {
+        self.items.borrow()
+    }
+
+    fn items_mut(&self, err: &'static str) -> Result<RefMut<'_, Vec<Expr>>, EvalErr> {
+        match self.literal {
+            true => Err(EvalErr::InvalidArgs(err)),
+            false => Ok(self.items.borrow_mut()),
+        }
+    }
+}
+
+impl PartialEq for Vector {
+    fn eq(&self, other: &Vector) -> bool {
+        *self.items() == *other.items()
+    }
+}
+
+impl Trace for Vector {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Ok(items) = self.items.try_borrow() {
+            items.iter().for_each(|item| tracer.expr(item));
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut items) = self.items.try_borrow_mut() {
+            items.clear();
+        }
+    }
+}
+
+pub fn make_vector(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let k = index_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'make-vector'. expected a length"))?,
+    )?;
+    let fill = args.next().unwrap_or(Expr::Void);
+    Ok(Expr::vector(vec![fill; k]))
+}
+
+pub fn vector(args: Args) -> Result<Expr, EvalErr> {
+    Ok(Expr::vector(args.into_iter().collect()))
+}
+
+pub fn vector_length(args: Args) -> Result<Expr, EvalErr> {
+    let v = one_vector(args, "'vector-length'. expected a vector")?;
+    let len = v.items().len();
+    Ok((len as i64).to_expr())
+}
+
+pub fn vector_ref(args: Args) -> Result<Expr, EvalErr> {
+    let (v, k) = args
+        .into_iter()
+        .own_two_or_else(|| EvalErr::InvalidArgs("'vector-ref'. expected a vector and an index"))?;
+    let (v, k) = (vector_arg(v)?, index_arg(k)?);
+    let item = v.items().get(k).cloned();
+    item.ok_or(EvalErr::InvalidArgs("'vector-ref'. index out of range"))
+}
+
+pub fn vector_set(args: Args) -> Result<Expr, EvalErr> {
+    let (v, k, item) = args.into_iter().own_three_or_else(|| {
+        EvalErr::InvalidArgs("'vector-set!'. expected a vector, an index and a value")
+    })?;
+    let (v, k) = (vector_arg(v)?, index_arg(k)?);
+    *v.items_mut("'vector-set!'. vector literals can't be changed")?
+        .get_mut(k)
+        .ok_or(EvalErr::InvalidArgs("'vector-set!'. index out of range"))? = item;
+    Ok(Expr::Void)
+}
+
+pub fn vector_fill(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let (v, fill) = args.own_two_or_else(|| {
+        EvalErr::InvalidArgs("'vector-fill!'. expected a vector and a value")
+    })?;
+    let v = vector_arg(v)?;
+    let mut items = v.items_mut("'vector-fill!'. vector literals can't be changed")?;
+    let (start, end) = range_args(args, items.len(), "'vector-fill!'. index out of range")?;
+    items[start..end].fill(fill);
+    Ok(Expr::Void)
+}
+
+// Copies are shallow, the new vector holds the same items as the old one.
+pub fn vector_copy(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let v = vector_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'vector-copy'. expected a vector"))?,
+    )?;
+    let items = v.items();
+    let (start, end) = range_args(args, items.len(), "'vector-copy'. index out of range")?;
+    Ok(Expr::vector(items[start..end].to_vec()))
+}
+
+pub fn vector_to_list(args: Args) -> Result<Expr, EvalErr> {
+    let mut args = args.into_iter();
+    let v = vector_arg(
+        args.own_one_or_else(|| EvalErr::InvalidArgs("'vector->list'. expected a vector"))?,
+    )?;
+    let items = v.items();
+    let (start, end) = range_args(args, items.len(), "'vector->list'. index out of range")?;
+    Ok(vec_to_list(items[start..end].to_vec()))
+}
+
+pub fn list_to_vector(args: Args) -> Result<Expr, EvalErr> {
+    let list = args
+        .into_iter()
+        .own_one_or_else(|| EvalErr::InvalidArgs("'list->vector'. expected a list"))?;
+    Ok(Expr::vector(list_to_vec(list)?))
+}
+
+fn vector_arg(expr: Expr) -> Result<Rc<Vector>, EvalErr> {
+    match expr {
+        Expr::Vector(v) => Ok(v),
+        expr => Err(EvalErr::TypeError("vector", expr)),
+    }
+}
+
+fn one_vector(args: Args, err: &'static str) -> Result<Rc<Vector>, EvalErr> {
+    vector_arg(
+        args.into_iter()
+            .own_one_or_else(|| EvalErr::InvalidArgs(err))?,
+    )
+}
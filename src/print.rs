@@ -1,8 +1,13 @@
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::expander::strip_syntax;
 use crate::{
-    lexer::Token,
+    lexer::{char_name, Token},
     parser::Expr,
-    primitives::pair::{MaybeList, Pair},
+    primitives::{pair::Pair, port::Port},
     procedure::Proc,
+    symbol::Symbol,
 };
 
 pub trait Print<T> {
@@ -24,25 +29,33 @@ impl Printable for Token {
         match self {
             Token::LParen => "(".into(),
             Token::RParen => ")".into(),
-            Token::If => "if".into(),
-            Token::Define => "define".into(),
-            Token::Let => "let".into(),
-            Token::LetStar => "let*".into(),
-            Token::Lambda => "lambda".into(),
-            Token::Assignment => "set!".into(),
-            Token::And => "and".into(),
-            Token::Or => "or".into(),
-            Token::Cond => "cond".into(),
+            Token::HashParen => "#(".into(),
+            Token::U8Paren => "#u8(".into(),
             Token::QuoteTick => "'".into(),
-            Token::QuoteProc => "quote".into(),
-            Token::Begin => "begin".into(),
-            Token::Symbol(s) => s.into(),
+            Token::Backquote => "`".into(),
+            Token::Comma => ",".into(),
+            Token::CommaAt => ",@".into(),
+            Token::Symbol(s) => s.to_string(),
             Token::Number(n) => n.to_string(),
             Token::Boolean(b) => match b {
                 true => "#t".into(),
                 false => "#f".into(),
             },
             Token::Str(s) => format!(r##""{s}""##),
+            Token::Char(c) => match char_name(*c) {
+                Some(name) => format!("#\\{name}"),
+                None => format!("#\\{c}"),
+            },
+        }
+    }
+}
+
+impl Printable for Port {
+    fn printable(&self) -> String {
+        match self {
+            Port::Input { .. } => "#<binary-input-port>".into(),
+            Port::Output(_) | Port::File(_) => "#<binary-output-port>".into(),
+            Port::Closed => "#<closed-port>".into(),
         }
     }
 }
@@ -51,24 +64,161 @@ impl Printable for Proc {
     fn printable(&self) -> String {
         match self {
             Proc::Primitive(p) => format!("#<primitive-{:?}>", p.inner()),
-            Proc::Compound(p) => format!("#<closure-(#f{})>", p.to_owned().params().printable()),
+            Proc::Control(p) => format!("#<primitive-{:?}>", p.inner()),
+            Proc::Compound(p) => format!("#<closure-(#f{})>", p.params().printable()),
+            Proc::CaseLambda(_) => "#<case-lambda>".into(),
+            Proc::Continuation(_) => "#<continuation>".into(),
         }
     }
 }
 
 impl Printable for Expr {
     fn printable(&self) -> String {
-        match self {
+        let mut writer = Writer::default();
+        writer.find_cycles(self);
+        writer.write(self)
+    }
+}
+
+// Pairs, vectors and records can be mutated to contain themselves. Anything that is reached again
+// while it is still being written is given a datum label the first time, as in `#0=(1 . #0#)`,
+// and written as a reference to the label after that.
+#[derive(Default)]
+struct Writer {
+    // The objects being written, from the outermost in.
+    path: HashSet<*const ()>,
+    labels: HashMap<*const (), Option<usize>>,
+    next_label: usize,
+}
+
+impl Writer {
+    // Goes over the data the same way writing it does, so that exactly the objects writing would
+    // never get out of are labelled.
+    fn find_cycles(&mut self, expr: &Expr) {
+        let Some(id) = identity(expr) else {
+            return;
+        };
+        if !self.path.insert(id) {
+            self.labels.insert(id, None);
+            return;
+        }
+        match expr {
+            Expr::Pair(p) => match p.to_vec() {
+                Some(items) => items.iter().for_each(|item| self.find_cycles(item)),
+                None => {
+                    self.find_cycles(&p.car());
+                    self.find_cycles(&p.cdr());
+                }
+            },
+            Expr::Vector(v) => v.items().iter().for_each(|item| self.find_cycles(item)),
+            Expr::Record(r) => r
+                .values
+                .borrow()
+                .iter()
+                .for_each(|val| self.find_cycles(val)),
+            _ => (),
+        }
+        self.path.remove(&id);
+    }
+
+    fn write(&mut self, expr: &Expr) -> String {
+        match expr {
             Expr::EmptyList => "'()".to_string(),
             Expr::Atom(a) => a.printable(),
             Expr::Proc(p) => p.printable(),
             Expr::Call(l) => l.printable(),
-            Expr::Pair(p) => p.printable(),
-            Expr::Quoted(q) => (*q).printable(),
+            Expr::Pair(p) => self.labelled(expr, |writer| match p.to_vec() {
+                Some(items) => format!("'({})", writer.write_all(&items)),
+                None => {
+                    let (items, tail) = writer.improper(p);
+                    format!("({} . {})", writer.write_all(&items), writer.write(&tail))
+                }
+            }),
+            Expr::Vector(v) => self.labelled(expr, |writer| {
+                format!("#({})", writer.write_all(&v.items()))
+            }),
+            Expr::Quoted(q) => self.write(q),
+            Expr::Bytevector(b) => format!(
+                "#u8({})",
+                b.borrow()
+                    .iter()
+                    .map(|byte| byte.to_string())
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
+            Expr::Port(p) => p.borrow().printable(),
+            Expr::Eof => "#<eof>".to_string(),
+            Expr::RecordType(rtd) => format!("#<record-type {}>", rtd.name),
+            Expr::Record(r) => self.labelled(expr, |writer| {
+                let fields = r
+                    .rtd
+                    .fields
+                    .iter()
+                    .zip(r.values.borrow().iter())
+                    .map(|(field, val)| format!(" {field}={}", writer.write(val)))
+                    .collect::<String>();
+                format!("#<record {}{fields}>", r.rtd.name)
+            }),
+            Expr::HashTable(t) => format!("#<hash-table {}>", t.borrow().len()),
+            Expr::Condition(err) => format!("#<condition {}>", err.message()),
+            Expr::Macro(_) => "#<macro>".to_string(),
+            Expr::Alias(_) => strip_syntax(expr.clone()).printable(),
             Expr::Void => "".to_string(),
+            Expr::Unassigned => "#<unassigned>".to_string(),
             x => format!("{:?}", x),
         }
     }
+
+    fn write_all(&mut self, items: &[Expr]) -> String {
+        items
+            .iter()
+            .map(|item| self.write(item))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    // Splits an improper list into the items written before the dot and the tail written after
+    // it. A pair with a label starts the tail, so that a cycle is written with a single dot, as in
+    // `#0=(1 2 . #0#)`.
+    fn improper(&self, pair: &Pair) -> (Vec<Expr>, Expr) {
+        let mut items = vec![pair.car()];
+        let mut tail = pair.cdr();
+        while let Expr::Pair(next) = &tail {
+            if identity(&tail).is_some_and(|id| self.labels.contains_key(&id)) {
+                break;
+            }
+            items.push(next.car());
+            let cdr = next.cdr();
+            tail = cdr;
+        }
+        (items, tail)
+    }
+
+    fn labelled(&mut self, expr: &Expr, write: impl FnOnce(&mut Self) -> String) -> String {
+        let Some(id) = identity(expr) else {
+            return write(self);
+        };
+        match self.labels.get(&id) {
+            None => write(self),
+            Some(Some(label)) => format!("#{label}#"),
+            Some(None) => {
+                let label = self.next_label;
+                self.next_label += 1;
+                self.labels.insert(id, Some(label));
+                format!("#{label}={}", write(self))
+            }
+        }
+    }
+}
+
+// What tells apart the objects that can end up containing themselves.
+fn identity(expr: &Expr) -> Option<*const ()> {
+    match expr {
+        Expr::Pair(p) => Some(Rc::as_ptr(p) as *const ()),
+        Expr::Vector(v) => Some(Rc::as_ptr(v) as *const ()),
+        Expr::Record(r) => Some(Rc::as_ptr(r) as *const ()),
+        _ => None,
+    }
 }
 
 impl Printable for Vec<Expr> {
@@ -82,7 +232,7 @@ impl Printable for Vec<Expr> {
     }
 }
 
-impl Printable for Vec<String> {
+impl Printable for [Symbol] {
     fn printable(&self) -> String {
         let ls = self
             .iter()
@@ -92,21 +242,3 @@ impl Printable for Vec<String> {
         format!("'({})", ls)
     }
 }
-
-impl Printable for Pair {
-    fn printable(&self) -> String {
-        match self.try_list() {
-            MaybeList::List(ls) => match ls {
-                Some(ls) => format!(
-                    "'({})",
-                    ls.iter()
-                        .map(|e| e.printable())
-                        .reduce(|curr, next| format!("{} {}", curr, next))
-                        .unwrap_or_else(|| "".to_string())
-                ),
-                None => Expr::EmptyList.printable(),
-            },
-            MaybeList::Pair(p) => format!("({} . {})", p.car.printable(), p.cdr.printable()),
-        }
-    }
-}
@@ -1,21 +1,53 @@
+use std::rc::Rc;
+
 use crate::{
+    analyzer::{BodyIter, LambdaCode},
+    compiler::{Chunk, Template},
+    continuation::{eval_sequence, Continuation},
     enviroment::{Env, EnvRef},
     error::EvalErr,
-    evaluator::{eval, Args},
+    evaluator::{Args, Step},
+    heap::{Trace, Tracer},
     parser::Expr,
+    primitives::pair::vec_to_list,
+    symbol::Symbol,
+    vm::Activation,
 };
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Proc {
     Primitive(Primitive),
+    Control(Control),
     Compound(Compound),
+    CaseLambda(Vec<Compound>),
+    Continuation(Continuation),
+}
+
+// Procedures can't be changed once made, so there is nothing to clear. Any cycle through one also
+// runs through the enviroment it closes over, which is cleared instead.
+impl Trace for Proc {
+    fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Proc::Compound(proc) => proc.env.trace(tracer),
+            Proc::CaseLambda(clauses) => clauses.iter().for_each(|proc| proc.env.trace(tracer)),
+            _ => (),
+        }
+    }
+
+    fn clear(&self) {}
 }
 
 pub type PSig = fn(Args) -> Result<Expr, EvalErr>;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct Primitive(PSig);
 
+impl PartialEq for Primitive {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::fn_addr_eq(self.0, other.0)
+    }
+}
+
 #[allow(clippy::new_ret_no_self)]
 impl Primitive {
     pub fn new(proc: PSig) -> Proc {
@@ -31,44 +63,100 @@ impl Primitive {
     }
 }
 
+// Control primitives take the current continuation along with their arguments, for procedures
+// like `apply` and `call/cc` that need to call back into the evaluator.
+pub type CSig = fn(Args, &mut Continuation) -> Result<Step, EvalErr>;
+
+#[derive(Debug, Clone)]
+pub struct Control(CSig);
+
+impl PartialEq for Control {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::fn_addr_eq(self.0, other.0)
+    }
+}
+
+#[allow(clippy::new_ret_no_self)]
+impl Control {
+    pub fn new(proc: CSig) -> Proc {
+        Proc::Control(Control(proc))
+    }
+
+    pub fn call(&self, args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+        (self.0)(args, k)
+    }
+
+    pub fn inner(&self) -> CSig {
+        self.0
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Compound {
-    params: Vec<String>,
-    body: Vec<Expr>,
+    lambda: Rc<LambdaCode>,
+    // Closures made by the VM run their body's compiled chunk instead of evaluating it.
+    chunk: Option<Rc<Chunk>>,
     env: EnvRef,
 }
 
-#[allow(clippy::new_ret_no_self)]
 impl Compound {
-    pub fn new(body: Vec<Expr>, params: Vec<String>, env: EnvRef) -> Proc {
-        Proc::Compound(Compound { body, params, env })
+    pub fn new(lambda: Rc<LambdaCode>, env: EnvRef) -> Self {
+        Compound {
+            lambda,
+            chunk: None,
+            env,
+        }
     }
 
-    pub fn call(&self, args: Args) -> Result<Expr, EvalErr> {
-        if self.params.len() != args.len() {
+    pub fn compiled(template: &Template, env: EnvRef) -> Self {
+        Compound {
+            lambda: Rc::clone(&template.lambda),
+            chunk: Some(Rc::clone(&template.chunk)),
+            env,
+        }
+    }
+
+    pub fn accepts(&self, args: usize) -> bool {
+        match self.lambda.rest {
+            Some(_) => args >= self.lambda.params.len(),
+            None => args == self.lambda.params.len(),
+        }
+    }
+
+    pub fn call(&self, args: Args, k: &mut Continuation) -> Result<Step, EvalErr> {
+        if !self.accepts(args.len()) {
             return Err(EvalErr::InvalidArgs(
                 "amount of args does not match function pararms",
             ));
         }
 
         let mut args = args.into_iter();
-        let mut new_env = Env::new(self.env.clone_rc()?);
+        let mut slots = Vec::with_capacity(self.lambda.slots);
+        slots.extend(args.by_ref().take(self.lambda.params.len()));
 
-        for param in self.params.iter() {
-            let arg = args.next().unwrap();
-            new_env.insert_val(param.to_owned(), arg);
+        // Whatever is left over is collected into a list for the rest parameter.
+        if self.lambda.rest.is_some() {
+            slots.push(vec_to_list(args.collect()));
         }
 
-        let new_env_ref = EnvRef::new(new_env);
+        // Variables defined in the body can't be used until their definitions have run.
+        slots.resize(self.lambda.slots, Expr::Unassigned);
 
-        self.body
-            .iter()
-            .try_fold(Expr::Void, |_returned_expr, expr| {
-                eval(expr.clone(), &new_env_ref)
-            })
+        let new_env_ref = EnvRef::new(Env::Frame {
+            parent: self.env.clone_rc()?,
+            slots,
+        });
+
+        match &self.chunk {
+            Some(chunk) => Ok(Step::Run(Activation::new(Rc::clone(chunk), new_env_ref))),
+            None => {
+                let body = BodyIter::new(self.lambda.body.clone());
+                Ok(eval_sequence(body, new_env_ref, k))
+            }
+        }
     }
 
-    pub fn params(self) -> Vec<String> {
-        self.params
+    pub fn params(&self) -> &[Symbol] {
+        &self.lambda.params
     }
 }
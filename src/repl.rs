@@ -2,13 +2,14 @@ use std::io::{self, Write};
 use std::process;
 
 use crate::enviroment::EnvRef;
-use crate::evaluator;
+use crate::evaluator::Engine;
 use crate::lexer::TokenStream;
 use crate::parser::Parser;
 use crate::print::Print;
 
 pub struct Repl {
     global_env: EnvRef,
+    engine: Engine,
 }
 
 impl Default for Repl {
@@ -19,8 +20,13 @@ impl Default for Repl {
 
 impl Repl {
     pub fn new() -> Self {
+        Repl::with_engine(Engine::default())
+    }
+
+    pub fn with_engine(engine: Engine) -> Self {
         Repl {
             global_env: EnvRef::global(),
+            engine,
         }
     }
 
@@ -28,10 +34,12 @@ impl Repl {
         println!("Schemeish v0.1.0");
         println!("Welcome :)");
 
-        self.global_env.import_prelude().unwrap_or_else(|err| {
-            eprintln!("{err}");
-            process::exit(1);
-        });
+        self.global_env
+            .import_prelude(self.engine)
+            .unwrap_or_else(|err| {
+                eprintln!("{err}");
+                process::exit(1);
+            });
 
         loop {
             print!("> ");
@@ -57,7 +65,7 @@ impl Repl {
                 };
 
                 for exp in exprs.into_iter() {
-                    match evaluator::eval(exp, &self.global_env) {
+                    match self.engine.eval(exp, &self.global_env) {
                         Ok(evalulated) => evalulated.print(),
                         Err(err) => eprintln!("{err}"),
                     }
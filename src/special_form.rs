@@ -1,11 +1,14 @@
+use std::rc::Rc;
+
 use crate::{
-    enviroment::EnvRef,
+    analyzer::{analyze, analyze_body, Code, LambdaCode, Node, Scope},
+    enviroment::Variable,
     error::EvalErr,
-    evaluator::eval,
+    expander::dotted_parts,
     lexer::Token,
     parser::Expr,
-    procedure::Compound,
-    utils::{IterInnerVal, ToExpr},
+    symbol::Symbol,
+    utils::IterInnerVal,
 };
 
 #[derive(Debug, Clone, PartialEq)]
@@ -13,30 +16,63 @@ pub enum SpecialForm {
     And(And),
     Assignment(Assignment),
     Begin(Begin),
+    CaseLambda(CaseLambda),
+    Declare(Declare),
     Define(Define),
+    GlobalRef(GlobalRef),
+    Guard(Guard),
     If(If),
     Lambda(Lambda),
     Or(Or),
 }
 
-pub trait Eval {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr>;
+// Special forms are only the shape the expander leaves a form in. Each one is analyzed into code
+// before it runs.
+pub trait Analyze {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr>;
 }
 
-impl Eval for SpecialForm {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
+impl Analyze for SpecialForm {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
         match self {
-            SpecialForm::And(and_x) => and_x.eval(env),
-            SpecialForm::Assignment(ass_x) => ass_x.eval(env),
-            SpecialForm::Begin(beg_x) => beg_x.eval(env),
-            SpecialForm::Define(def_x) => def_x.eval(env),
-            SpecialForm::If(if_x) => if_x.eval(env),
-            SpecialForm::Lambda(lam_x) => lam_x.eval(env),
-            SpecialForm::Or(or_x) => or_x.eval(env),
+            SpecialForm::And(and_x) => and_x.analyze(scope),
+            SpecialForm::Assignment(ass_x) => ass_x.analyze(scope),
+            SpecialForm::Begin(beg_x) => beg_x.analyze(scope),
+            SpecialForm::CaseLambda(cas_x) => cas_x.analyze(scope),
+            SpecialForm::Declare(dec_x) => dec_x.analyze(scope),
+            SpecialForm::Define(def_x) => def_x.analyze(scope),
+            SpecialForm::GlobalRef(glb_x) => glb_x.analyze(scope),
+            SpecialForm::Guard(grd_x) => grd_x.analyze(scope),
+            SpecialForm::If(if_x) => if_x.analyze(scope),
+            SpecialForm::Lambda(lam_x) => lam_x.analyze(scope),
+            SpecialForm::Or(or_x) => or_x.analyze(scope),
         }
     }
 }
 
+// Binds every variable defined in a body before any of their values are evaluated, so that they
+// can refer to each other and shadow outer variables from the start. Until its definition runs, a
+// variable holds a marker that is an error to read.
+//
+// The expander puts these at the start of a lambda's body, and the lambda gives each of the
+// variables a slot in its frame, so there is nothing left to do when the body runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declare {
+    identifiers: Vec<Symbol>,
+}
+
+impl Declare {
+    pub fn new(identifiers: Vec<Symbol>) -> Self {
+        Declare { identifiers }
+    }
+}
+
+impl Analyze for Declare {
+    fn analyze(&self, _scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Constant(Expr::Void)))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Define {
     identifier: Expr,
@@ -49,15 +85,44 @@ impl Define {
     }
 }
 
-impl Eval for Define {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        match &self.identifier {
-            Expr::Atom(Token::Symbol(identifier)) => {
-                env.insert_val(identifier.clone(), eval(self.body.clone(), env)?)?;
-                Ok(Expr::Void)
-            }
-            identifier => Err(EvalErr::TypeError("symbol or list", identifier.clone())),
-        }
+impl Analyze for Define {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Define(
+            target(&self.identifier, scope, "symbol or list")?,
+            analyze(&self.body, scope)?,
+        )))
+    }
+}
+
+// The variable a definition or an assignment is to. The expander gives it as a `GlobalRef` when
+// a macro refers to a global that is shadowed where the macro is used.
+fn target(identifier: &Expr, scope: &Scope, expected: &'static str) -> Result<Variable, EvalErr> {
+    match identifier {
+        Expr::Atom(Token::Symbol(identifier)) => scope.variable(*identifier),
+        Expr::SpecialForm(form) => match form.as_ref() {
+            SpecialForm::GlobalRef(global) => scope.global(global.identifier),
+            _ => Err(EvalErr::TypeError(expected, identifier.clone())),
+        },
+        identifier => Err(EvalErr::TypeError(expected, identifier.clone())),
+    }
+}
+
+// A reference to a global variable that skips over any local variables of the same name. Only the
+// expander produces these, for globals referred to by a macro where they have been shadowed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlobalRef {
+    identifier: Symbol,
+}
+
+impl GlobalRef {
+    pub fn new(identifier: Symbol) -> Self {
+        GlobalRef { identifier }
+    }
+}
+
+impl Analyze for GlobalRef {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Variable(scope.global(self.identifier)?)))
     }
 }
 
@@ -73,18 +138,70 @@ impl Lambda {
     }
 }
 
-impl Eval for Lambda {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        match &self.params {
-            Expr::Call(first_expr) => {
-                let proc_args = first_expr.to_owned().into_strings()?;
-                Ok(Compound::new(self.body.to_owned(), proc_args, env.clone_rc()?).to_expr())
-            }
-            Expr::EmptyList => {
-                Ok(Compound::new(self.body.to_owned(), vec![], env.clone_rc()?).to_expr())
+impl Lambda {
+    fn lambda(&self, scope: &mut Scope) -> Result<Rc<LambdaCode>, EvalErr> {
+        let (params, rest) = match &self.params {
+            Expr::Call(params) => (params.to_owned().into_symbols()?, None),
+            Expr::EmptyList => (vec![], None),
+            Expr::Atom(Token::Symbol(rest)) => (vec![], Some(*rest)),
+            Expr::Pair(_) => {
+                let (params, rest) = dotted_parts(&self.params);
+                match rest {
+                    Expr::Atom(Token::Symbol(rest)) => (params.into_symbols()?, Some(rest)),
+                    rest => return Err(EvalErr::TypeError("symbol", rest)),
+                }
             }
-            first_expr => Err(EvalErr::TypeError("list", first_expr.clone())),
-        }
+            params => return Err(EvalErr::TypeError("list", params.clone())),
+        };
+        let (declared, body) = self
+            .body
+            .split_first()
+            .and_then(|(first, body)| Some((declaration(first)?, body)))
+            .unwrap_or((&[], &self.body));
+        let vars = params
+            .iter()
+            .chain(rest.iter())
+            .chain(declared.iter())
+            .copied()
+            .collect::<Vec<Symbol>>();
+        let slots = vars.len();
+        let body = scope.with_frame(vars, |scope| analyze_body(body, scope))?;
+        Ok(Rc::new(LambdaCode {
+            params,
+            rest,
+            slots,
+            body,
+        }))
+    }
+}
+
+impl Analyze for Lambda {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Lambda(self.lambda(scope)?)))
+    }
+}
+
+// A procedure made of several lambdas, called with the first one that accepts the number of
+// arguments it is given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseLambda {
+    clauses: Vec<Lambda>,
+}
+
+impl CaseLambda {
+    pub fn new(clauses: Vec<Lambda>) -> Self {
+        CaseLambda { clauses }
+    }
+}
+
+impl Analyze for CaseLambda {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        let clauses = self
+            .clauses
+            .iter()
+            .map(|clause| clause.lambda(scope))
+            .collect::<Result<Vec<Rc<LambdaCode>>, EvalErr>>()?;
+        Ok(Code::new(Node::CaseLambda(clauses)))
     }
 }
 
@@ -105,13 +222,13 @@ impl If {
     }
 }
 
-impl Eval for If {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        match eval(self.predicate.clone(), env)? {
-            Expr::Atom(Token::Boolean(true)) => eval(self.consequence.clone(), env),
-            Expr::Atom(Token::Boolean(false)) => eval(self.alternative.clone(), env),
-            pred => Err(EvalErr::TypeError("bool", pred)),
-        }
+impl Analyze for If {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::If {
+            predicate: analyze(&self.predicate, scope)?,
+            consequence: analyze(&self.consequence, scope)?,
+            alternative: analyze(&self.alternative, scope)?,
+        }))
     }
 }
 
@@ -127,14 +244,12 @@ impl Assignment {
     }
 }
 
-impl Eval for Assignment {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        match &self.identifier {
-            Expr::Atom(Token::Symbol(identifier)) => {
-                env.update_val(identifier.to_string(), eval(self.value.clone(), env)?)
-            }
-            expr => Err(EvalErr::TypeError("symbol", expr.clone())),
-        }
+impl Analyze for Assignment {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Assignment(
+            target(&self.identifier, scope, "symbol")?,
+            analyze(&self.value, scope)?,
+        )))
     }
 }
 
@@ -149,11 +264,9 @@ impl Begin {
     }
 }
 
-impl Eval for Begin {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        self.exprs
-            .iter()
-            .try_fold(Expr::Void, |_returned_expr, expr| eval(expr.clone(), env))
+impl Analyze for Begin {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Begin(analyze_body(&self.exprs, scope)?)))
     }
 }
 
@@ -168,16 +281,9 @@ impl And {
     }
 }
 
-impl Eval for And {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        for expr in self.body.iter() {
-            match eval(expr.clone(), env)? {
-                Expr::Atom(Token::Boolean(n)) if !n => return Ok(n.to_expr()),
-                Expr::Atom(Token::Boolean(n)) if n => Ok(()),
-                expr => Err(EvalErr::TypeError("boolean", expr)),
-            }?;
-        }
-        Ok(true.to_expr())
+impl Analyze for And {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::And(analyze_body(&self.body, scope)?)))
     }
 }
 
@@ -192,15 +298,65 @@ impl Or {
     }
 }
 
-impl Eval for Or {
-    fn eval(&self, env: &EnvRef) -> Result<Expr, EvalErr> {
-        for expr in self.body.iter() {
-            match eval(expr.clone(), env)? {
-                Expr::Atom(Token::Boolean(n)) if n => return Ok(n.to_expr()),
-                Expr::Atom(Token::Boolean(n)) if !n => Ok(()),
-                expr => Err(EvalErr::TypeError("boolean", expr)),
-            }?;
+impl Analyze for Or {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        Ok(Code::new(Node::Or(analyze_body(&self.body, scope)?)))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Guard {
+    identifier: Expr,
+    reraise: Expr,
+    clauses: Expr,
+    body: Vec<Expr>,
+}
+
+impl Guard {
+    pub fn new(identifier: Expr, reraise: Expr, clauses: Expr, body: Vec<Expr>) -> Self {
+        Guard {
+            identifier,
+            reraise,
+            clauses,
+            body,
         }
-        Ok(false.to_expr())
+    }
+}
+
+impl Analyze for Guard {
+    fn analyze(&self, scope: &mut Scope) -> Result<Code, EvalErr> {
+        let params = [&self.identifier, &self.reraise]
+            .into_iter()
+            .map(|param| match param {
+                Expr::Atom(Token::Symbol(param)) => Ok(*param),
+                expr => Err(EvalErr::TypeError("symbol", expr.clone())),
+            })
+            .collect::<Result<Vec<Symbol>, EvalErr>>()?;
+        let clauses = scope.with_frame(params.clone(), |scope| {
+            analyze_body(std::slice::from_ref(&self.clauses), scope)
+        })?;
+        Ok(Code::new(Node::Guard {
+            clauses: Rc::new(LambdaCode {
+                params,
+                rest: None,
+                slots: 2,
+                body: clauses,
+            }),
+            body: analyze_body(&self.body, scope)?,
+        }))
+    }
+}
+
+// The variables a body declares, when it starts with a declaration.
+fn declaration(expr: &Expr) -> Option<&[Symbol]> {
+    match expr {
+        Expr::Call(items) => match items.as_slice() {
+            [Expr::SpecialForm(form)] => match form.as_ref() {
+                SpecialForm::Declare(declare) => Some(&declare.identifiers),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
     }
 }
@@ -0,0 +1,160 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::sync::{LazyLock, Mutex, PoisonError};
+
+// Symbols are interned into one table for the whole program, so every name is only stored once
+// and two symbols are the same exactly when their ids are. Looking up or comparing a variable
+// never has to touch the characters of its name. Names are never freed once interned.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+struct SymbolTable {
+    names: Vec<&'static str>,
+    ids: HashMap<&'static str, Symbol>,
+}
+
+static SYMBOLS: LazyLock<Mutex<SymbolTable>> = LazyLock::new(|| {
+    let mut table = SymbolTable {
+        names: vec![],
+        ids: HashMap::new(),
+    };
+    for &name in WELL_KNOWN {
+        let symbol = Symbol(table.names.len() as u32);
+        table.names.push(name);
+        table.ids.insert(name, symbol);
+    }
+    Mutex::new(table)
+});
+
+thread_local! {
+    // Each thread copies the names it has seen out of the table, so that looking a name up only
+    // takes the lock the first time the thread comes across a symbol interned since it last did.
+    static NAMES: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+// Names the interpreter looks for itself are interned first, in this order, so that they can be
+// compared against as constants. Each is listed once, and both its constant and its slot in the
+// table come from that line.
+macro_rules! well_known {
+    ($($constant:ident = $name:literal,)*) => {
+        #[allow(non_camel_case_types, clippy::upper_case_acronyms)]
+        #[repr(u32)]
+        enum WellKnown {
+            $($constant,)*
+        }
+
+        const WELL_KNOWN: &[&str] = &[$($name,)*];
+
+        impl Symbol {
+            $(pub const $constant: Symbol = Symbol(WellKnown::$constant as u32);)*
+        }
+    };
+}
+
+well_known! {
+    WILDCARD = "_",
+    ELLIPSIS = "...",
+    DOT = ".",
+    QUOTE = "quote",
+    QUASIQUOTE = "quasiquote",
+    UNQUOTE = "unquote",
+    UNQUOTE_SPLICING = "unquote-splicing",
+    IF = "if",
+    DEFINE = "define",
+    LAMBDA = "lambda",
+    CASE_LAMBDA = "case-lambda",
+    SET = "set!",
+    BEGIN = "begin",
+    AND = "and",
+    OR = "or",
+    GUARD = "guard",
+    DEFINE_SYNTAX = "define-syntax",
+    LET_SYNTAX = "let-syntax",
+    LETREC_SYNTAX = "letrec-syntax",
+    SYNTAX_RULES = "syntax-rules",
+}
+
+impl Symbol {
+    pub fn intern(name: &str) -> Symbol {
+        let mut table = SYMBOLS.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(symbol) = table.ids.get(name) {
+            return *symbol;
+        }
+        let name: &'static str = Box::leak(name.into());
+        let symbol = Symbol(table.names.len() as u32);
+        table.names.push(name);
+        table.ids.insert(name, symbol);
+        symbol
+    }
+
+    pub fn as_str(self) -> &'static str {
+        NAMES.with_borrow_mut(|names| {
+            let index = self.0 as usize;
+            if index >= names.len() {
+                let table = SYMBOLS.lock().unwrap_or_else(PoisonError::into_inner);
+                names.extend_from_slice(&table.names[names.len()..]);
+            }
+            names[index]
+        })
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(name: &str) -> Self {
+        Symbol::intern(name)
+    }
+}
+
+impl From<String> for Symbol {
+    fn from(name: String) -> Self {
+        Symbol::intern(&name)
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+// Maps keyed by symbols only need to spread the id's bits out, since ids are already unique.
+pub type SymbolMap<V> = HashMap<Symbol, V, BuildHasherDefault<SymbolHasher>>;
+
+#[derive(Default)]
+pub struct SymbolHasher(u64);
+
+impl Hasher for SymbolHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8) | u64::from(*byte);
+        }
+    }
+
+    fn write_u32(&mut self, id: u32) {
+        self.0 = u64::from(id).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    }
+}
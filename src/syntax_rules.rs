@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+
+use crate::error::EvalErr;
+use crate::expander::{
+    dotted, dotted_parts, identifier_name, strip_syntax, to_syntax, Alias, IdentKey, Scope,
+};
+use crate::parser::Expr;
+use crate::symbol::Symbol;
+use crate::utils::{OwnIterVals, ToExpr};
+
+// A `syntax-rules` transformer. Rules are kept as the syntax they were written as, along with the
+// scope the macro was defined in. Identifiers a template introduces are renamed on every use, and
+// resolve in that scope rather than wherever the macro happens to be used.
+#[derive(Debug)]
+pub struct Macro {
+    ellipsis: Symbol,
+    literals: Vec<IdentKey>,
+    rules: Vec<Rule>,
+    scope: Scope,
+}
+
+// A pattern may end in a dotted tail, `(_ a . rest)`, which matches whatever follows the elements
+// before it. Proper list patterns have an empty tail.
+#[derive(Debug)]
+struct Rule {
+    pattern: Vec<Expr>,
+    tail: Expr,
+    template: Expr,
+}
+
+impl PartialEq for Macro {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Match {
+    One(Expr),
+    Many(Vec<Match>),
+}
+
+type Bindings = HashMap<IdentKey, Match>;
+
+impl Macro {
+    // Built from the operands of a `(syntax-rules [ellipsis] (literal ...) (pattern template) ...)`
+    // form.
+    pub fn new(spec: Vec<Expr>, scope: Scope) -> Result<Macro, EvalErr> {
+        let mut spec = spec.into_iter().peekable();
+        let ellipsis = match spec.peek().and_then(identifier_name) {
+            Some(name) => {
+                spec.next();
+                name
+            }
+            None => Symbol::ELLIPSIS,
+        };
+
+        let literals = match spec.next() {
+            Some(Expr::Call(literals)) => literals
+                .into_iter()
+                .map(|literal| IdentKey::of(&literal).ok_or(EvalErr::TypeError("symbol", literal)))
+                .collect::<Result<Vec<IdentKey>, EvalErr>>()?,
+            Some(Expr::EmptyList) => vec![],
+            Some(expr) => return Err(EvalErr::TypeError("list", expr)),
+            None => {
+                return Err(EvalErr::InvalidArgs(
+                    "'syntax-rules' expression. expected literals and rules",
+                ))
+            }
+        };
+
+        let rules = spec
+            .map(|rule| match rule {
+                Expr::Call(rule) => {
+                    let (pattern, template) = rule.into_iter().own_two_or_else(|| {
+                        EvalErr::InvalidArgs(
+                            "'syntax-rules' expression. expected rules of a pattern and template",
+                        )
+                    })?;
+                    match to_syntax(pattern) {
+                        pattern @ (Expr::Call(_) | Expr::Pair(_)) => {
+                            let (pattern, tail) = dotted_parts(&pattern);
+                            Ok(Rule {
+                                pattern,
+                                tail,
+                                template: to_syntax(template),
+                            })
+                        }
+                        expr => Err(EvalErr::TypeError("list", expr)),
+                    }
+                }
+                expr => Err(EvalErr::TypeError("list", expr)),
+            })
+            .collect::<Result<Vec<Rule>, EvalErr>>()?;
+
+        Ok(Macro {
+            ellipsis,
+            literals,
+            rules,
+            scope,
+        })
+    }
+
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+
+    // Rewrites a use of the macro with the first rule whose pattern matches it. `same_binding`
+    // decides whether an identifier in the use matches one of the literals, which depends on what
+    // each of them refers to where they appear.
+    pub fn expand(
+        &self,
+        form: &[Expr],
+        same_binding: &dyn Fn(&Expr, &Expr) -> bool,
+    ) -> Result<Expr, EvalErr> {
+        for rule in self.rules.iter() {
+            let mut bindings = Bindings::new();
+            // The keyword position is ignored, the macro may have been bound under any name.
+            let (pattern, inputs) = (&rule.pattern[1..], &form[1..]);
+            let tail = &Expr::EmptyList;
+            if self.match_dotted(
+                pattern,
+                &rule.tail,
+                inputs,
+                tail,
+                &mut bindings,
+                same_binding,
+            ) {
+                return self.instantiate(&rule.template, &bindings, &mut HashMap::new(), false);
+            }
+        }
+        Err(EvalErr::InvalidExpr(strip_syntax(form.to_vec().to_expr())))
+    }
+
+    fn is_ellipsis(&self, expr: &Expr) -> bool {
+        identifier_name(expr).is_some_and(|name| name == self.ellipsis)
+    }
+
+    // Matches the elements of a list against the patterns before the tail. Without an ellipsis the
+    // tail pattern takes what is left over once those have matched.
+    fn match_dotted(
+        &self,
+        patterns: &[Expr],
+        tail: &Expr,
+        inputs: &[Expr],
+        input_tail: &Expr,
+        bindings: &mut Bindings,
+        same_binding: &dyn Fn(&Expr, &Expr) -> bool,
+    ) -> bool {
+        if *tail == Expr::EmptyList {
+            return *input_tail == Expr::EmptyList
+                && self.match_list(patterns, inputs, bindings, same_binding);
+        }
+        if patterns.iter().skip(1).any(|p| self.is_ellipsis(p)) {
+            return self.match_list(patterns, inputs, bindings, same_binding)
+                && self.match_pattern(tail, input_tail, bindings, same_binding);
+        }
+        if inputs.len() < patterns.len() {
+            return false;
+        }
+        let (head, rest) = inputs.split_at(patterns.len());
+        self.match_each(patterns, head, bindings, same_binding)
+            && self.match_pattern(
+                tail,
+                &dotted(rest.to_vec(), input_tail.clone()),
+                bindings,
+                same_binding,
+            )
+    }
+
+    fn match_list(
+        &self,
+        patterns: &[Expr],
+        inputs: &[Expr],
+        bindings: &mut Bindings,
+        same_binding: &dyn Fn(&Expr, &Expr) -> bool,
+    ) -> bool {
+        let Some(i) = patterns
+            .iter()
+            .position(|p| self.is_ellipsis(p))
+            .filter(|i| *i > 0)
+        else {
+            return patterns.len() == inputs.len()
+                && self.match_each(patterns, inputs, bindings, same_binding);
+        };
+
+        let (before, repeated, after) = (&patterns[..i - 1], &patterns[i - 1], &patterns[i + 1..]);
+        let Some(n) = inputs.len().checked_sub(before.len() + after.len()) else {
+            return false;
+        };
+        let (head, rest) = inputs.split_at(before.len());
+        let (middle, tail) = rest.split_at(n);
+        if !self.match_each(before, head, bindings, same_binding)
+            || !self.match_each(after, tail, bindings, same_binding)
+        {
+            return false;
+        }
+
+        let mut matches = vec![];
+        for input in middle {
+            let mut inner = Bindings::new();
+            if !self.match_pattern(repeated, input, &mut inner, same_binding) {
+                return false;
+            }
+            matches.push(inner);
+        }
+
+        let mut vars = vec![];
+        self.pattern_vars(repeated, &mut vars);
+        for var in vars {
+            let seq = matches.iter_mut().filter_map(|m| m.remove(&var)).collect();
+            bindings.insert(var, Match::Many(seq));
+        }
+        true
+    }
+
+    fn match_each(
+        &self,
+        patterns: &[Expr],
+        inputs: &[Expr],
+        bindings: &mut Bindings,
+        same_binding: &dyn Fn(&Expr, &Expr) -> bool,
+    ) -> bool {
+        patterns
+            .iter()
+            .zip(inputs)
+            .all(|(pattern, input)| self.match_pattern(pattern, input, bindings, same_binding))
+    }
+
+    fn match_pattern(
+        &self,
+        pattern: &Expr,
+        input: &Expr,
+        bindings: &mut Bindings,
+        same_binding: &dyn Fn(&Expr, &Expr) -> bool,
+    ) -> bool {
+        match pattern {
+            Expr::Call(_) | Expr::Pair(_) => match input {
+                Expr::Call(_) | Expr::Pair(_) | Expr::EmptyList => {
+                    let (patterns, tail) = dotted_parts(pattern);
+                    let (inputs, input_tail) = dotted_parts(input);
+                    self.match_dotted(
+                        &patterns,
+                        &tail,
+                        &inputs,
+                        &input_tail,
+                        bindings,
+                        same_binding,
+                    )
+                }
+                _ => false,
+            },
+            Expr::Vector(patterns) => match input {
+                Expr::Vector(inputs) => {
+                    self.match_list(&patterns.items(), &inputs.items(), bindings, same_binding)
+                }
+                _ => false,
+            },
+            pattern => match IdentKey::of(pattern) {
+                Some(key) if self.literals.contains(&key) => {
+                    IdentKey::of(input).is_some() && same_binding(input, pattern)
+                }
+                Some(key) => {
+                    if identifier_name(pattern).is_some_and(|name| name != Symbol::WILDCARD) {
+                        bindings.insert(key, Match::One(input.clone()));
+                    }
+                    true
+                }
+                None => pattern == input,
+            },
+        }
+    }
+
+    fn pattern_vars(&self, pattern: &Expr, vars: &mut Vec<IdentKey>) {
+        match pattern {
+            Expr::Call(_) | Expr::Pair(_) => {
+                let (patterns, tail) = dotted_parts(pattern);
+                patterns.iter().for_each(|p| self.pattern_vars(p, vars));
+                self.pattern_vars(&tail, vars);
+            }
+            Expr::Vector(patterns) => patterns
+                .items()
+                .iter()
+                .for_each(|p| self.pattern_vars(p, vars)),
+            pattern => {
+                if let Some(key) = IdentKey::of(pattern) {
+                    let wildcard =
+                        identifier_name(pattern).is_some_and(|name| name == Symbol::WILDCARD);
+                    if !wildcard && !self.is_ellipsis(pattern) && !self.literals.contains(&key) {
+                        vars.push(key)
+                    }
+                }
+            }
+        }
+    }
+
+    // Fills in a template. Pattern variables are replaced by what they matched, and any other
+    // identifier becomes an alias that is shared by every occurrence within this one expansion.
+    // `escaped` is set inside a `(... template)` form, where ellipses are taken literally.
+    fn instantiate(
+        &self,
+        template: &Expr,
+        bindings: &Bindings,
+        renames: &mut HashMap<IdentKey, Expr>,
+        escaped: bool,
+    ) -> Result<Expr, EvalErr> {
+        match template {
+            Expr::Call(items) => {
+                if let [first, inner] = items.as_slice() {
+                    if !escaped && self.is_ellipsis(first) {
+                        return self.instantiate(inner, bindings, renames, true);
+                    }
+                }
+
+                let out = self.instantiate_items(items, bindings, renames, escaped)?;
+                Ok(dotted(out, Expr::EmptyList))
+            }
+            Expr::Pair(_) => {
+                let (items, tail) = dotted_parts(template);
+                let out = self.instantiate_items(&items, bindings, renames, escaped)?;
+                Ok(dotted(
+                    out,
+                    self.instantiate(&tail, bindings, renames, escaped)?,
+                ))
+            }
+            Expr::Vector(items) => Ok(Expr::literal_vector(self.instantiate_items(
+                &items.items(),
+                bindings,
+                renames,
+                escaped,
+            )?)),
+            template => match IdentKey::of(template) {
+                Some(key) => match bindings.get(&key) {
+                    Some(Match::One(expr)) => Ok(expr.clone()),
+                    Some(Match::Many(_)) => Err(EvalErr::InvalidArgs(
+                        "'syntax-rules' template. pattern variable used without ellipsis",
+                    )),
+                    None => Ok(renames
+                        .entry(key)
+                        .or_insert_with(|| Alias::new(template.clone(), self.scope.clone()))
+                        .clone()),
+                },
+                None => Ok(template.clone()),
+            },
+        }
+    }
+
+    fn instantiate_items(
+        &self,
+        items: &[Expr],
+        bindings: &Bindings,
+        renames: &mut HashMap<IdentKey, Expr>,
+        escaped: bool,
+    ) -> Result<Vec<Expr>, EvalErr> {
+        let mut out = vec![];
+        let mut items = items.iter().peekable();
+        while let Some(item) = items.next() {
+            let mut depth = 0;
+            while !escaped && items.next_if(|next| self.is_ellipsis(next)).is_some() {
+                depth += 1;
+            }
+            match depth {
+                0 => out.push(self.instantiate(item, bindings, renames, escaped)?),
+                _ => out.extend(self.instantiate_repeated(item, depth, bindings, renames)?),
+            }
+        }
+        Ok(out)
+    }
+
+    fn instantiate_repeated(
+        &self,
+        template: &Expr,
+        depth: usize,
+        bindings: &Bindings,
+        renames: &mut HashMap<IdentKey, Expr>,
+    ) -> Result<Vec<Expr>, EvalErr> {
+        let mut vars = vec![];
+        self.pattern_vars(template, &mut vars);
+        let seqs = vars
+            .into_iter()
+            .filter_map(|var| match bindings.get(&var) {
+                Some(Match::Many(seq)) => Some((var, seq)),
+                _ => None,
+            })
+            .collect::<Vec<(IdentKey, &Vec<Match>)>>();
+
+        let len = match seqs.first() {
+            Some((_, seq)) => seq.len(),
+            None => {
+                return Err(EvalErr::InvalidArgs(
+                    "'syntax-rules' template. no pattern variables before ellipsis",
+                ))
+            }
+        };
+        if seqs.iter().any(|(_, seq)| seq.len() != len) {
+            return Err(EvalErr::InvalidArgs(
+                "'syntax-rules' template. pattern variables matched differing lengths",
+            ));
+        }
+
+        let mut out = vec![];
+        for i in 0..len {
+            let mut inner = bindings.clone();
+            for (var, seq) in seqs.iter() {
+                inner.insert(var.clone(), seq[i].clone());
+            }
+            match depth {
+                1 => out.push(self.instantiate(template, &inner, renames, false)?),
+                _ => out.extend(self.instantiate_repeated(template, depth - 1, &inner, renames)?),
+            }
+        }
+        Ok(out)
+    }
+}
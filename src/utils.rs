@@ -3,30 +3,40 @@ use std::rc::Rc;
 use std::slice::Iter;
 use std::vec::IntoIter;
 
+use num_traits::ToPrimitive;
+
 use crate::error::EvalErr;
+use crate::heap;
 use crate::lexer::Token;
+use crate::number::Number;
 use crate::parser::Expr;
 use crate::primitives::pair::Pair;
 use crate::procedure::Proc;
 use crate::special_form::And;
 use crate::special_form::Assignment;
 use crate::special_form::Begin;
+use crate::special_form::CaseLambda;
+use crate::special_form::Declare;
 use crate::special_form::Define;
+use crate::special_form::GlobalRef;
+use crate::special_form::Guard;
 use crate::special_form::If;
 use crate::special_form::Lambda;
 use crate::special_form::Or;
 use crate::special_form::SpecialForm;
+use crate::symbol::Symbol;
 
 pub trait IterInnerVal {
-    fn into_nums(self) -> Result<Vec<f64>, EvalErr>;
-    fn into_strings(self) -> Result<Vec<String>, EvalErr>;
+    fn into_nums(self) -> Result<Vec<Number>, EvalErr>;
+    fn into_symbols(self) -> Result<Vec<Symbol>, EvalErr>;
+    fn into_chars(self) -> Result<Vec<char>, EvalErr>;
 }
 
 impl<T> IterInnerVal for T
 where
     T: IntoIterator<Item = Expr>,
 {
-    fn into_nums(self) -> Result<Vec<f64>, EvalErr> {
+    fn into_nums(self) -> Result<Vec<Number>, EvalErr> {
         self.into_iter()
             .map(|expr| match expr {
                 Expr::Atom(Token::Number(n)) => Ok(n),
@@ -35,14 +45,23 @@ where
             .collect()
     }
 
-    fn into_strings(self) -> Result<Vec<String>, EvalErr> {
+    fn into_symbols(self) -> Result<Vec<Symbol>, EvalErr> {
         self.into_iter()
             .map(|expr| match expr {
-                Expr::Atom(Token::Symbol(name)) => Ok(name.to_string()),
+                Expr::Atom(Token::Symbol(name)) => Ok(name),
                 _ => Err(EvalErr::TypeError("symbol", expr)),
             })
             .collect()
     }
+
+    fn into_chars(self) -> Result<Vec<char>, EvalErr> {
+        self.into_iter()
+            .map(|expr| match expr {
+                Expr::Atom(Token::Char(c)) => Ok(c),
+                _ => Err(EvalErr::TypeError("char", expr)),
+            })
+            .collect()
+    }
 }
 
 pub trait SoftIter<I>
@@ -68,7 +87,7 @@ where
         F: Fn(&I::Item) -> bool,
     {
         let mut new = vec![];
-        while self.peek().map_or(false, &pred) {
+        while self.peek().is_some_and(&pred) {
             new.push(self.next().unwrap())
         }
         new.into_iter()
@@ -140,19 +159,76 @@ where
     }
 }
 
+pub fn index_arg(expr: Expr) -> Result<usize, EvalErr> {
+    match expr {
+        Expr::Atom(Token::Number(Number::Integer(n))) => n
+            .to_usize()
+            .ok_or(EvalErr::InvalidArgs("index must not be negative")),
+        expr => Err(EvalErr::TypeError("exact integer", expr)),
+    }
+}
+
+// The optional `start` and `end` arguments taken by many string and vector procedures,
+// defaulting to the whole sequence.
+pub fn range_args(
+    mut args: IntoIter<Expr>,
+    len: usize,
+    err: &'static str,
+) -> Result<(usize, usize), EvalErr> {
+    let start = args.next().map(index_arg).transpose()?.unwrap_or(0);
+    let end = args.next().map(index_arg).transpose()?.unwrap_or(len);
+    match start <= end && end <= len {
+        true => Ok((start, end)),
+        false => Err(EvalErr::InvalidArgs(err)),
+    }
+}
+
+// An exact integer from 0 to 255.
+pub fn byte_arg(expr: Expr) -> Result<u8, EvalErr> {
+    let byte = match &expr {
+        Expr::Atom(Token::Number(Number::Integer(n))) => n.to_u8(),
+        _ => None,
+    };
+    byte.ok_or(EvalErr::TypeError("byte", expr))
+}
+
 pub trait ToExpr {
     fn to_expr(self) -> Expr;
 }
 
-impl ToExpr for f64 {
+impl ToExpr for Number {
     fn to_expr(self) -> Expr {
         Expr::Atom(Token::Number(self))
     }
 }
 
+impl ToExpr for i64 {
+    fn to_expr(self) -> Expr {
+        Number::from(self).to_expr()
+    }
+}
+
+impl ToExpr for f64 {
+    fn to_expr(self) -> Expr {
+        Number::from(self).to_expr()
+    }
+}
+
 impl ToExpr for &str {
     fn to_expr(self) -> Expr {
-        Expr::Atom(Token::Symbol(self.to_string()))
+        Expr::Atom(Token::Symbol(Symbol::intern(self)))
+    }
+}
+
+impl ToExpr for Symbol {
+    fn to_expr(self) -> Expr {
+        Expr::Atom(Token::Symbol(self))
+    }
+}
+
+impl ToExpr for char {
+    fn to_expr(self) -> Expr {
+        Expr::Atom(Token::Char(self))
     }
 }
 
@@ -164,7 +240,20 @@ impl ToExpr for bool {
 
 impl ToExpr for Proc {
     fn to_expr(self) -> Expr {
-        Expr::Proc(Rc::new(self))
+        let proc = Rc::new(self);
+        heap::track(&proc);
+        Expr::Proc(proc)
+    }
+}
+
+// Errors handed to Scheme become condition objects, except for objects that were raised directly,
+// which are passed along as they are.
+impl ToExpr for EvalErr {
+    fn to_expr(self) -> Expr {
+        match self {
+            EvalErr::Raised(obj) => obj,
+            err => Expr::Condition(Rc::new(err)),
+        }
     }
 }
 
@@ -176,7 +265,9 @@ impl ToExpr for Vec<Expr> {
 
 impl ToExpr for Pair {
     fn to_expr(self) -> Expr {
-        Expr::Pair(Rc::new(self))
+        let pair = Rc::new(self);
+        heap::track(&pair);
+        Expr::Pair(pair)
     }
 }
 
@@ -192,6 +283,18 @@ impl ToExpr for Lambda {
     }
 }
 
+impl ToExpr for CaseLambda {
+    fn to_expr(self) -> Expr {
+        Expr::SpecialForm(Rc::new(SpecialForm::CaseLambda(self)))
+    }
+}
+
+impl ToExpr for Declare {
+    fn to_expr(self) -> Expr {
+        Expr::SpecialForm(Rc::new(SpecialForm::Declare(self)))
+    }
+}
+
 impl ToExpr for Define {
     fn to_expr(self) -> Expr {
         Expr::SpecialForm(Rc::new(SpecialForm::Define(self)))
@@ -221,3 +324,15 @@ impl ToExpr for Begin {
         Expr::SpecialForm(Rc::new(SpecialForm::Begin(self)))
     }
 }
+
+impl ToExpr for Guard {
+    fn to_expr(self) -> Expr {
+        Expr::SpecialForm(Rc::new(SpecialForm::Guard(self)))
+    }
+}
+
+impl ToExpr for GlobalRef {
+    fn to_expr(self) -> Expr {
+        Expr::SpecialForm(Rc::new(SpecialForm::GlobalRef(self)))
+    }
+}
@@ -0,0 +1,180 @@
+use std::mem;
+use std::rc::Rc;
+
+use crate::analyzer::{analyze, Scope};
+use crate::compiler::{compile, Chunk, Op};
+use crate::continuation::{Catch, Continuation, Frame, Handler};
+use crate::enviroment::EnvRef;
+use crate::error::EvalErr;
+use crate::evaluator::{self, apply, Args, Step};
+use crate::expander::expand;
+use crate::heap;
+use crate::parser::Expr;
+use crate::procedure::{Compound, Proc};
+use crate::utils::ToExpr;
+
+// A chunk being run: the next instruction, the operands pushed so far and the enviroment of the
+// call it is running for. Calling out of the VM leaves the activation on the continuation to be
+// resumed with the value the call returns, so the VM shares call/cc, dynamic-wind and exception
+// handling with the evaluator.
+#[derive(Debug, Clone)]
+pub struct Activation {
+    chunk: Rc<Chunk>,
+    pc: usize,
+    stack: Vec<Expr>,
+    env: EnvRef,
+}
+
+impl Activation {
+    pub fn new(chunk: Rc<Chunk>, env: EnvRef) -> Self {
+        Activation {
+            chunk,
+            pc: 0,
+            stack: vec![],
+            env,
+        }
+    }
+
+    pub fn resume(mut self, val: Expr) -> Step {
+        self.stack.push(val);
+        Step::Run(self)
+    }
+
+    fn pop(&mut self) -> Expr {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    fn top(&self) -> &Expr {
+        self.stack.last().expect("operand stack underflow")
+    }
+}
+
+pub fn eval(expr: Expr, env: &EnvRef) -> Result<Expr, EvalErr> {
+    let code = analyze(&expand(expr, env)?, &mut Scope::new(env))?;
+    let chunk = Rc::new(compile(&code));
+    evaluator::run(Step::Run(Activation::new(chunk, env.clone_rc()?)))
+}
+
+// Runs instructions until the activation finishes or calls a procedure that wasn't compiled.
+// Calls to compiled closures and returns back into the VM switch activations without leaving the
+// loop.
+pub fn run(mut act: Activation, k: &mut Continuation) -> Result<Step, EvalErr> {
+    loop {
+        let op = act.chunk.code[act.pc];
+        act.pc += 1;
+        match op {
+            Op::Constant(index) => {
+                let val = act.chunk.constants[index as usize].clone();
+                act.stack.push(val);
+            }
+            Op::Get(index) => {
+                let variable = &act.chunk.variables[index as usize];
+                match act.env.get_var(variable)? {
+                    Expr::Unassigned => {
+                        return Err(EvalErr::UnassignedVar(variable.name().to_string()))
+                    }
+                    val => act.stack.push(val),
+                }
+            }
+            Op::Define(index) => {
+                let val = act.pop();
+                act.env
+                    .define_var(&act.chunk.variables[index as usize], val)?;
+                act.stack.push(Expr::Void);
+            }
+            Op::Set(index) => {
+                let val = act.pop();
+                let val = act.env.set_var(&act.chunk.variables[index as usize], val)?;
+                act.stack.push(val);
+            }
+            Op::Closure(index) => {
+                let template = &act.chunk.lambdas[index as usize];
+                let closure = Compound::compiled(template, act.env.clone_rc()?);
+                act.stack.push(Proc::Compound(closure).to_expr());
+            }
+            Op::CaseLambda(start, count) => {
+                let start = start as usize;
+                let clauses = act.chunk.lambdas[start..start + count as usize]
+                    .iter()
+                    .map(|template| Ok(Compound::compiled(template, act.env.clone_rc()?)))
+                    .collect::<Result<Vec<Compound>, EvalErr>>()?;
+                act.stack.push(Proc::CaseLambda(clauses).to_expr());
+            }
+            Op::Jump(to) => act.pc = to as usize,
+            Op::JumpIfFalse(to) => {
+                if !act.pop().is_true() {
+                    act.pc = to as usize;
+                }
+            }
+            Op::JumpIfFalseOrPop(to) => match act.top().is_true() {
+                true => drop(act.pop()),
+                false => act.pc = to as usize,
+            },
+            Op::JumpIfTrueOrPop(to) => match act.top().is_true() {
+                true => act.pc = to as usize,
+                false => drop(act.pop()),
+            },
+            Op::Pop => drop(act.pop()),
+            Op::Call(argc) | Op::TailCall(argc) => {
+                let args = act.stack.split_off(act.stack.len() - argc as usize);
+                let proc = act.pop();
+                // Primitives can't capture the continuation, so they are called in place.
+                if let Expr::Proc(primitive) = &proc {
+                    if let Proc::Primitive(primitive) = primitive.as_ref() {
+                        let val = primitive.call(Args::new(args, &act.env)?)?;
+                        act.stack.push(val);
+                        continue;
+                    }
+                }
+                let args = Args::new(args, &act.env)?;
+                if let Op::Call(_) = op {
+                    k.push(Frame::Resume(act));
+                }
+                match apply(proc, args, k)? {
+                    Step::Run(next) => {
+                        heap::collect_if_due();
+                        act = next;
+                    }
+                    step => return Ok(step),
+                }
+            }
+            Op::Return => {
+                let val = act.pop();
+                match k.pop() {
+                    Some(Frame::Resume(caller)) => {
+                        act = caller;
+                        act.stack.push(val);
+                    }
+                    Some(frame) => return frame.resume(val, k),
+                    None => return Ok(Step::Done(val)),
+                }
+            }
+            // The operands from before the guard are moved aside for the guard to escape to, with
+            // the value of the clauses in place of the value of the body. The body starts out on
+            // an empty stack, and gets them back if it finishes.
+            Op::Guard(index, end) => {
+                let template = &act.chunk.lambdas[index as usize];
+                let clauses = Compound::compiled(template, act.env.clone_rc()?);
+                let catch = Catch::new(Proc::Compound(clauses).to_expr(), act.env.clone_rc()?);
+                let escape = Activation {
+                    chunk: Rc::clone(&act.chunk),
+                    pc: end as usize,
+                    stack: mem::take(&mut act.stack),
+                    env: act.env.clone_rc()?,
+                };
+                k.push(Frame::Resume(escape));
+                let handlers = k.handlers().with(Handler::Guard(k.clone(), Rc::new(catch)));
+                k.push(Frame::Handlers(handlers));
+            }
+            Op::EndGuard => {
+                let val = act.pop();
+                k.pop();
+                let Some(Frame::Resume(escape)) = k.pop() else {
+                    unreachable!("guard ended without its escape");
+                };
+                act.stack = escape.stack;
+                act.stack.push(val);
+            }
+        }
+    }
+}
@@ -0,0 +1,13 @@
+;; factorial built from a generic higher order product
+(define (product term a next b)
+  (if (> a b)
+      1
+      (* (term a)
+         (product term (next a) next b))))
+
+(define (inc x) (+ x 1))
+
+(define (factorial x)
+  (product id 1 inc x))
+
+(factorial 10)